@@ -2,7 +2,7 @@ use crate::app::{ChatbotApp, ChatbotAppState, Icons};
 use crate::chat::Chat;
 use crate::ui::ChatbotUi;
 use crate::settings::Settings;
-use crate::app::ProfileType;
+use crate::providers::model_cache;
 use eframe;
 use std::sync::Arc;
 use eframe::egui::{FontData, FontDefinitions, FontFamily};
@@ -15,12 +15,17 @@ pub fn initialize_app(cc: &eframe::CreationContext<'_>) -> ChatbotApp {
     settings.ui_scale = cc.egui_ctx.pixels_per_point();
     cc.egui_ctx.set_pixels_per_point(settings.ui_scale);
 
-    let providers = ChatbotApp::create_providers(&settings.get_api_keys());
+    let providers = ChatbotApp::create_providers(&settings);
     let initial_provider = settings.get_first_provider_with_key(&providers);
-    let initial_model = initial_provider.models()[0].0.to_string();
+    let initial_model = initial_provider.models()[0].0.clone();
 
     let chat = Chat::new(Arc::clone(&initial_provider));
+    chat.set_default_system_prompt(&settings.default_system_prompt);
+    chat.set_compaction_settings(settings.get_compaction_settings(&providers));
+    settings.start_mcp_servers(&chat.runtime);
+    chat.set_toolbox(settings.get_toolbox());
     chat.load_most_recent_or_create_new().unwrap_or_else(|e| eprintln!("Failed to load or create chat: {}", e));
+    model_cache::refresh_models(&providers, &chat.runtime, false);
 
     ChatbotApp {
         state: ChatbotAppState::new(),
@@ -31,7 +36,6 @@ pub fn initialize_app(cc: &eframe::CreationContext<'_>) -> ChatbotApp {
         providers,
         theme: theme.clone(),
         last_scale_change: std::time::Instant::now() - std::time::Duration::from_secs(1),
-        current_profile: ProfileType::Normal,
         bot_text_color: theme.bot_text_color,
         user_text_color: theme.user_text_color,
     }
@@ -1,7 +1,6 @@
 use crate::chat::Chat;
 use crate::chat::history_manager::ChatHistory;
 use crate::app::Icons;
-use crate::app::ProfileType;
 use crate::settings::Settings;
 use crate::ui::ChatbotUi;
 use crate::providers::ProviderTrait;
@@ -14,7 +13,6 @@ use crate::chat::ui_rendering;
 pub struct ChatbotAppState {
     pub chat_history: ChatHistory,
     pub previous_model: Option<String>,
-    pub current_profile: ProfileType,
 }
 
 impl ChatbotAppState {
@@ -22,7 +20,6 @@ impl ChatbotAppState {
         Self {
             chat_history: ChatHistory::new("chat_history"),
             previous_model: None,
-            current_profile: ProfileType::Normal,
         }
     }
 
@@ -45,7 +42,7 @@ impl ChatbotAppState {
 
     pub fn render_bottom_left_section(&mut self, ui: &mut Ui, chat: &mut Chat, settings: &mut Settings, chatbot_ui: &mut ChatbotUi, providers: &[Arc<dyn ProviderTrait + Send + Sync>], theme: &Theme) {
         ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-            bottom_panel::render(ui, chat, settings, chatbot_ui, providers, theme, &mut self.current_profile);
+            bottom_panel::render(ui, chat, settings, chatbot_ui, providers, theme);
         });
     }
 }
\ No newline at end of file
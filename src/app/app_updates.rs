@@ -1,14 +1,32 @@
 use crate::app::ChatbotApp;
 use eframe::egui::{self, Key};
 use std::sync::Arc;
+use crate::providers::{find_provider_for_model, model_cache};
 use std::time::Instant;
 
 pub fn update(app: &mut ChatbotApp, ctx: &egui::Context) {
     if app.settings.api_keys_updated {
         reload_providers(app);
+        app.chat.set_default_system_prompt(&app.settings.default_system_prompt);
+        app.chat.set_compaction_settings(app.settings.get_compaction_settings(&app.providers));
+        app.settings.start_mcp_servers(&app.chat.runtime);
+        app.chat.set_toolbox(app.settings.get_toolbox());
         app.settings.api_keys_updated = false;
     }
 
+    // MCP servers start in the background; their tools join the chat once they are up.
+    if app.settings.mcp.take_changed() {
+        app.chat.set_toolbox(app.settings.get_toolbox());
+    }
+    if app.settings.mcp.is_starting() {
+        ctx.request_repaint_after(std::time::Duration::from_millis(250));
+    }
+
+    if app.settings.refresh_models_requested {
+        model_cache::refresh_models(&app.providers, &app.chat.runtime, true);
+        app.settings.refresh_models_requested = false;
+    }
+
     let current_theme = app.settings.get_current_theme().clone();
     if app.theme.name != current_theme.name {
         app.theme = current_theme.clone();
@@ -55,7 +73,7 @@ pub fn switch_provider(app: &mut ChatbotApp, model: String) {
     } else if model.starts_with("accounts/fireworks/models/") {
         (app.providers.iter().find(|p| p.name() == "Fireworks"), true)
     } else {
-        (app.providers.iter().find(|p| p.models().iter().any(|&(model_name, _)| model_name == model.as_str())), false)
+        (find_provider_for_model(&app.providers, &app.ui.selected_provider, &model), false)
     };
 
     if let Some(current_provider) = provider {
@@ -71,7 +89,7 @@ pub fn switch_provider(app: &mut ChatbotApp, model: String) {
 
         let providers_clone = app.providers.clone();
         if let Some(chatbot) = Arc::get_mut(&mut app.chat.chatbot) {
-            chatbot.switch_model(&providers_clone, model_to_use.clone());
+            chatbot.switch_model(&providers_clone, &app.ui.selected_provider, model_to_use.clone());
         }
 
         if let Ok(mut current_model) = app.chat.current_model.lock() {
@@ -85,11 +103,11 @@ pub fn switch_provider(app: &mut ChatbotApp, model: String) {
 }
 
 pub fn reload_providers(app: &mut ChatbotApp) {
-    let api_keys = app.settings.get_api_keys();
-    app.providers = ChatbotApp::create_providers(&api_keys);
+    app.providers = ChatbotApp::create_providers(&app.settings);
+    model_cache::refresh_models(&app.providers, &app.chat.runtime, false);
 
     app.ui.selected_provider = app.providers[0].name().to_string();
-    app.ui.selected_model = app.providers[0].models()[0].0.to_string();
+    app.ui.selected_model = app.providers[0].models()[0].0.clone();
     app.chat.update_provider(Arc::clone(&app.providers[0]));
 }
 
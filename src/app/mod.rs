@@ -3,6 +3,7 @@ mod app_ui;
 mod app_initialization;
 mod app_updates;
 mod icons;
+mod profile;
 
 use crate::chat::Chat;
 use crate::ui::ChatbotUi;
@@ -13,15 +14,9 @@ use std::sync::Arc;
 use std::time::Instant;
 pub use icons::Icons;
 pub use app_state::ChatbotAppState;
+pub use profile::{Profile, DEFAULT_PROFILE};
 use egui::Color32;
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum ProfileType {
-    Coder,
-    Normal,
-    Creative,
-}
-
 pub struct ChatbotApp {
     state: ChatbotAppState,
     chat: Chat,
@@ -31,7 +26,6 @@ pub struct ChatbotApp {
     providers: Vec<Arc<dyn ProviderTrait + Send + Sync>>,
     theme: crate::ui::themes::Theme,
     last_scale_change: Instant,
-    current_profile: ProfileType,
     pub bot_text_color: Color32,
     pub user_text_color: Color32,
 }
@@ -47,10 +41,12 @@ impl ChatbotApp {
         app
     }
 
-    fn create_providers(api_keys: &str) -> Vec<Arc<dyn ProviderTrait + Send + Sync>> {
-        providers::get_providers(api_keys.to_string())
+    fn create_providers(settings: &Settings) -> Vec<Arc<dyn ProviderTrait + Send + Sync>> {
+        let profile = settings.get_current_profile();
+        providers::get_providers(&settings.get_provider_configs(), &settings.endpoints, settings.get_retry_policy(), &settings.network)
             .into_iter()
             .map(|p| Arc::from(p) as Arc<dyn ProviderTrait + Send + Sync>)
+            .inspect(|p| p.update_profile(profile.clone()))
             .collect()
     }
 
@@ -0,0 +1,49 @@
+/// Sampling settings sent with every request. Coder, Creative and Normal
+/// are the defaults; users can edit them and add their own in Settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub temperature: f32,
+    pub top_p: f32,
+    pub top_k: u32,
+    pub frequency_penalty: f32,
+    pub presence_penalty: f32,
+    /// Longest reply to ask for; `None` keeps each provider's default.
+    pub max_tokens: Option<u32>,
+    pub stop_sequences: Vec<String>,
+    /// Tokens the model may spend thinking before it answers; `None` turns thinking off.
+    pub thinking_budget: Option<u32>,
+}
+
+pub const DEFAULT_PROFILE: &str = "Normal";
+
+impl Profile {
+    fn preset(name: &str, temperature: f32, top_p: f32, top_k: u32, frequency_penalty: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            temperature,
+            top_p,
+            top_k,
+            frequency_penalty,
+            presence_penalty: 0.0,
+            max_tokens: None,
+            stop_sequences: Vec::new(),
+            thinking_budget: None,
+        }
+    }
+
+    /// The profiles a fresh settings file starts with, in the order they are listed.
+    pub fn defaults() -> Vec<Profile> {
+        vec![
+            Self::preset("Coder", 0.4, 0.85, 40, 0.04),
+            Self::preset("Creative", 1.4, 0.95, 80, 0.4),
+            Self::preset(DEFAULT_PROFILE, 0.7, 0.9, 50, 0.15),
+        ]
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::preset(DEFAULT_PROFILE, 0.7, 0.9, 50, 0.15)
+    }
+}
@@ -0,0 +1,177 @@
+use crate::message::{FileAttachment, ImageAttachment};
+use image::{DynamicImage, ImageFormat};
+use std::io::Cursor;
+use std::path::Path;
+
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+// Longer sides get scaled down: Claude resizes anything above this anyway,
+// and smaller images keep requests and chat files light.
+const MAX_IMAGE_SIDE: u32 = 1568;
+// Claude's 5 MB limit applies to the base64 text, which is 4/3 of the bytes.
+const MAX_IMAGE_BYTES: usize = 3_750_000;
+
+// Far more than fits most context windows; the size check against the model happens when attaching.
+const MAX_FILE_BYTES: u64 = 2_000_000;
+// File extensions by language name, as the syntax highlighter and the models know them.
+const LANGUAGES: &[(&[&str], &str)] = &[
+    (&["rs"], "rust"),
+    (&["py", "pyi"], "python"),
+    (&["js", "mjs", "cjs", "jsx"], "javascript"),
+    (&["ts", "tsx"], "typescript"),
+    (&["c", "h"], "c"),
+    (&["cc", "cpp", "cxx", "hpp", "hh"], "cpp"),
+    (&["cs"], "csharp"),
+    (&["go"], "go"),
+    (&["java"], "java"),
+    (&["kt", "kts"], "kotlin"),
+    (&["swift"], "swift"),
+    (&["rb"], "ruby"),
+    (&["php"], "php"),
+    (&["sh", "bash", "zsh"], "bash"),
+    (&["ps1"], "powershell"),
+    (&["sql"], "sql"),
+    (&["html", "htm"], "html"),
+    (&["css"], "css"),
+    (&["scss"], "scss"),
+    (&["xml"], "xml"),
+    (&["json"], "json"),
+    (&["toml"], "toml"),
+    (&["yaml", "yml"], "yaml"),
+    (&["ini", "cfg"], "ini"),
+    (&["md", "markdown"], "markdown"),
+    (&["csv"], "csv"),
+    (&["lua"], "lua"),
+    (&["hs"], "haskell"),
+    (&["ex", "exs"], "elixir"),
+    (&["tex"], "latex"),
+];
+
+pub fn image_from_file(path: &Path) -> Result<ImageAttachment, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+    let name = path.file_name().map_or_else(|| "image".to_string(), |n| n.to_string_lossy().to_string());
+    prepare_image(name, bytes)
+}
+
+/// Takes the image currently on the clipboard, e.g. a screenshot.
+pub fn image_from_clipboard() -> Result<ImageAttachment, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| format!("Can't open the clipboard: {}", e))?;
+    let data = clipboard.get_image().map_err(|_| "The clipboard holds no image".to_string())?;
+    let image = image::RgbaImage::from_raw(data.width as u32, data.height as u32, data.bytes.into_owned())
+        .ok_or_else(|| "The clipboard image is malformed".to_string())?;
+    encode_png("pasted image.png".to_string(), &fit(DynamicImage::ImageRgba8(image)))
+}
+
+/// Reads a text or source file to attach as context.
+pub fn file_from_path(path: &Path) -> Result<FileAttachment, String> {
+    let name = path.file_name().map_or_else(|| "file".to_string(), |n| n.to_string_lossy().to_string());
+    let size = std::fs::metadata(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?.len();
+    if size > MAX_FILE_BYTES {
+        return Err(format!("{} is too large to attach ({} KB, at most {} KB)", name, size / 1024, MAX_FILE_BYTES / 1024));
+    }
+    let bytes = std::fs::read(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    text_file(name, &extension, bytes)
+}
+
+fn text_file(name: String, extension: &str, bytes: Vec<u8>) -> Result<FileAttachment, String> {
+    if bytes.contains(&0) {
+        return Err(format!("{} looks like a binary file", name));
+    }
+    let content = String::from_utf8(bytes).map_err(|_| format!("{} is not UTF-8 text", name))?;
+    let language = language_for(&name, extension).to_string();
+    Ok(FileAttachment { name, language, content })
+}
+
+fn language_for(name: &str, extension: &str) -> &'static str {
+    match name {
+        "Dockerfile" => return "dockerfile",
+        "Makefile" => return "makefile",
+        _ => {}
+    }
+    LANGUAGES.iter().find(|(extensions, _)| extensions.contains(&extension)).map_or("text", |(_, language)| *language)
+}
+
+/// Sends images the APIs accept as they are, and re-encodes the rest, or
+/// ones that are too large, as PNG.
+fn prepare_image(name: String, bytes: Vec<u8>) -> Result<ImageAttachment, String> {
+    let format = image::guess_format(&bytes).map_err(|_| format!("{} is not a supported image", name))?;
+    let media_type = match format {
+        ImageFormat::Png => Some("image/png"),
+        ImageFormat::Jpeg => Some("image/jpeg"),
+        ImageFormat::Gif => Some("image/gif"),
+        ImageFormat::WebP => Some("image/webp"),
+        _ => None,
+    };
+    let image = image::load_from_memory_with_format(&bytes, format).map_err(|e| format!("Can't decode {}: {}", name, e))?;
+    match media_type {
+        Some(media_type) if image.width().max(image.height()) <= MAX_IMAGE_SIDE && bytes.len() <= MAX_IMAGE_BYTES => {
+            Ok(ImageAttachment::new(name, media_type.to_string(), &bytes))
+        }
+        _ => {
+            println!("Debug: Re-encoding {} ({}x{}, {} bytes)", name, image.width(), image.height(), bytes.len());
+            encode_png(name, &fit(image))
+        }
+    }
+}
+
+fn fit(image: DynamicImage) -> DynamicImage {
+    if image.width().max(image.height()) > MAX_IMAGE_SIDE {
+        image.resize(MAX_IMAGE_SIDE, MAX_IMAGE_SIDE, image::imageops::FilterType::Triangle)
+    } else {
+        image
+    }
+}
+
+fn encode_png(name: String, image: &DynamicImage) -> Result<ImageAttachment, String> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).map_err(|e| format!("Can't encode {}: {}", name, e))?;
+    if png.len() > MAX_IMAGE_BYTES {
+        return Err(format!("{} is too large to send, even scaled down", name));
+    }
+    Ok(ImageAttachment::new(name, "image/png".to_string(), &png))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(width, height).write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn keeps_small_images_as_they_are() {
+        let bytes = png(40, 30);
+        let image = prepare_image("small.png".into(), bytes.clone()).unwrap();
+        assert_eq!(image.media_type, "image/png");
+        assert_eq!(image.bytes(), Some(bytes));
+    }
+
+    #[test]
+    fn scales_down_large_images() {
+        let image = prepare_image("wide.png".into(), png(3136, 100)).unwrap();
+        let decoded = image::load_from_memory(&image.bytes().unwrap()).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (MAX_IMAGE_SIDE, 50));
+    }
+
+    #[test]
+    fn names_the_language_of_text_files() {
+        let file = text_file("main.rs".into(), "rs", b"fn main() {}\n".to_vec()).unwrap();
+        assert_eq!((file.language.as_str(), file.content.as_str()), ("rust", "fn main() {}\n"));
+        assert_eq!(language_for("Makefile", ""), "makefile");
+        assert_eq!(language_for("notes.unknown", "unknown"), "text");
+    }
+
+    #[test]
+    fn rejects_binary_files() {
+        assert!(text_file("data.bin".into(), "bin", vec![0x7f, b'E', b'L', b'F', 0, 1]).is_err());
+        assert!(text_file("latin1.txt".into(), "txt", vec![b'c', b'a', b'f', 0xe9]).is_err());
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_images() {
+        assert!(prepare_image("notes.txt".into(), b"just text".to_vec()).is_err());
+    }
+}
@@ -1,23 +1,50 @@
-use crate::message::Message;
+use crate::message::{FileAttachment, ImageAttachment, Message, MessageMeta, Reasoning};
+use crate::tools::{ToolCall, ToolResult, ToolUse, Toolbox};
 use crate::chatbot::Chatbot;
-use crate::providers::ProviderTrait;
+use crate::providers::{ProviderTrait, RequestOptions, StopReason, StreamEvent, Usage};
+use crate::providers::{model_limits, pricing, usage_ledger};
+use crate::json_schema;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 use super::history_manager::ChatHistory;
 use super::chat_name_generation;
-use crate::app::ProfileType;
+use super::context_budget;
+use super::compaction::{self, CompactionSettings, Summary};
 use crate::ui::MessageView;
 
+/// What the response task reports to the UI while a reply streams in.
+pub enum ResponseUpdate {
+    Delta(String),
+    /// More of what the model thinks before answering.
+    Reasoning(String),
+    /// The request failed and will be re-sent at `until`.
+    Retrying { reason: String, until: Instant },
+    /// Older turns were summarized; the summary should be saved with the chat.
+    Summarized(Summary),
+    /// The tools called in the last reply have run; the results belong in that message.
+    ToolResults(Vec<ToolUse>),
+    /// `replaces_last` is set when a continuation was merged into the last message.
+    Complete { content: String, meta: Box<MessageMeta>, replaces_last: bool },
+}
+
+// In case a model keeps calling tools without ever answering.
+const MAX_TOOL_ROUNDS: usize = 10;
+
+// Sent after a cut-off reply to providers that can't prefill the assistant turn.
+const CONTINUE_PROMPT: &str = "Your previous reply was cut off. Continue it exactly where it stopped, without repeating anything or adding an introduction.";
+
 pub struct Chat {
     pub messages: Arc<Mutex<Vec<Message>>>,
     pub chatbot: Arc<Chatbot>,
     pub provider: Arc<dyn ProviderTrait + Send + Sync>,
     pub runtime: Runtime,
     pub is_processing: Arc<AtomicBool>,
-    pub ui_sender: mpsc::UnboundedSender<(String, bool)>,
-    pub ui_receiver: Arc<Mutex<mpsc::UnboundedReceiver<(String, bool)>>>,
+    pub ui_sender: mpsc::UnboundedSender<ResponseUpdate>,
+    pub ui_receiver: Arc<Mutex<mpsc::UnboundedReceiver<ResponseUpdate>>>,
     pub history_manager: Arc<Mutex<ChatHistory>>,
     pub needs_naming: Arc<Mutex<bool>>,
     pub name_sender: mpsc::UnboundedSender<String>,
@@ -26,9 +53,24 @@ pub struct Chat {
     pub error_sender: mpsc::UnboundedSender<String>,
     pub error_receiver: Arc<Mutex<mpsc::UnboundedReceiver<String>>>,
     pub has_updates: Arc<Mutex<bool>>,
-    pub stop_flag: Arc<AtomicBool>,
+    /// Cancels the response in flight; taken by `stop_processing`.
+    pub cancel_sender: Arc<Mutex<Option<oneshot::Sender<()>>>>,
     pub current_response: Arc<Mutex<String>>,
     pub message_view: Arc<Mutex<MessageView>>,
+    pub system_prompt: Arc<Mutex<Option<String>>>,
+    /// Copied into every new chat; set from Settings.
+    pub default_system_prompt: Arc<Mutex<String>>,
+    /// Leading messages that didn't fit the context window of the last request.
+    pub excluded_messages: Arc<Mutex<usize>>,
+    /// Stands in for the older turns of a long chat in requests.
+    pub summary: Arc<Mutex<Option<Summary>>>,
+    pub compaction: Arc<Mutex<CompactionSettings>>,
+    /// Reply length and stop sequences for this chat, over the profile's.
+    pub options: Arc<Mutex<RequestOptions>>,
+    /// Tools offered to the model; set from Settings.
+    pub toolbox: Arc<Mutex<Toolbox>>,
+    /// Senders for the approve (`true`) or deny decision on each tool call waiting for one, by call id.
+    pub tool_approvals: Arc<Mutex<HashMap<String, oneshot::Sender<bool>>>>,
 }
 
 impl Chat {
@@ -36,7 +78,7 @@ impl Chat {
         let (ui_sender, ui_receiver) = mpsc::unbounded_channel();
         let (name_sender, name_receiver) = mpsc::unbounded_channel();
         let (error_sender, error_receiver) = mpsc::unbounded_channel();
-        let initial_model = initial_provider.models()[0].0.to_string();
+        let initial_model = initial_provider.models()[0].0.clone();
         Self {
             messages: Arc::new(Mutex::new(Vec::new())),
             chatbot: Arc::new(Chatbot::new(Arc::clone(&initial_provider))),
@@ -53,9 +95,17 @@ impl Chat {
             error_sender,
             error_receiver: Arc::new(Mutex::new(error_receiver)),
             has_updates: Arc::new(Mutex::new(true)),
-            stop_flag: Arc::new(AtomicBool::new(false)),
+            cancel_sender: Arc::new(Mutex::new(None)),
             current_response: Arc::new(Mutex::new(String::new())),
             message_view: Arc::new(Mutex::new(MessageView::new())),
+            system_prompt: Arc::new(Mutex::new(None)),
+            default_system_prompt: Arc::new(Mutex::new(String::new())),
+            excluded_messages: Arc::new(Mutex::new(0)),
+            summary: Arc::new(Mutex::new(None)),
+            compaction: Arc::new(Mutex::new(CompactionSettings::default())),
+            options: Arc::new(Mutex::new(RequestOptions::default())),
+            toolbox: Arc::new(Mutex::new(Toolbox::default())),
+            tool_approvals: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -76,61 +126,226 @@ impl Chat {
         self.is_processing.load(Ordering::SeqCst)
     }
 
-    pub fn process_input(&self, input: String, model: String) {
+    pub fn process_input(&self, input: String, images: Vec<ImageAttachment>, files: Vec<FileAttachment>, model: String) {
         let input_with_newlines = input.replace("\n", "\n").trim().to_string();
-        self.add_message(input_with_newlines.clone(), true);
+        self.add_message_with_meta(input_with_newlines.clone(), true, MessageMeta { images, files, ..MessageMeta::default() });
+        self.start_response(model, false);
+    }
+
+    /// Has the model carry on with the last reply, which was cut off at the
+    /// token limit, and merges what it writes into that reply.
+    pub fn continue_response(&self, model: String) {
+        self.start_response(model, true);
+    }
+
+    fn start_response(&self, model: String, continuation: bool) {
         self.is_processing.store(true, Ordering::SeqCst);
-        self.stop_flag.store(false, Ordering::SeqCst);
+        let (cancel_sender, mut cancel_receiver) = oneshot::channel();
+        *self.cancel_sender.lock().unwrap() = Some(cancel_sender);
         let chatbot = Arc::clone(&self.chatbot);
         let is_processing = Arc::clone(&self.is_processing);
         let ui_sender = self.ui_sender.clone();
         let needs_naming = Arc::clone(&self.needs_naming);
         let current_model = Arc::clone(&self.current_model);
         let error_sender = self.error_sender.clone();
-        let stop_flag = Arc::clone(&self.stop_flag);
         let provider = Arc::clone(&self.provider);
         let messages = Arc::clone(&self.messages);
         let current_response = Arc::clone(&self.current_response);
+        let system_prompt = self.get_system_prompt();
+        let excluded_messages = Arc::clone(&self.excluded_messages);
+        let mut summary = self.get_summary();
+        let compaction = self.compaction.lock().unwrap().clone();
+        let mut options = self.get_options();
+        let toolbox = self.toolbox.lock().unwrap().clone();
+        let tool_approvals = Arc::clone(&self.tool_approvals);
     
         self.runtime.spawn(async move {
             provider.set_current_model(model.clone());
             let messages_clone = messages.lock().unwrap().clone();
-            match chatbot.stream_response(&messages_clone) {
-                Ok(mut rx) => {
-                    let mut full_response = String::new();
-                    while let Some(chunk) = rx.recv().await {
-                        if stop_flag.load(Ordering::SeqCst) {
+            let context_window = model_limits::context_window(provider.as_ref(), &model);
+
+            if compaction.enabled {
+                if let Some(cut) = compaction::compaction_point(&messages_clone, system_prompt.as_deref(), summary.as_ref(), context_window, compaction.threshold) {
+                    let summary_provider = compaction.provider.clone().unwrap_or_else(|| Arc::clone(&provider));
+                    let summary_model = compaction.model.clone().unwrap_or_else(|| model.clone());
+                    let start = summary.as_ref().map_or(0, |s| s.covers.min(cut));
+                    println!("Debug: Summarizing messages {}..{} with {}", start, cut, summary_model);
+                    let previous = summary.as_ref().map(|s| s.text.clone());
+                    match compaction::summarize(summary_provider, summary_model, previous.as_deref(), &messages_clone[start..cut]).await {
+                        Ok(text) => {
+                            let new_summary = Summary { text, covers: cut, enabled: true };
+                            let _ = ui_sender.send(ResponseUpdate::Summarized(new_summary.clone()));
+                            summary = Some(new_summary);
+                        }
+                        // The request still goes out; the context window check below drops what doesn't fit.
+                        Err(e) => println!("Debug: Summarizing older messages failed: {}", e),
+                    }
+                }
+            }
+
+            let (summarized, request_system_prompt) = match summary.as_ref().filter(|s| s.enabled) {
+                Some(summary) => (summary.covers.min(messages_clone.len()), Some(compaction::system_prompt_with_summary(system_prompt.as_deref(), summary))),
+                None => (0, system_prompt),
+            };
+            let plan = context_budget::fit_to_window(&messages_clone[summarized..], request_system_prompt.as_deref(), context_window);
+            if plan.excluded > 0 {
+                println!("Debug: Leaving {} of {} messages out to fit the {} token context window", plan.excluded, messages_clone.len() - summarized, context_window);
+            }
+            *excluded_messages.lock().unwrap() = summarized + plan.excluded;
+            // The chat may have switched to a model with a lower limit since the options were saved.
+            if let (Some(max_tokens), Some(limit)) = (options.max_tokens, model_limits::max_output_tokens(provider.as_ref(), &model)) {
+                if max_tokens > limit {
+                    println!("Debug: Lowering max tokens from {} to the {} limit of {}", max_tokens, model, limit);
+                    options.max_tokens = Some(limit);
+                }
+            }
+            let mut request_messages = plan.messages;
+            // Where the next round's messages go: after the history, without the reply being continued.
+            let mut history_len = request_messages.len();
+            // What a continuation builds on: the cut-off reply and its metadata.
+            let mut partial = None;
+            if continuation {
+                if let Some(last) = messages_clone.last().filter(|m| !m.is_user()) {
+                    history_len -= 1;
+                    if provider.supports_prefill() {
+                        // The model picks up mid-sentence from its own partial turn, which may not end in whitespace.
+                        let prefill = last.content().trim_end().to_string();
+                        request_messages.pop();
+                        request_messages.push(Message::new(prefill.clone(), false, last.model().map(str::to_string)));
+                        partial = Some((prefill, last.meta().clone()));
+                    } else {
+                        request_messages.push(Message::new(CONTINUE_PROMPT.to_string(), true, None));
+                        partial = Some((last.content().to_string(), last.meta().clone()));
+                    }
+                }
+            }
+            // A JSON mode reply is the answer itself, so it can't be a tool call.
+            let tools = if options.json_schema.is_some() { Vec::new() } else { toolbox.specs() };
+            let mut rounds = 0;
+            let mut answered = false;
+            loop {
+                let mut rx = match chatbot.stream_response(&request_messages, request_system_prompt.clone(), options.clone(), tools.clone()) {
+                    Ok(rx) => rx,
+                    Err(e) => {
+                        let error_message = format!("Error: {}", e);
+                        error_sender.send(error_message).unwrap();
+                        break;
+                    }
+                };
+                let mut full_response = String::new();
+                let mut reasoning = Reasoning::default();
+                let mut stop_reason = None;
+                let mut usage = Usage::default();
+                let mut tool_calls = Vec::new();
+                loop {
+                    let event = tokio::select! {
+                        event = rx.recv() => event,
+                        _ = &mut cancel_receiver => {
+                            stop_reason = Some(StopReason::Cancelled);
                             break;
                         }
-                        full_response.push_str(&chunk);
-                        *current_response.lock().unwrap() = full_response.clone();
-                        if ui_sender.send((chunk, false)).is_err() {
+                    };
+                    let Some(event) = event else { break };
+                    match event {
+                        StreamEvent::TextDelta(chunk) => {
+                            full_response.push_str(&chunk);
+                            *current_response.lock().unwrap() = full_response.clone();
+                            if ui_sender.send(ResponseUpdate::Delta(chunk)).is_err() {
+                                break;
+                            }
+                        }
+                        // Kept apart from the answer, so it is never sent back as part of it.
+                        StreamEvent::ReasoningDelta(chunk) => {
+                            reasoning.text.push_str(&chunk);
+                            if ui_sender.send(ResponseUpdate::Reasoning(chunk)).is_err() {
+                                break;
+                            }
+                        }
+                        StreamEvent::ReasoningSignature(signature) => reasoning.signature = Some(signature),
+                        StreamEvent::ToolCall(call) => tool_calls.push(call),
+                        StreamEvent::Usage(reported) => usage.merge(reported),
+                        StreamEvent::StopReason(reason) => stop_reason = Some(reason),
+                        StreamEvent::Error(e) => {
+                            let _ = error_sender.send(format!("Error: {}", e));
                             break;
                         }
+                        StreamEvent::Retrying { reason, delay, attempt, max_attempts } => {
+                            let reason = format!("{} (attempt {} of {})", reason, attempt, max_attempts);
+                            let _ = ui_sender.send(ResponseUpdate::Retrying { reason, until: Instant::now() + delay });
+                        }
+                        StreamEvent::Done => break,
                     }
+                }
 
-                    let _ = ui_sender.send((full_response.clone(), true));
-                    *current_response.lock().unwrap() = String::new();
-
-                    if messages_clone.len() == 1 {
-                        *needs_naming.lock().unwrap() = true;
+                // Dropping the receiver makes the provider task hang up right away.
+                drop(rx);
+                println!("Debug: Response finished - stop reason: {:?}, usage: {:?}, tool calls: {}", stop_reason, usage, tool_calls.len());
+                let cost_usd = pricing::cost_usd(provider.name(), &model, &usage);
+                if !usage.is_empty() {
+                    usage_ledger::record(provider.name(), &usage, cost_usd);
+                }
+                // Calls cut short by the token limit or by Stop are answered as cancelled in the next request.
+                let run_tools = !tool_calls.is_empty() && !matches!(stop_reason, Some(StopReason::Cancelled | StopReason::MaxTokens));
+                let meta = MessageMeta {
+                    stop_reason,
+                    usage: Some(usage).filter(|u| !u.is_empty()),
+                    cost_usd,
+                    tool_calls: tool_calls.iter().map(|call| ToolUse { call: call.clone(), result: None }).collect(),
+                    reasoning: Some(reasoning).filter(|r| !r.text.is_empty()),
+                    ..MessageMeta::default()
+                };
+                let (content, mut meta, replaces_last) = match partial.take() {
+                    Some((text, previous_meta)) if !full_response.is_empty() || !meta.tool_calls.is_empty() => {
+                        (format!("{}{}", text, full_response), previous_meta.followed_by(meta), true)
                     }
+                    // Nothing new arrived; the cut-off reply stays as it was.
+                    Some(_) => (String::new(), meta, false),
+                    None => (full_response, meta, false),
+                };
+                if let Some(schema) = options.json_schema.as_ref().filter(|_| !content.is_empty()) {
+                    meta.schema_errors = Some(json_schema::check_reply(schema, &content));
+                }
+                let _ = ui_sender.send(ResponseUpdate::Complete { content: content.clone(), meta: Box::new(meta.clone()), replaces_last });
+                *current_response.lock().unwrap() = String::new();
+                answered = true;
+                if !run_tools {
+                    break;
+                }
 
-                    *current_model.lock().unwrap() = model;
+                rounds += 1;
+                if rounds > MAX_TOOL_ROUNDS {
+                    let _ = error_sender.send(format!("Stopped after {} rounds of tool calls without an answer", MAX_TOOL_ROUNDS));
+                    break;
                 }
-                Err(e) => {
-                    let error_message = format!("Error: {}", e);
-                    error_sender.send(error_message).unwrap();
+                let Some(results) = approve_and_run(&tool_calls, &toolbox, &tool_approvals, &mut cancel_receiver).await else {
+                    // Lets the UI know the response is over; the calls stay unanswered.
+                    let meta = MessageMeta { stop_reason: Some(StopReason::Cancelled), ..MessageMeta::default() };
+                    let _ = ui_sender.send(ResponseUpdate::Complete { content: String::new(), meta: Box::new(meta), replaces_last: false });
+                    break;
+                };
+                meta.set_tool_results(&results);
+                let _ = ui_sender.send(ResponseUpdate::ToolResults(results));
+                request_messages.truncate(history_len);
+                request_messages.push(Message::new(content, false, Some(model.clone())).with_meta(meta));
+                history_len = request_messages.len();
+            }
+
+            if answered {
+                if messages_clone.len() == 1 {
+                    *needs_naming.lock().unwrap() = true;
                 }
+                *current_model.lock().unwrap() = model;
             }
             is_processing.store(false, Ordering::SeqCst);
         });
         self.set_has_updates();
     }
 
+    /// Cancels the response in flight. Text received so far is kept and saved.
     pub fn stop_processing(&self) {
-        self.stop_flag.store(true, Ordering::SeqCst);
-        self.is_processing.store(false, Ordering::SeqCst);
+        if let Some(cancel_sender) = self.cancel_sender.lock().unwrap().take() {
+            let _ = cancel_sender.send(());
+        }
     }
 
     pub fn generate_chat_name(&self) {
@@ -143,8 +358,77 @@ impl Chat {
         *needs_naming.lock().unwrap() = false;
     }
 
-    pub fn update_profile(&self, profile: ProfileType) {
-        self.provider.update_profile(profile);
+}
+
+/// Waits for the user to approve or deny each call, running approved ones
+/// right away. Returns `None` if the response is stopped first.
+async fn approve_and_run(
+    calls: &[ToolCall],
+    toolbox: &Toolbox,
+    approvals: &Mutex<HashMap<String, oneshot::Sender<bool>>>,
+    cancel_receiver: &mut oneshot::Receiver<()>,
+) -> Option<Vec<ToolUse>> {
+    let decisions: Vec<_> = calls.iter().map(|call| {
+        let (sender, receiver) = oneshot::channel();
+        approvals.lock().unwrap().insert(call.id.clone(), sender);
+        receiver
+    }).collect();
+
+    let mut results = Vec::new();
+    for (call, decision) in calls.iter().zip(decisions) {
+        let approved = tokio::select! {
+            decision = decision => decision.unwrap_or(false),
+            _ = &mut *cancel_receiver => {
+                let mut approvals = approvals.lock().unwrap();
+                for call in calls {
+                    approvals.remove(&call.id);
+                }
+                return None;
+            }
+        };
+        let result = if approved { toolbox.run(call).await } else { ToolResult::denied() };
+        results.push(ToolUse { call: call.clone(), result: Some(result) });
     }
+    Some(results)
+}
 
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(id: &str, expression: &str) -> ToolCall {
+        ToolCall { id: id.into(), name: "calculator".into(), input: json!({ "expression": expression }) }
+    }
+
+    #[tokio::test]
+    async fn runs_approved_calls_and_denies_the_rest() {
+        let approvals: Arc<Mutex<HashMap<String, oneshot::Sender<bool>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let (_cancel_sender, mut cancel_receiver) = oneshot::channel();
+        let calls = vec![call("a", "1 + 1"), call("b", "2 + 2")];
+
+        let deciding = Arc::clone(&approvals);
+        tokio::spawn(async move {
+            while deciding.lock().unwrap().len() < 2 {
+                tokio::task::yield_now().await;
+            }
+            let mut approvals = deciding.lock().unwrap();
+            approvals.remove("b").unwrap().send(false).unwrap();
+            approvals.remove("a").unwrap().send(true).unwrap();
+        });
+
+        let results = approve_and_run(&calls, &Toolbox::builtin(None), &approvals, &mut cancel_receiver).await.unwrap();
+        assert_eq!(results[0].result, Some(ToolResult { content: "2".into(), is_error: false }));
+        assert_eq!(results[1].result, Some(ToolResult::denied()));
+    }
+
+    #[tokio::test]
+    async fn stopping_drops_the_pending_approvals() {
+        let approvals = Mutex::new(HashMap::new());
+        let (cancel_sender, mut cancel_receiver) = oneshot::channel();
+        cancel_sender.send(()).unwrap();
+
+        assert!(approve_and_run(&[call("a", "1")], &Toolbox::builtin(None), &approvals, &mut cancel_receiver).await.is_none());
+        assert!(approvals.lock().unwrap().is_empty());
+    }
+}
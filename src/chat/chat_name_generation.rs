@@ -1,5 +1,6 @@
 use crate::message::Message;
 use crate::chatbot::Chatbot;
+use crate::providers::StreamEvent;
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 
@@ -14,8 +15,16 @@ pub fn generate_chat_name(
         match chatbot.generate_chat_name(&current_messages) {
             Ok(mut rx) => {
                 let mut full_name = String::new();
-                while let Some(chunk) = rx.recv().await {
-                    full_name.push_str(&chunk);
+                while let Some(event) = rx.recv().await {
+                    match event {
+                        StreamEvent::TextDelta(chunk) => full_name.push_str(&chunk),
+                        StreamEvent::Error(e) => {
+                            eprintln!("Error: Failed to generate chat name: {}", e);
+                            return;
+                        }
+                        StreamEvent::Done => break,
+                        _ => {}
+                    }
                 }
                 if name_sender.send(full_name).is_err() {
                     eprintln!("Error: Failed to send generated chat name");
@@ -1,9 +1,12 @@
-use super::chat_core::Chat;
-use super::file_operations;
-use crate::message::Message;
+use super::chat_core::{Chat, ResponseUpdate};
+use super::compaction::{CompactionSettings, Summary};
+use super::file_operations::{self, ChatHeader};
+use crate::message::{Message, MessageMeta};
+use crate::providers::{RequestOptions, Usage};
+use crate::tools::{ToolUse, Toolbox};
 
 impl Chat {
-    pub fn check_ui_updates(&self) -> Option<(String, bool)> {
+    pub fn check_ui_updates(&self) -> Option<ResponseUpdate> {
         self.ui_receiver.lock().unwrap().try_recv().ok()
     }
 
@@ -20,6 +23,7 @@ impl Chat {
         *self.needs_naming.lock().unwrap() = true;
         let new_file = self.history_manager.lock().unwrap().create_new_chat()?;
         self.load_chat(&new_file)?;
+        self.apply_default_system_prompt()?;
         self.set_has_updates();
         self.clear_syntax_cache();
         Ok(())
@@ -27,7 +31,11 @@ impl Chat {
 
     pub fn load_chat(&self, file_name: &str) -> Result<(), std::io::Error> {
         *self.needs_naming.lock().unwrap() = false;
-        self.history_manager.lock().unwrap().load_chat(file_name, &mut self.messages.lock().unwrap())?;
+        let header = self.history_manager.lock().unwrap().load_chat(file_name, &mut self.messages.lock().unwrap())?;
+        *self.system_prompt.lock().unwrap() = header.system_prompt;
+        *self.summary.lock().unwrap() = header.summary;
+        *self.options.lock().unwrap() = header.options;
+        *self.excluded_messages.lock().unwrap() = 0;
         self.set_has_updates();
         self.clear_syntax_cache();
         Ok(())
@@ -42,6 +50,7 @@ impl Chat {
         if let Some(new_file) = history_manager.delete_chat(file_name)? {
             drop(history_manager);
             self.load_chat(&new_file)?;
+            self.apply_default_system_prompt()?;
         } else if history_manager.get_current_file().is_none() {
             if let Some(first_file) = history_manager.get_history_files().first() {
                 drop(history_manager);
@@ -54,7 +63,89 @@ impl Chat {
     }
 
     pub fn export_chat(&self, path: &std::path::Path) -> Result<(), std::io::Error> {
-        file_operations::export_chat(path, &self.messages.lock().unwrap())
+        file_operations::export_chat(path, &self.header(), &self.messages.lock().unwrap())
+    }
+
+    fn header(&self) -> ChatHeader {
+        ChatHeader { system_prompt: self.get_system_prompt(), summary: self.get_summary(), options: self.get_options() }
+    }
+
+    pub fn get_system_prompt(&self) -> Option<String> {
+        self.system_prompt.lock().unwrap().clone()
+    }
+
+    /// Sets the system prompt of the current chat and saves it with the chat.
+    pub fn set_system_prompt(&self, system_prompt: &str) -> Result<(), std::io::Error> {
+        let system_prompt = Some(system_prompt.trim().to_string()).filter(|s| !s.is_empty());
+        *self.system_prompt.lock().unwrap() = system_prompt;
+        self.history_manager.lock().unwrap().rewrite_current_chat(&self.header(), &self.messages.lock().unwrap())?;
+        self.set_has_updates();
+        Ok(())
+    }
+
+    pub fn set_default_system_prompt(&self, system_prompt: &str) {
+        *self.default_system_prompt.lock().unwrap() = system_prompt.to_string();
+    }
+
+    fn apply_default_system_prompt(&self) -> Result<(), std::io::Error> {
+        let default = self.default_system_prompt.lock().unwrap().clone();
+        if default.trim().is_empty() {
+            return Ok(());
+        }
+        self.set_system_prompt(&default)
+    }
+
+    pub fn get_summary(&self) -> Option<Summary> {
+        self.summary.lock().unwrap().clone()
+    }
+
+    /// Replaces (or with `None` deletes) the summary of the current chat and saves it with the chat.
+    pub fn set_summary(&self, summary: Option<Summary>) -> Result<(), std::io::Error> {
+        *self.summary.lock().unwrap() = summary;
+        self.history_manager.lock().unwrap().rewrite_current_chat(&self.header(), &self.messages.lock().unwrap())?;
+        self.set_has_updates();
+        Ok(())
+    }
+
+    pub fn get_options(&self) -> RequestOptions {
+        self.options.lock().unwrap().clone()
+    }
+
+    /// Sets the reply length and stop sequences of the current chat and saves them with the chat.
+    pub fn set_options(&self, options: RequestOptions) -> Result<(), std::io::Error> {
+        *self.options.lock().unwrap() = options;
+        self.history_manager.lock().unwrap().rewrite_current_chat(&self.header(), &self.messages.lock().unwrap())?;
+        self.set_has_updates();
+        Ok(())
+    }
+
+    pub fn set_compaction_settings(&self, settings: CompactionSettings) {
+        *self.compaction.lock().unwrap() = settings;
+    }
+
+    pub fn set_toolbox(&self, toolbox: Toolbox) {
+        *self.toolbox.lock().unwrap() = toolbox;
+    }
+
+    /// Whether tool call `id` is waiting for the user's decision.
+    pub fn awaits_approval(&self, id: &str) -> bool {
+        self.tool_approvals.lock().unwrap().contains_key(id)
+    }
+
+    /// Lets the response task run (`approved`) or skip tool call `id`.
+    pub fn decide_tool_call(&self, id: &str, approved: bool) {
+        if let Some(sender) = self.tool_approvals.lock().unwrap().remove(id) {
+            println!("Debug: Tool call {} {}", id, if approved { "approved" } else { "denied" });
+            let _ = sender.send(approved);
+        }
+    }
+
+    /// Fills in the results of the last message's tool calls and saves the chat.
+    pub fn set_tool_results(&self, results: &[ToolUse]) {
+        let Some(last) = self.messages.lock().unwrap().last().cloned() else { return };
+        let mut meta = last.meta().clone();
+        meta.set_tool_results(results);
+        self.replace_last_message(last.content().to_string(), meta);
     }
 
     pub fn rename_current_chat(&self, new_name: &str) -> Result<(), std::io::Error> {
@@ -64,6 +155,23 @@ impl Chat {
         Ok(())
     }
 
+    /// Tokens and cost of every response in this chat. The cost leaves out
+    /// responses from models without a known price.
+    pub fn usage_totals(&self) -> (Usage, f64) {
+        let messages = self.messages.lock().unwrap();
+        let mut input_tokens = 0;
+        let mut output_tokens = 0;
+        let mut cost_usd = 0.0;
+        for meta in messages.iter().map(Message::meta) {
+            if let Some(usage) = &meta.usage {
+                input_tokens += usage.input_tokens.unwrap_or(0);
+                output_tokens += usage.output_tokens.unwrap_or(0);
+            }
+            cost_usd += meta.cost_usd.unwrap_or(0.0);
+        }
+        (Usage { input_tokens: Some(input_tokens), output_tokens: Some(output_tokens) }, cost_usd)
+    }
+
     pub fn get_current_model(&self) -> String {
         self.current_model.lock().unwrap().clone()
     }
@@ -96,13 +204,27 @@ impl Chat {
         *self.has_updates.lock().unwrap() = true;
     }
 
-    pub fn add_message(&self, content: String, is_user: bool) {
+    /// Swaps the last message's text and metadata, e.g. after a continuation, and saves the chat.
+    pub fn replace_last_message(&self, content: String, meta: MessageMeta) {
+        let mut messages = self.messages.lock().unwrap();
+        if let Some(last) = messages.pop() {
+            messages.push(Message::new(content, last.is_user(), last.model().map(str::to_string)).with_meta(meta));
+        }
+        if let Err(e) = self.history_manager.lock().unwrap().rewrite_current_chat(&self.header(), &messages) {
+            eprintln!("Failed to rewrite chat history: {}", e);
+        }
+        drop(messages);
+        self.set_has_updates();
+        self.clear_syntax_cache();
+    }
+
+    pub fn add_message_with_meta(&self, content: String, is_user: bool, meta: MessageMeta) {
         let model = if is_user { None } else { Some(self.get_current_model()) };
-        let message = Message::new(content.clone(), is_user, model.clone());
-        self.messages.lock().unwrap().push(message);
-        if let Err(e) = self.history_manager.lock().unwrap().append_message(&content, is_user, model.as_deref()) {
+        let message = Message::new(content, is_user, model).with_meta(meta);
+        if let Err(e) = self.history_manager.lock().unwrap().append_message(&message) {
             eprintln!("Failed to append message to history: {}", e);
         }
+        self.messages.lock().unwrap().push(message);
 
         if !is_user && *self.needs_naming.lock().unwrap() {
             self.generate_chat_name();
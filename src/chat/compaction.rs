@@ -0,0 +1,195 @@
+use crate::message::Message;
+use crate::providers::{pricing, usage_ledger};
+use crate::providers::{ChatRequest, ProviderError, ProviderTrait, StreamEvent, Usage};
+use serde_json::json;
+use std::sync::Arc;
+use super::context_budget::{estimate_tokens, message_tokens};
+
+pub const DEFAULT_THRESHOLD: f32 = 0.75;
+// The newest messages are always sent verbatim, however long they are.
+const MIN_RECENT_MESSAGES: usize = 2;
+const SUMMARY_INSTRUCTIONS: &str = "You condense conversations so they can go on with less context. \
+    Summarize the conversation you are given. Keep every decision, requirement, fact, name, number and \
+    open question that later messages may rely on, and code only where it is essential. Reply with the \
+    summary alone, in the language of the conversation.";
+
+/// A summary that stands in for the first `covers` messages of a chat.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub text: String,
+    pub covers: usize,
+    /// Disabled summaries are kept in the chat file but not sent.
+    pub enabled: bool,
+}
+
+/// When and with which model chats get compacted; set from Settings.
+#[derive(Clone)]
+pub struct CompactionSettings {
+    pub enabled: bool,
+    /// Fraction of the context window a request may fill before older turns get summarized.
+    pub threshold: f32,
+    /// Writes the summaries; `None` uses the chat's own provider.
+    pub provider: Option<Arc<dyn ProviderTrait + Send + Sync>>,
+    /// `None` uses the chat's own model.
+    pub model: Option<String>,
+}
+
+impl Default for CompactionSettings {
+    fn default() -> Self {
+        Self { enabled: false, threshold: DEFAULT_THRESHOLD, provider: None, model: None }
+    }
+}
+
+/// Index of the first message to keep verbatim once the messages before it
+/// are summarized, or `None` while the chat stays under the threshold. A
+/// disabled summary turns compaction off for the chat until it is deleted.
+pub fn compaction_point(
+    messages: &[Message],
+    system_prompt: Option<&str>,
+    summary: Option<&Summary>,
+    context_window: usize,
+    threshold: f32,
+) -> Option<usize> {
+    if summary.is_some_and(|s| !s.enabled) {
+        return None;
+    }
+    let start = summary.map_or(0, |s| s.covers.min(messages.len()));
+    let fixed = system_prompt.map_or(0, estimate_tokens) + summary.map_or(0, |s| estimate_tokens(&s.text));
+    let used: usize = fixed + messages[start..].iter().map(message_tokens).sum::<usize>();
+    if used as f32 <= context_window as f32 * threshold {
+        return None;
+    }
+
+    // Keep up to a quarter of the window of recent turns word for word.
+    let keep_budget = context_window / 4;
+    let mut kept = 0;
+    let mut cut = messages.len();
+    while cut > start {
+        let tokens = message_tokens(&messages[cut - 1]);
+        if messages.len() - cut >= MIN_RECENT_MESSAGES && kept + tokens > keep_budget {
+            break;
+        }
+        kept += tokens;
+        cut -= 1;
+    }
+    // The verbatim part has to open with a user turn, like any request.
+    while cut < messages.len() && !messages[cut].is_user() {
+        cut += 1;
+    }
+    (cut > start && cut < messages.len()).then_some(cut)
+}
+
+/// Asks `model` for a summary of `messages`, building on the previous summary if there is one.
+pub async fn summarize(
+    provider: Arc<dyn ProviderTrait + Send + Sync>,
+    model: String,
+    previous: Option<&str>,
+    messages: &[Message],
+) -> Result<String, ProviderError> {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str(&format!("Summary of the conversation before this point:\n{}\n\n", previous));
+    }
+    for message in messages {
+        transcript.push_str(&format!("{}: {}\n\n", if message.is_user() { "User" } else { "Assistant" }, message.content_with_files()));
+    }
+
+    let request = ChatRequest::new(Some(SUMMARY_INSTRUCTIONS.to_string()), vec![json!({ "role": "user", "content": transcript })])
+        .with_model(model.clone());
+    let mut rx = provider.stream_response(request)?;
+    let mut text = String::new();
+    let mut usage = Usage::default();
+    while let Some(event) = rx.recv().await {
+        match event {
+            StreamEvent::TextDelta(chunk) => text.push_str(&chunk),
+            StreamEvent::Usage(reported) => usage.merge(reported),
+            StreamEvent::Error(e) => return Err(e),
+            StreamEvent::Done => break,
+            _ => {}
+        }
+    }
+
+    if !usage.is_empty() {
+        usage_ledger::record(provider.name(), &usage, pricing::cost_usd(provider.name(), &model, &usage));
+    }
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(ProviderError::ResponseError("The summary came back empty".to_string()));
+    }
+    Ok(text.to_string())
+}
+
+/// The system prompt for requests that send `summary` in place of the turns it covers.
+pub fn system_prompt_with_summary(system_prompt: Option<&str>, summary: &Summary) -> String {
+    let section = format!("Summary of the earlier conversation:\n{}", summary.text);
+    match system_prompt {
+        Some(system_prompt) => format!("{}\n\n{}", system_prompt, section),
+        None => section,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::mock::{Mock, MockStep};
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::new("Use Postgres 16.".to_string(), true, None),
+            Message::new("Noted.".to_string(), false, Some("mock-model".to_string())),
+        ]
+    }
+
+    // Alternating turns, user first, of 103 tokens each.
+    fn long_chat(turns: usize) -> Vec<Message> {
+        (0..turns).map(|i| Message::new("x".repeat(396), i % 2 == 0, None)).collect()
+    }
+
+    #[test]
+    fn leaves_chats_under_the_threshold_alone() {
+        assert_eq!(compaction_point(&long_chat(4), None, None, 1000, 0.75), None);
+        assert_eq!(compaction_point(&long_chat(4), Some(&"s".repeat(1600)), None, 1000, 0.75), Some(2));
+    }
+
+    #[test]
+    fn keeps_the_recent_turns_verbatim() {
+        let messages = long_chat(10);
+        let cut = compaction_point(&messages, None, None, 1000, 0.75).unwrap();
+        assert_eq!(cut, 8);
+        assert!(messages[cut].is_user());
+    }
+
+    #[test]
+    fn does_not_compact_again_what_a_summary_covers() {
+        let messages = long_chat(10);
+        let summary = Summary { text: "The user is building a web app.".to_string(), covers: 6, enabled: true };
+        assert_eq!(compaction_point(&messages, None, Some(&summary), 1000, 0.75), None);
+
+        let disabled = Summary { covers: 0, enabled: false, ..summary };
+        assert_eq!(compaction_point(&messages, None, Some(&disabled), 1000, 0.75), None);
+    }
+
+    #[tokio::test]
+    async fn summarizes_with_the_previous_summary_and_transcript() {
+        let mock = Arc::new(Mock::new("Mock").reply(vec![MockStep::Text(" The user wants ".into()), MockStep::Text("Postgres 16. ".into())]));
+
+        let summary = summarize(mock.clone(), "mock-model".to_string(), Some("Earlier: a web app."), &messages()).await.unwrap();
+        assert_eq!(summary, "The user wants Postgres 16.");
+
+        let request = &mock.requests()[0];
+        assert_eq!(request.model.as_deref(), Some("mock-model"));
+        let transcript = request.messages[0]["content"].as_str().unwrap();
+        assert!(transcript.starts_with("Summary of the conversation before this point:\nEarlier: a web app."));
+        assert!(transcript.contains("User: Use Postgres 16.\n\nAssistant: Noted."));
+    }
+
+    #[tokio::test]
+    async fn fails_on_an_error_or_an_empty_summary() {
+        let mock = Arc::new(Mock::new("Mock")
+            .reply(vec![MockStep::Error(ProviderError::StreamError("overloaded".into()))])
+            .reply(vec![MockStep::Text("  ".into())]));
+
+        assert!(matches!(summarize(mock.clone(), "mock-model".to_string(), None, &messages()).await, Err(ProviderError::StreamError(_))));
+        assert!(matches!(summarize(mock, "mock-model".to_string(), None, &messages()).await, Err(ProviderError::ResponseError(_))));
+    }
+}
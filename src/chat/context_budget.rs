@@ -0,0 +1,145 @@
+use crate::message::{Message, MessageMeta};
+
+// Rough average for English text and code. Real tokenizers differ per
+// provider; this only has to be close enough to stay under the limit.
+const CHARS_PER_TOKEN: usize = 4;
+// Role markers and separators the APIs add around every message.
+const TOKENS_PER_MESSAGE: usize = 4;
+// Claude bills about 1,600 tokens for an image at the size attachments are
+// scaled to; OpenAI's high-detail tiles come out similar.
+const TOKENS_PER_IMAGE: usize = 1600;
+const TRIM_MARKER: &str = "[... earlier part of this message left out ...]\n";
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN) + TOKENS_PER_MESSAGE
+}
+
+pub fn message_tokens(message: &Message) -> usize {
+    let tool_tokens: usize = message.meta().tool_calls.iter()
+        .map(|tool_use| estimate_tokens(&tool_use.call.input.to_string()) + tool_use.result.as_ref().map_or(0, |result| estimate_tokens(&result.content)))
+        .sum();
+    estimate_tokens(&message.content_with_files()) + message.images().len() * TOKENS_PER_IMAGE + tool_tokens
+}
+
+/// The part of a chat that fits the model's context window.
+pub struct ContextPlan {
+    /// Number of leading messages left out of the request.
+    pub excluded: usize,
+    pub messages: Vec<Message>,
+}
+
+fn reply_reserve(context_window: usize) -> usize {
+    (context_window / 4).min(4096)
+}
+
+/// How many tokens of a request can go to the prompt, after the room kept for the reply.
+pub fn prompt_budget(context_window: usize) -> usize {
+    context_window.saturating_sub(reply_reserve(context_window))
+}
+
+/// Keeps the newest messages that fit in `context_window`, leaving room for
+/// the system prompt and the reply. Older turns are dropped whole; only a
+/// single message that is too long on its own gets trimmed, from the front.
+pub fn fit_to_window(messages: &[Message], system_prompt: Option<&str>, context_window: usize) -> ContextPlan {
+    let reply_reserve = reply_reserve(context_window);
+    let system_tokens = system_prompt.map_or(0, estimate_tokens);
+    let budget = context_window.saturating_sub(reply_reserve + system_tokens);
+
+    let mut used = 0;
+    let mut start = messages.len();
+    while start > 0 {
+        let tokens = message_tokens(&messages[start - 1]);
+        if used + tokens > budget {
+            break;
+        }
+        used += tokens;
+        start -= 1;
+    }
+
+    // Most APIs require the conversation to open with a user turn.
+    while start < messages.len() && !messages[start].is_user() {
+        start += 1;
+    }
+
+    if start < messages.len() {
+        return ContextPlan { excluded: start, messages: messages[start..].to_vec() };
+    }
+
+    // Not even the newest message fits: send its tail.
+    match messages.last() {
+        Some(last) => {
+            let image_tokens = last.images().len() * TOKENS_PER_IMAGE;
+            let keep_chars = budget.saturating_sub(TOKENS_PER_MESSAGE + estimate_tokens(TRIM_MARKER) + image_tokens) * CHARS_PER_TOKEN;
+            // Attached files are inlined so that they get trimmed along with the text.
+            let content = last.content_with_files();
+            let skip = content.chars().count().saturating_sub(keep_chars);
+            let tail: String = content.chars().skip(skip).collect();
+            println!("Debug: Newest message exceeds the context window, sending its last {} characters", keep_chars);
+            let meta = MessageMeta { files: Vec::new(), ..last.meta().clone() };
+            let trimmed = Message::new(format!("{}{}", TRIM_MARKER, tail), last.is_user(), last.model().map(str::to_string)).with_meta(meta);
+            ContextPlan { excluded: messages.len() - 1, messages: vec![trimmed] }
+        }
+        None => ContextPlan { excluded: 0, messages: Vec::new() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::FileAttachment;
+
+    // 796 characters come to 203 tokens, so three of them fit the 750 token
+    // prompt budget of a 1000 token window.
+    fn turn(is_user: bool) -> Message {
+        Message::new("x".repeat(796), is_user, None)
+    }
+
+    #[test]
+    fn counts_the_system_prompt_against_the_budget() {
+        let messages = vec![turn(true), turn(false), turn(true)];
+        assert_eq!(fit_to_window(&messages, None, 1000).excluded, 0);
+
+        let plan = fit_to_window(&messages, Some(&"s".repeat(800)), 1000);
+        assert_eq!(plan.excluded, 2);
+        assert_eq!(plan.messages.len(), 1);
+    }
+
+    #[test]
+    fn drops_older_turns_whole() {
+        let messages = vec![turn(true), turn(false), turn(true), turn(false), turn(true)];
+        let plan = fit_to_window(&messages, None, 1000);
+        assert_eq!(plan.excluded, 2);
+        assert!(plan.messages.iter().all(|message| message.content() == messages[0].content()));
+    }
+
+    #[test]
+    fn starts_on_a_user_turn() {
+        let messages = vec![turn(true), turn(false), turn(true), turn(false)];
+        let plan = fit_to_window(&messages, None, 1000);
+        assert_eq!(plan.excluded, 2);
+        assert!(plan.messages[0].is_user());
+    }
+
+    #[test]
+    fn trims_an_oversized_newest_message_from_the_front() {
+        let file = FileAttachment { name: "big.rs".to_string(), language: "rust".to_string(), content: "y".repeat(10_000) };
+        let big = Message::new("question".to_string(), true, None)
+            .with_meta(MessageMeta { files: vec![file], ..MessageMeta::default() });
+        let messages = vec![turn(true), big];
+
+        let plan = fit_to_window(&messages, None, 1000);
+        assert_eq!(plan.excluded, 1);
+        let trimmed = &plan.messages[0];
+        assert!(trimmed.content().starts_with(TRIM_MARKER));
+        assert!(trimmed.content().ends_with("</file>\n\nquestion"));
+        assert!(trimmed.files().is_empty());
+        assert!(message_tokens(trimmed) <= prompt_budget(1000));
+    }
+
+    #[test]
+    fn keeps_a_quarter_of_small_windows_for_the_reply() {
+        assert_eq!(prompt_budget(1000), 750);
+        assert_eq!(prompt_budget(100_000), 95_904);
+        assert_eq!(prompt_budget(0), 0);
+    }
+}
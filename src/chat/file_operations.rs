@@ -1,10 +1,41 @@
-use crate::message::Message;
+use crate::message::{Message, MessageMeta};
+use super::compaction::Summary;
+use crate::providers::RequestOptions;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::fs::{self, File, OpenOptions};
 use std::io::{Write, Read};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 const MESSAGE_SEPARATOR: &str = "\n<<<MESSAGE_SEPARATOR>>>\n";
+const SYSTEM_PREFIX: &str = "System: ";
+const SUMMARY_PREFIX: &str = "Summary: ";
+const OPTIONS_PREFIX: &str = "Options: ";
+// Metadata (usage, stop reason, ...) goes on the last line of a message entry.
+const META_START: &str = "\n<<<META ";
+const META_END: &str = ">>>";
+
+/// Per-chat entries stored ahead of the messages in a chat file.
+#[derive(Clone, Default)]
+pub struct ChatHeader {
+    pub system_prompt: Option<String>,
+    pub summary: Option<Summary>,
+    pub options: RequestOptions,
+}
+
+/// What the metadata line of a summary entry holds.
+#[derive(Serialize, Deserialize)]
+struct SummaryMeta {
+    covers: usize,
+    enabled: bool,
+}
+
+impl Default for SummaryMeta {
+    fn default() -> Self {
+        Self { covers: 0, enabled: true }
+    }
+}
 
 pub fn create_new_chat_file(directory: &str) -> Result<String, std::io::Error> {
     fs::create_dir_all(directory)?;
@@ -17,9 +48,8 @@ pub fn create_new_chat_file(directory: &str) -> Result<String, std::io::Error> {
     Ok(file_name)
 }
 
-pub fn append_message_to_file(file_path: PathBuf, content: &str, is_user: bool, model: Option<&str>) -> Result<(), std::io::Error> {
-    let mut file = OpenOptions::new().append(true).open(file_path)?;
-    let prefix = if is_user { 
+fn message_prefix(is_user: bool, model: Option<&str>) -> String {
+    if is_user { 
         "User: ".to_string() 
     } else { 
         let model_name = match model {
@@ -30,26 +60,91 @@ pub fn append_message_to_file(file_path: PathBuf, content: &str, is_user: bool,
             None => "Bot"
         };
         format!("{}: ", model_name)
-    };
-    writeln!(file, "{}{}{}", prefix, content, MESSAGE_SEPARATOR)?;
+    }
+}
+
+fn format_message(message: &Message) -> String {
+    let mut entry = format!("{}{}", message_prefix(message.is_user(), message.model()), message.content());
+    if !message.meta().is_empty() {
+        if let Ok(meta) = serde_json::to_string(message.meta()) {
+            entry.push_str(&format!("{}{}{}", META_START, meta, META_END));
+        }
+    }
+    entry
+}
+
+/// Splits the metadata line off an entry. Entries written before metadata
+/// existed, or with metadata we can't parse, come back unchanged.
+fn split_meta<T: DeserializeOwned + Default>(entry: &str) -> (&str, T) {
+    if let Some((body, meta)) = entry.rsplit_once(META_START) {
+        if let Some(meta) = meta.strip_suffix(META_END).and_then(|json| serde_json::from_str(json).ok()) {
+            return (body.trim_end(), meta);
+        }
+    }
+    (entry, T::default())
+}
+
+fn write_entries(file: &mut File, header: &ChatHeader, messages: &[Message]) -> Result<(), std::io::Error> {
+    if let Some(system_prompt) = &header.system_prompt {
+        writeln!(file, "{}{}{}", SYSTEM_PREFIX, system_prompt, MESSAGE_SEPARATOR)?;
+    }
+    if !header.options.is_empty() {
+        let options = serde_json::to_string(&header.options).map_err(std::io::Error::other)?;
+        writeln!(file, "{}{}{}", OPTIONS_PREFIX, options, MESSAGE_SEPARATOR)?;
+    }
+    if let Some(summary) = &header.summary {
+        let meta = serde_json::to_string(&SummaryMeta { covers: summary.covers, enabled: summary.enabled })
+            .map_err(std::io::Error::other)?;
+        writeln!(file, "{}{}{}{}{}{}", SUMMARY_PREFIX, summary.text, META_START, meta, META_END, MESSAGE_SEPARATOR)?;
+    }
+    for message in messages {
+        writeln!(file, "{}{}", format_message(message), MESSAGE_SEPARATOR)?;
+    }
     Ok(())
 }
 
-pub fn load_messages_from_file(file_path: PathBuf, messages: &mut Vec<Message>) -> Result<(), std::io::Error> {
+pub fn append_message_to_file(file_path: PathBuf, message: &Message) -> Result<(), std::io::Error> {
+    let mut file = OpenOptions::new().append(true).open(file_path)?;
+    writeln!(file, "{}{}", format_message(message), MESSAGE_SEPARATOR)?;
+    Ok(())
+}
+
+/// Replaces the whole chat file, for changes that can't be appended such as editing the system prompt or summary.
+pub fn write_chat_file(file_path: PathBuf, header: &ChatHeader, messages: &[Message]) -> Result<(), std::io::Error> {
+    let mut file = File::create(file_path)?;
+    write_entries(&mut file, header, messages)
+}
+
+pub fn load_messages_from_file(file_path: PathBuf, messages: &mut Vec<Message>) -> Result<ChatHeader, std::io::Error> {
     let mut content = String::new();
     File::open(file_path)?.read_to_string(&mut content)?;
     messages.clear();
+    let mut header = ChatHeader::default();
     for message in content.split(MESSAGE_SEPARATOR) {
         let trimmed = message.trim();
         if !trimmed.is_empty() {
-            if let Some(content) = trimmed.strip_prefix("User: ") {
-                messages.push(Message::new(content.to_string(), true, None));
-            } else if let Some((model, content)) = trimmed.split_once(": ") {
-                messages.push(Message::new(content.to_string(), false, Some(model.to_string())));
+            // Header entries come before the first message.
+            if let Some(system_prompt) = trimmed.strip_prefix(SYSTEM_PREFIX).filter(|_| messages.is_empty()) {
+                header.system_prompt = Some(system_prompt.to_string());
+            } else if let Some(options) = trimmed.strip_prefix(OPTIONS_PREFIX).filter(|_| messages.is_empty()) {
+                match serde_json::from_str(options) {
+                    Ok(options) => header.options = options,
+                    Err(e) => eprintln!("Ignoring unreadable chat options: {}", e),
+                }
+            } else if let Some(summary) = trimmed.strip_prefix(SUMMARY_PREFIX).filter(|_| messages.is_empty()) {
+                let (text, meta) = split_meta::<SummaryMeta>(summary);
+                header.summary = Some(Summary { text: text.to_string(), covers: meta.covers, enabled: meta.enabled });
+            } else {
+                let (entry, meta) = split_meta::<MessageMeta>(trimmed);
+                if let Some(content) = entry.strip_prefix("User: ") {
+                    messages.push(Message::new(content.to_string(), true, None).with_meta(meta));
+                } else if let Some((model, content)) = entry.split_once(": ") {
+                    messages.push(Message::new(content.to_string(), false, Some(model.to_string())).with_meta(meta));
+                }
             }
         }
     }
-    Ok(())
+    Ok(header)
 }
 
 pub fn delete_chat_file(file_path: PathBuf) -> Result<(), std::io::Error> {
@@ -75,17 +170,10 @@ pub fn rename_chat_file(old_path: &Path, new_name: &str, directory: &str) -> Res
     Ok(new_path.file_name().unwrap().to_string_lossy().to_string())
 }
 
-pub fn export_chat(path: &Path, messages: &[Message]) -> Result<(), std::io::Error> {
+pub fn export_chat(path: &Path, header: &ChatHeader, messages: &[Message]) -> Result<(), std::io::Error> {
     println!("Debug: Exporting chat to: {:?}", path);
     let mut file = File::create(path)?;
-    for message in messages {
-        let prefix = if message.is_user() {
-            "User: ".to_string()
-        } else {
-            format!("{}: ", message.model().unwrap_or("Bot"))
-        };
-        writeln!(file, "{}{}{}", prefix, message.content(), MESSAGE_SEPARATOR)?;
-    }
+    write_entries(&mut file, header, messages)?;
     println!("Debug: Exported {} messages", messages.len());
     Ok(())
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use crate::message::Message;
 use std::path::Path;
-use super::file_operations::{load_messages_from_file, append_message_to_file, create_new_chat_file, delete_chat_file, rename_chat_file};
+use super::file_operations::{load_messages_from_file, append_message_to_file, create_new_chat_file, delete_chat_file, rename_chat_file, write_chat_file, ChatHeader};
 
 pub struct ChatHistory {
     history_files: Vec<String>,
@@ -53,22 +53,32 @@ impl ChatHistory {
         Ok(file_name)
     }
 
-    pub fn append_message(&mut self, content: &str, is_user: bool, model: Option<&str>) -> Result<(), std::io::Error> {
+    pub fn append_message(&mut self, message: &Message) -> Result<(), std::io::Error> {
         if let Some(current_file) = &self.current_file {
             println!("Debug: Appending message to file: {}", current_file);
-            append_message_to_file(Path::new(&self.directory).join(current_file), content, is_user, model)?;
+            append_message_to_file(Path::new(&self.directory).join(current_file), message)?;
         } else {
             println!("Debug: No current file to append message");
         }
         Ok(())
     }
 
-    pub fn load_chat(&mut self, file_name: &str, messages: &mut Vec<Message>) -> Result<(), std::io::Error> {
+    pub fn rewrite_current_chat(&self, header: &ChatHeader, messages: &[Message]) -> Result<(), std::io::Error> {
+        if let Some(current_file) = &self.current_file {
+            println!("Debug: Rewriting chat file: {}", current_file);
+            write_chat_file(Path::new(&self.directory).join(current_file), header, messages)?;
+        } else {
+            println!("Debug: No current file to rewrite");
+        }
+        Ok(())
+    }
+
+    pub fn load_chat(&mut self, file_name: &str, messages: &mut Vec<Message>) -> Result<ChatHeader, std::io::Error> {
         println!("Debug: Loading chat from file: {}", file_name);
-        load_messages_from_file(Path::new(&self.directory).join(file_name), messages)?;
+        let header = load_messages_from_file(Path::new(&self.directory).join(file_name), messages)?;
         self.current_file = Some(file_name.to_string());
         println!("Debug: Loaded {} messages from file", messages.len());
-        Ok(())
+        Ok(header)
     }
 
     pub fn delete_chat(&mut self, file_name: &str) -> Result<Option<String>, std::io::Error> {
@@ -4,5 +4,7 @@ pub mod ui_rendering;
 pub mod chat_operations;
 pub mod chat_core;
 pub mod chat_name_generation;
+pub mod context_budget;
+pub mod compaction;
 
 pub use chat_core::Chat;
\ No newline at end of file
@@ -1,6 +1,7 @@
 use crate::message::Message;
-use crate::providers::{ProviderTrait, ProviderError};
-use serde_json::json;
+use crate::providers::{ChatRequest, RequestOptions, ProviderTrait, ProviderError, StreamEvent, find_provider_for_model};
+use crate::tools::{ToolResult, ToolSpec};
+use serde_json::{json, Value};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
@@ -11,26 +12,29 @@ pub struct Chatbot {
 
 impl Chatbot {
     pub fn new(provider: Arc<dyn ProviderTrait + Send + Sync>) -> Self {
-        let initial_model = provider.models()[0].0.to_string();
+        let initial_model = provider.models()[0].0.clone();
         Self { 
             provider,
             current_model: initial_model,
         }
     }
 
-    pub fn stream_response(&self, messages: &Vec<Message>) -> Result<mpsc::Receiver<String>, ProviderError> {
+    /// Streams the next reply. `tools` are only offered if the provider supports them.
+    pub fn stream_response(&self, messages: &[Message], system_prompt: Option<String>, options: RequestOptions, tools: Vec<ToolSpec>) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
         println!("Debug: Streaming response for {} messages", messages.len());
-        let formatted_messages = messages.iter().map(|m| {
-            json!({
-                "role": if m.is_user() { "user" } else { "assistant" },
-                "content": m.content()
-            })
-        }).collect::<Vec<_>>();
-
-        self.provider.stream_response(formatted_messages)
+        let supports_tools = self.provider.supports_tools();
+        let formatted_messages = format_messages(messages, supports_tools);
+        let tools = if supports_tools { tools } else { Vec::new() };
+        let (system_prompt, options) = match options.json_schema.clone() {
+            Some(schema) if !self.provider.supports_json_schema() => {
+                (Some(system_prompt_with_schema(system_prompt.as_deref(), &schema)), RequestOptions { json_schema: None, ..options })
+            }
+            _ => (system_prompt, options),
+        };
+        self.provider.stream_response(ChatRequest::new(system_prompt, formatted_messages).with_options(options).with_tools(tools))
     }
 
-    pub fn generate_chat_name(&self, messages: &Vec<Message>) -> Result<mpsc::Receiver<String>, ProviderError> {
+    pub fn generate_chat_name(&self, messages: &Vec<Message>) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
         println!("Debug: Generating chat name for {} messages", messages.len());
         let prompt = format!(
             "No intro text or confirmation, just give me a concise 3-word name for this chat. Your response should be 3 words max. If you don't have enough info, be a bit creative. Use initcaps:\n\n{}",
@@ -42,15 +46,15 @@ impl Chatbot {
             "content": prompt
         })];
 
-        self.provider.stream_response(formatted_message)
+        self.provider.stream_response(ChatRequest::new(None, formatted_message))
     }
 
-    pub fn switch_model(&mut self, providers: &Vec<Arc<dyn ProviderTrait + Send + Sync>>, model: String) {
+    pub fn switch_model(&mut self, providers: &Vec<Arc<dyn ProviderTrait + Send + Sync>>, preferred_provider: &str, model: String) {
         if model == "Other" {
             // For "Other", we keep the current provider but update the model name
             self.current_model = model;
             println!("Debug: Switched to custom model: {}", self.current_model);
-        } else if let Some(new_provider) = providers.iter().find(|p| p.models().iter().any(|&(model_name, _)| model_name == model.as_str())) {
+        } else if let Some(new_provider) = find_provider_for_model(providers, preferred_provider, &model) {
             self.provider = Arc::clone(new_provider);
             self.current_model = model;
             println!("Debug: Switched to model: {}", self.current_model);
@@ -67,4 +71,125 @@ impl Chatbot {
             eprintln!("Model not found: {}", model);
         }
     }
-}
\ No newline at end of file
+}
+/// Our messages in the provider-neutral format of `ChatRequest`. A reply with
+/// tool calls is followed by a message with their results; providers without
+/// tool support get both as plain text instead.
+fn format_messages(messages: &[Message], supports_tools: bool) -> Vec<Value> {
+    let mut formatted = Vec::new();
+    for m in messages {
+        let mut message = json!({
+            "role": if m.is_user() { "user" } else { "assistant" },
+            "content": m.content_with_files()
+        });
+        if !m.images().is_empty() {
+            message["images"] = m.images().iter()
+                .map(|image| json!({ "media_type": image.media_type, "data": image.data }))
+                .collect();
+        }
+        let tool_calls = &m.meta().tool_calls;
+        if tool_calls.is_empty() {
+            formatted.push(message);
+            continue;
+        }
+
+        // Every call needs a result, also when the response was stopped before it ran.
+        let cancelled = ToolResult::error("The tool call was cancelled before it ran.");
+        let results = tool_calls.iter().map(|tool_use| (&tool_use.call, tool_use.result.as_ref().unwrap_or(&cancelled)));
+        if supports_tools {
+            message["tool_calls"] = tool_calls.iter()
+                .map(|tool_use| json!({ "id": tool_use.call.id, "name": tool_use.call.name, "input": tool_use.call.input }))
+                .collect();
+            if let Some(reasoning) = m.meta().reasoning.as_ref().filter(|r| r.signature.is_some()) {
+                message["reasoning"] = json!({ "text": reasoning.text, "signature": reasoning.signature });
+            }
+            formatted.push(message);
+            formatted.push(json!({
+                "role": "tool",
+                "results": results.map(|(call, result)| json!({ "id": call.id, "content": result.content, "is_error": result.is_error })).collect::<Vec<_>>()
+            }));
+        } else {
+            let calls: Vec<String> = tool_calls.iter().map(|tool_use| format!("[Called {} with {}]", tool_use.call.name, tool_use.call.input)).collect();
+            message["content"] = json!(format!("{}\n\n{}", m.content(), calls.join("\n")).trim_start());
+            formatted.push(message);
+            let results: Vec<String> = results.map(|(call, result)| format!("[Result of {}]\n{}", call.name, result.content)).collect();
+            formatted.push(json!({ "role": "user", "content": results.join("\n\n") }));
+        }
+    }
+    formatted
+}
+
+/// The system prompt for providers that can't be held to a schema: they are
+/// asked to answer in JSON, and the reply is checked afterwards like any other.
+fn system_prompt_with_schema(system_prompt: Option<&str>, schema: &Value) -> String {
+    let instruction = format!(
+        "Reply with only a JSON object that matches this JSON Schema, without any other text or a code fence:\n{}",
+        serde_json::to_string_pretty(schema).unwrap_or_else(|_| schema.to_string()),
+    );
+    match system_prompt.map(str::trim).filter(|prompt| !prompt.is_empty()) {
+        Some(prompt) => format!("{}\n\n{}", prompt, instruction),
+        None => instruction,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{MessageMeta, Reasoning};
+    use crate::tools::{ToolCall, ToolUse};
+
+    fn tool_round() -> Vec<Message> {
+        let call = ToolCall { id: "call_1".into(), name: "calculator".into(), input: json!({ "expression": "2+2" }) };
+        let meta = MessageMeta {
+            tool_calls: vec![ToolUse { call, result: Some(ToolResult { content: "4".into(), is_error: false }) }],
+            ..MessageMeta::default()
+        };
+        vec![
+            Message::new("What is 2+2?".into(), true, None),
+            Message::new(String::new(), false, None).with_meta(meta),
+            Message::new("It is 4.".into(), false, None),
+        ]
+    }
+
+    #[test]
+    fn follows_tool_calls_with_their_results() {
+        assert_eq!(format_messages(&tool_round(), true), vec![
+            json!({ "role": "user", "content": "What is 2+2?" }),
+            json!({ "role": "assistant", "content": "", "tool_calls": [{ "id": "call_1", "name": "calculator", "input": { "expression": "2+2" } }] }),
+            json!({ "role": "tool", "results": [{ "id": "call_1", "content": "4", "is_error": false }] }),
+            json!({ "role": "assistant", "content": "It is 4." }),
+        ]);
+    }
+
+    #[test]
+    fn writes_tool_rounds_out_as_text_for_providers_without_tools() {
+        let formatted = format_messages(&tool_round(), false);
+        assert_eq!(formatted[1], json!({ "role": "assistant", "content": "[Called calculator with {\"expression\":\"2+2\"}]" }));
+        assert_eq!(formatted[2], json!({ "role": "user", "content": "[Result of calculator]\n4" }));
+    }
+
+    #[test]
+    fn keeps_reasoning_out_of_the_content() {
+        let mut messages = tool_round();
+        let signed = Reasoning { text: "2+2 is simple".into(), signature: Some("sig".into()) };
+        let mut meta = messages[1].meta().clone();
+        meta.reasoning = Some(signed);
+        messages[1] = Message::new(String::new(), false, None).with_meta(meta);
+        let unsigned = MessageMeta { reasoning: Some(Reasoning { text: "Just say 4".into(), signature: None }), ..MessageMeta::default() };
+        messages[2] = Message::new("It is 4.".into(), false, None).with_meta(unsigned);
+
+        let formatted = format_messages(&messages, true);
+        assert_eq!(formatted[1]["reasoning"], json!({ "text": "2+2 is simple", "signature": "sig" }));
+        assert_eq!(formatted[3], json!({ "role": "assistant", "content": "It is 4." }));
+        assert!(format_messages(&messages, false).iter().all(|message| message.get("reasoning").is_none()));
+    }
+
+    #[test]
+    fn asks_for_json_in_the_system_prompt() {
+        let schema = json!({ "type": "object" });
+        let prompt = system_prompt_with_schema(Some("Be brief."), &schema);
+        assert!(prompt.starts_with("Be brief.\n\nReply with only a JSON object"));
+        assert!(prompt.ends_with("{\n  \"type\": \"object\"\n}"));
+        assert!(system_prompt_with_schema(Some("  "), &schema).starts_with("Reply with only"));
+    }
+}
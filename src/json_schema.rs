@@ -0,0 +1,319 @@
+use regex::Regex;
+use serde_json::Value;
+
+// Guards against schemas whose `$ref`s point back at themselves.
+const MAX_DEPTH: usize = 64;
+
+/// Reads the schema of a chat in JSON mode. Providers want an object at the
+/// top, so the schema has to describe one.
+pub fn parse_schema(text: &str) -> Result<Value, String> {
+    let schema: Value = serde_json::from_str(text).map_err(|e| format!("The schema is not valid JSON: {}", e))?;
+    if !schema.is_object() {
+        return Err("The schema must be a JSON object.".to_string());
+    }
+    if schema["type"] != "object" {
+        return Err("The schema must describe an object (\"type\": \"object\").".to_string());
+    }
+    Ok(schema)
+}
+
+/// The JSON value of a reply. Models without enforced output sometimes wrap
+/// it in a code fence, which is ignored.
+pub fn parse_reply(content: &str) -> Result<Value, String> {
+    let trimmed = content.trim();
+    let unfenced = trimmed.strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map(|inner| inner.trim_start_matches(|c: char| c.is_ascii_alphabetic()).trim())
+        .unwrap_or(trimmed);
+    serde_json::from_str(unfenced).map_err(|e| format!("The reply is not valid JSON: {}", e))
+}
+
+/// Everything about a reply that doesn't match `schema`; empty if it all does.
+pub fn check_reply(schema: &Value, content: &str) -> Vec<String> {
+    match parse_reply(content) {
+        Ok(value) => validate(schema, &value),
+        Err(e) => vec![e],
+    }
+}
+
+/// Checks `value` against `schema`, covering the keywords extraction schemas
+/// use: types, properties, items, enums, bounds, patterns, combinators and
+/// local `$ref`s. Other keywords, such as `format`, are not checked.
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut validator = Validator { root: schema, errors: Vec::new() };
+    validator.check(schema, value, "$", 0);
+    validator.errors
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    errors: Vec<String>,
+}
+
+impl<'a> Validator<'a> {
+    fn fail(&mut self, path: &str, message: String) {
+        self.errors.push(format!("{}: {}", path, message));
+    }
+
+    /// Whether `value` matches `schema`, without recording why not.
+    fn matches(&self, schema: &Value, value: &Value, depth: usize) -> bool {
+        let mut inner = Validator { root: self.root, errors: Vec::new() };
+        inner.check(schema, value, "$", depth);
+        inner.errors.is_empty()
+    }
+
+    fn check(&mut self, schema: &Value, value: &Value, path: &str, depth: usize) {
+        if depth > MAX_DEPTH {
+            self.fail(path, "the schema nests too deeply".to_string());
+            return;
+        }
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return self.fail(path, "no value is allowed here".to_string()),
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match reference.strip_prefix('#').and_then(|pointer| self.root.pointer(pointer)) {
+                Some(target) => self.check(target, value, path, depth + 1),
+                None => self.fail(path, format!("the schema's $ref {} can't be resolved", reference)),
+            }
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let types: Vec<&str> = match expected {
+                Value::String(name) => vec![name.as_str()],
+                Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+                return self.fail(path, format!("expected {}, got {}", types.join(" or "), type_name(value)));
+            }
+        }
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                let listed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+                self.fail(path, format!("{} is not one of {}", value, listed.join(", ")));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                self.fail(path, format!("expected {}, got {}", expected, value));
+            }
+        }
+
+        match value {
+            Value::String(text) => self.check_string(schema, text, path),
+            Value::Number(_) => self.check_number(schema, value.as_f64().unwrap_or_default(), path),
+            Value::Array(items) => self.check_array(schema, items, path, depth),
+            Value::Object(object) => self.check_object(schema, object, path, depth),
+            _ => {}
+        }
+
+        if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+            for sub in all {
+                self.check(sub, value, path, depth + 1);
+            }
+        }
+        if let Some(any) = schema.get("anyOf").and_then(Value::as_array) {
+            if !any.iter().any(|sub| self.matches(sub, value, depth + 1)) {
+                self.fail(path, "matches none of the anyOf schemas".to_string());
+            }
+        }
+        if let Some(one) = schema.get("oneOf").and_then(Value::as_array) {
+            let matching = one.iter().filter(|sub| self.matches(sub, value, depth + 1)).count();
+            if matching != 1 {
+                self.fail(path, format!("matches {} of the oneOf schemas instead of exactly one", matching));
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if self.matches(not, value, depth + 1) {
+                self.fail(path, "matches the schema under \"not\"".to_string());
+            }
+        }
+    }
+
+    fn check_string(&mut self, schema: &serde_json::Map<String, Value>, text: &str, path: &str) {
+        let length = text.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64).filter(|min| length < *min) {
+            self.fail(path, format!("is {} characters long, less than {}", length, min));
+        }
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64).filter(|max| length > *max) {
+            self.fail(path, format!("is {} characters long, more than {}", length, max));
+        }
+        if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+            match Regex::new(pattern) {
+                Ok(regex) if !regex.is_match(text) => self.fail(path, format!("doesn't match the pattern {}", pattern)),
+                Ok(_) => {}
+                Err(e) => self.fail(path, format!("the schema's pattern {} is invalid: {}", pattern, e)),
+            }
+        }
+    }
+
+    fn check_number(&mut self, schema: &serde_json::Map<String, Value>, number: f64, path: &str) {
+        let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+        if let Some(min) = bound("minimum").filter(|min| number < *min) {
+            self.fail(path, format!("{} is less than the minimum {}", number, min));
+        }
+        if let Some(max) = bound("maximum").filter(|max| number > *max) {
+            self.fail(path, format!("{} is more than the maximum {}", number, max));
+        }
+        if let Some(min) = bound("exclusiveMinimum").filter(|min| number <= *min) {
+            self.fail(path, format!("{} is not more than {}", number, min));
+        }
+        if let Some(max) = bound("exclusiveMaximum").filter(|max| number >= *max) {
+            self.fail(path, format!("{} is not less than {}", number, max));
+        }
+        if let Some(step) = bound("multipleOf").filter(|step| *step > 0.0) {
+            let quotient = number / step;
+            if (quotient - quotient.round()).abs() > 1e-9 {
+                self.fail(path, format!("{} is not a multiple of {}", number, step));
+            }
+        }
+    }
+
+    fn check_array(&mut self, schema: &serde_json::Map<String, Value>, items: &[Value], path: &str, depth: usize) {
+        let count = items.len() as u64;
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64).filter(|min| count < *min) {
+            self.fail(path, format!("has {} items, fewer than {}", count, min));
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64).filter(|max| count > *max) {
+            self.fail(path, format!("has {} items, more than {}", count, max));
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            if let Some(index) = (1..items.len()).find(|&i| items[..i].contains(&items[i])) {
+                self.fail(path, format!("item {} repeats an earlier item", index));
+            }
+        }
+        let prefix = schema.get("prefixItems").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+        for (index, item) in items.iter().enumerate() {
+            let item_path = format!("{}[{}]", path, index);
+            match prefix.get(index) {
+                Some(item_schema) => self.check(item_schema, item, &item_path, depth + 1),
+                None => {
+                    if let Some(item_schema) = schema.get("items") {
+                        self.check(item_schema, item, &item_path, depth + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_object(&mut self, schema: &serde_json::Map<String, Value>, object: &serde_json::Map<String, Value>, path: &str, depth: usize) {
+        for name in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                self.fail(path, format!("is missing the required property \"{}\"", name));
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (name, property) in object {
+            let property_path = format!("{}.{}", path, name);
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(property_schema) => self.check(property_schema, property, &property_path, depth + 1),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => self.fail(path, format!("has the property \"{}\", which the schema doesn't allow", name)),
+                    Some(additional) => self.check(additional, property, &property_path, depth + 1),
+                    None => {}
+                },
+            }
+        }
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn invoice_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "number": { "type": "string", "pattern": "^INV-[0-9]+$" },
+                "total": { "type": "number", "minimum": 0 },
+                "currency": { "enum": ["EUR", "USD"] },
+                "lines": { "type": "array", "minItems": 1, "items": { "$ref": "#/$defs/line" } },
+                "paid": { "type": ["boolean", "null"] }
+            },
+            "required": ["number", "total", "lines"],
+            "additionalProperties": false,
+            "$defs": {
+                "line": {
+                    "type": "object",
+                    "properties": { "description": { "type": "string" }, "quantity": { "type": "integer", "exclusiveMinimum": 0 } },
+                    "required": ["description", "quantity"]
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn accepts_a_matching_value() {
+        let value = json!({ "number": "INV-42", "total": 19.5, "currency": "EUR", "lines": [{ "description": "Pens", "quantity": 3 }], "paid": null });
+        assert_eq!(validate(&invoice_schema(), &value), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_each_mismatch_with_its_path() {
+        let value = json!({ "number": "42", "total": -1, "currency": "GBP", "lines": [{ "description": "Pens", "quantity": 1.5 }, { "quantity": 0 }], "note": "x" });
+        assert_eq!(validate(&invoice_schema(), &value), vec![
+            "$.currency: \"GBP\" is not one of \"EUR\", \"USD\"",
+            "$.lines[0].quantity: expected integer, got number",
+            "$.lines[1]: is missing the required property \"description\"",
+            "$.lines[1].quantity: 0 is not more than 0",
+            "$: has the property \"note\", which the schema doesn't allow",
+            "$.number: doesn't match the pattern ^INV-[0-9]+$",
+            "$.total: -1 is less than the minimum 0",
+        ]);
+    }
+
+    #[test]
+    fn checks_combinators() {
+        let schema = json!({ "anyOf": [{ "type": "string" }, { "type": "integer" }], "not": { "const": 3 } });
+        assert!(validate(&schema, &json!("a")).is_empty());
+        assert_eq!(validate(&schema, &json!(true)), vec!["$: matches none of the anyOf schemas"]);
+        assert_eq!(validate(&schema, &json!(3)), vec!["$: matches the schema under \"not\""]);
+        let one_of = json!({ "oneOf": [{ "type": "number" }, { "type": "integer" }] });
+        assert_eq!(validate(&one_of, &json!(1)), vec!["$: matches 2 of the oneOf schemas instead of exactly one"]);
+    }
+
+    #[test]
+    fn reads_replies_with_or_without_a_code_fence() {
+        assert_eq!(parse_reply("```json\n{\"a\": 1}\n```").unwrap(), json!({ "a": 1 }));
+        assert_eq!(parse_reply(" {\"a\": 1} ").unwrap(), json!({ "a": 1 }));
+        assert_eq!(check_reply(&json!({ "type": "object" }), "{\"a\": ").len(), 1);
+    }
+
+    #[test]
+    fn requires_an_object_schema() {
+        assert!(parse_schema("{\"type\": \"object\"}").is_ok());
+        assert!(parse_schema("{\"type\": \"array\"}").is_err());
+        assert!(parse_schema("[1]").is_err());
+        assert!(parse_schema("{").is_err());
+    }
+}
@@ -5,6 +5,9 @@ mod message;
 mod chatbot;
 mod settings;
 mod providers;
+mod attachments;
+mod tools;
+mod json_schema;
 use eframe::NativeOptions;
 use egui::{ViewportBuilder, IconData};
 
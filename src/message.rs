@@ -1,13 +1,148 @@
+use crate::providers::{StopReason, Usage};
+use crate::tools::ToolUse;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+/// An image sent with a message. The data is kept base64-encoded, which is
+/// how every API takes it and keeps chat files self-contained.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImageAttachment {
+    pub name: String,
+    /// e.g. `image/png`
+    pub media_type: String,
+    pub data: String,
+}
+
+impl ImageAttachment {
+    pub fn new(name: String, media_type: String, bytes: &[u8]) -> Self {
+        Self { name, media_type, data: BASE64.encode(bytes) }
+    }
+
+    pub fn bytes(&self) -> Option<Vec<u8>> {
+        BASE64.decode(&self.data).ok()
+    }
+}
+
+/// A text or source file attached to a message. It is sent ahead of the
+/// message text but shown apart from it, so the history stays readable.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileAttachment {
+    pub name: String,
+    /// Language name for highlighting and for the model, e.g. `rust`; `text` if unknown.
+    pub language: String,
+    pub content: String,
+}
+
+impl FileAttachment {
+    /// The file as the model sees it, delimited so it can't run into the message.
+    pub fn as_context(&self) -> String {
+        format!("<file name=\"{}\" language=\"{}\">\n{}\n</file>", self.name, self.language, self.content.trim_end())
+    }
+}
+
+/// What the model thought before answering. It is shown apart from the
+/// answer and not sent back as part of it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reasoning {
+    pub text: String,
+    /// Claude signs its thinking, and needs it back, signed, when a tool round continues.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// Details about a message beyond its text, saved alongside it in the chat file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<StopReason>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Cost at the prices known when the response arrived; `None` for unpriced models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageAttachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileAttachment>,
+    /// Tools the model called in this reply, with their results once they have run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolUse>,
+    /// Set on replies in JSON mode: where the reply doesn't match the chat's
+    /// schema, empty if it does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_errors: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<Reasoning>,
+}
+
+impl MessageMeta {
+    pub fn is_empty(&self) -> bool {
+        *self == MessageMeta::default()
+    }
+
+    /// Fills in the results of the tool calls that `results` has an entry for.
+    pub fn set_tool_results(&mut self, results: &[ToolUse]) {
+        for tool_use in self.tool_calls.iter_mut() {
+            if let Some(done) = results.iter().find(|done| done.call.id == tool_use.call.id) {
+                tool_use.result = done.result.clone();
+            }
+        }
+    }
+
+    /// Metadata for a reply finished by a continuation: usage and cost add
+    /// up, the stop reason is the continuation's and tool calls and reasoning
+    /// are kept from both.
+    pub fn followed_by(&self, next: MessageMeta) -> MessageMeta {
+        let add = |a: Option<u64>, b: Option<u64>| match (a, b) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+        };
+        let usage = match (self.usage, next.usage) {
+            (Some(previous), Some(next)) => Some(Usage {
+                input_tokens: add(previous.input_tokens, next.input_tokens),
+                output_tokens: add(previous.output_tokens, next.output_tokens),
+            }),
+            (previous, next) => next.or(previous),
+        };
+        // A part without a known price leaves the total unknown too.
+        let cost_usd = self.cost_usd.zip(next.cost_usd).map(|(a, b)| a + b);
+        let reasoning = match (self.reasoning.clone(), next.reasoning) {
+            (Some(previous), Some(next)) => Some(Reasoning {
+                text: format!("{}\n\n{}", previous.text, next.text),
+                signature: next.signature,
+            }),
+            (previous, next) => next.or(previous),
+        };
+        MessageMeta {
+            stop_reason: next.stop_reason,
+            usage,
+            cost_usd,
+            images: self.images.clone(),
+            files: self.files.clone(),
+            tool_calls: [self.tool_calls.clone(), next.tool_calls].concat(),
+            schema_errors: next.schema_errors,
+            reasoning,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Message {
     content: String,
     is_user: bool,
     model: Option<String>,
+    meta: MessageMeta,
 }
 
 impl Message {
     pub fn new(content: String, is_user: bool, model: Option<String>) -> Self {
-        Self { content, is_user, model }
+        Self { content, is_user, model, meta: MessageMeta::default() }
+    }
+
+    pub fn with_meta(mut self, meta: MessageMeta) -> Self {
+        self.meta = meta;
+        self
     }
 
     pub fn content(&self) -> &str {
@@ -21,4 +156,25 @@ impl Message {
     pub fn model(&self) -> Option<&str> {
         self.model.as_deref()
     }
-}
\ No newline at end of file
+
+    pub fn meta(&self) -> &MessageMeta {
+        &self.meta
+    }
+
+    pub fn images(&self) -> &[ImageAttachment] {
+        &self.meta.images
+    }
+
+    pub fn files(&self) -> &[FileAttachment] {
+        &self.meta.files
+    }
+
+    /// The text sent to the model: attached files first, then the message itself.
+    pub fn content_with_files(&self) -> String {
+        if self.meta.files.is_empty() {
+            return self.content.clone();
+        }
+        let files: Vec<String> = self.meta.files.iter().map(FileAttachment::as_context).collect();
+        format!("{}\n\n{}", files.join("\n\n"), self.content)
+    }
+}
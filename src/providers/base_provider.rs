@@ -1,73 +1,129 @@
 use crate::providers::ProviderError;
-use crate::app::ProfileType;
-use reqwest::Client;
+use crate::providers::registry::ProviderConfig;
+use crate::providers::retry::RetryPolicy;
+use crate::providers::chat_request::ChatRequest;
+use crate::providers::stream_event::StreamEvent;
+use crate::providers::parameters::ParamSpec;
+use crate::providers::network;
+use crate::app::Profile;
+use reqwest::{Client, RequestBuilder};
 use serde_json::Value;
 use tokio::sync::mpsc;
 use std::fmt;
+use async_trait::async_trait;
 
 pub struct BaseProvider {
-    pub client: Client,
+    /// Fails when the proxy, CA bundle or headers can't be applied; the
+    /// provider then refuses requests rather than connect without them.
+    pub client: Result<Client, String>,
     pub api_key: String,
-    pub top_p: f32,
-    pub top_k: u32,
-    pub repetition_penalty: f32,
-    pub creativity: f32,
+    pub base_url: String,
+    pub profile: Profile,
+    pub discovered_models: Option<Vec<(String, usize)>>,
+    pub retry_policy: RetryPolicy,
 }
 
 impl BaseProvider {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(config: ProviderConfig) -> Self {
+        let client = network::build_client(&config.network, &config.headers);
+        if let Err(e) = &client {
+            eprintln!("Failed to apply network settings: {}", e);
+        }
         Self {
-            client: Client::new(),
-            api_key,
-            top_p: 0.9,
-            top_k: 50,
-            repetition_penalty: 0.2,
-            creativity: 0.8,
+            client,
+            api_key: config.api_key,
+            base_url: config.base_url.trim().trim_end_matches('/').to_string(),
+            profile: Profile::default(),
+            discovered_models: None,
+            retry_policy: config.retry,
         }
     }
 
-    pub fn update_profile(&mut self, profile: ProfileType) {
-        match profile {
-            ProfileType::Coder => {
-                self.top_p = 0.85;
-                self.top_k = 40;
-                self.repetition_penalty = 0.04;
-                self.creativity = 0.4;
-            },
-            ProfileType::Normal => {
-                self.top_p = 0.9;
-                self.top_k = 50;
-                self.repetition_penalty = 0.15;
-                self.creativity = 0.7;
-            },
-            ProfileType::Creative => {
-                self.top_p = 0.95;
-                self.top_k = 80;
-                self.repetition_penalty = 0.4;
-                self.creativity = 1.4;
-            },
-        }
+    pub fn update_profile(&mut self, profile: Profile) {
+        self.profile = profile;
     }
 
-    pub fn get_client(&self) -> Client {
-        self.client.clone()
+    pub fn get_client(&self) -> Result<Client, ProviderError> {
+        self.client.clone().map_err(|e| ProviderError::NotConfigured(format!("Network settings can't be applied: {}", e)))
     }
 
     pub fn get_api_key(&self) -> String {
         self.api_key.clone()
     }
 
-    pub fn get_parameters(&self) -> (f32, u32, f32, f32) {
-        (self.top_p, self.top_k, self.repetition_penalty, self.creativity)
+    pub fn get_base_url(&self) -> String {
+        self.base_url.clone()
+    }
+
+    pub fn get_retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
+
+    pub fn get_profile(&self) -> Profile {
+        self.profile.clone()
+    }
+
+    pub fn set_models(&mut self, models: Vec<(String, usize)>) {
+        self.discovered_models = Some(models);
+    }
+
+    /// The discovered model list, or `fallback` if discovery hasn't succeeded yet.
+    pub fn models_or(&self, fallback: Vec<(String, usize)>) -> Vec<(String, usize)> {
+        match &self.discovered_models {
+            Some(models) if !models.is_empty() => models.clone(),
+            _ => fallback,
+        }
     }
 }
 
+/// Sends a model-listing request and parses the JSON body.
+pub async fn get_json(request: RequestBuilder) -> Result<Value, ProviderError> {
+    let response = request.send().await.map_err(|e| ProviderError::RequestError(e.to_string()))?;
+    if !response.status().is_success() {
+        let error_body = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(ProviderError::ResponseError(error_body));
+    }
+    response.json().await.map_err(|e| ProviderError::ResponseError(e.to_string()))
+}
+
+/// Looks up the token limit for `model` in a provider's static list, falling back to `default`.
+pub fn known_token_limit(defaults: &[(String, usize)], model: &str, default: usize) -> usize {
+    defaults.iter().find(|(name, _)| name == model).map_or(default, |(_, tokens)| *tokens)
+}
 
+#[async_trait]
 pub trait ProviderTrait: fmt::Display + Send + Sync {
-    fn name(&self) -> &'static str;
-    fn models(&self) -> Vec<(&'static str, usize)>;
-    fn stream_response(&self, messages: Vec<Value>) -> Result<mpsc::Receiver<String>, ProviderError>;
+    fn name(&self) -> &str;
+    /// The models to offer: the last discovered list, or `default_models` when offline.
+    fn models(&self) -> Vec<(String, usize)>;
+    /// Built-in model list used until discovery succeeds.
+    fn default_models(&self) -> Vec<(String, usize)>;
+    /// Queries the provider's model-listing endpoint. An empty list means nothing was discovered.
+    async fn fetch_models(&self) -> Result<Vec<(String, usize)>, ProviderError> {
+        Ok(Vec::new())
+    }
+    fn set_models(&self, models: Vec<(String, usize)>);
+    fn stream_response(&self, request: ChatRequest) -> Result<mpsc::Receiver<StreamEvent>, ProviderError>;
     fn set_current_model(&self, model: String);
-    fn update_profile(&self, profile: ProfileType);
-    fn get_parameters(&self) -> (f32, u32, f32, f32);
+    fn update_profile(&self, profile: Profile);
+    /// Whether a trailing assistant message is continued rather than answered.
+    fn supports_prefill(&self) -> bool {
+        false
+    }
+    /// Whether the provider maps `ChatRequest::tools` and tool messages onto its API.
+    fn supports_tools(&self) -> bool {
+        false
+    }
+    /// Whether the provider makes replies follow `RequestOptions::json_schema`.
+    /// Others are asked to in the system prompt.
+    fn supports_json_schema(&self) -> bool {
+        false
+    }
+    /// Whether the numbers `models()` pairs with each model are reply length
+    /// limits. Ollama lists context lengths instead.
+    fn lists_output_limits(&self) -> bool {
+        true
+    }
+    /// The sampling parameters `model` accepts; the rest of a profile is not sent.
+    fn parameter_specs(&self, model: &str) -> &'static [ParamSpec];
 }
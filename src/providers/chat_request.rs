@@ -0,0 +1,87 @@
+use crate::app::Profile;
+use crate::tools::ToolSpec;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Per-chat settings that take precedence over the generation profile.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// `None` keeps the profile's stop sequences; an empty list sends none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    /// JSON mode: the reply must be a JSON object matching this schema.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<Value>,
+}
+
+impl RequestOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == RequestOptions::default()
+    }
+
+    pub fn applied_to(&self, mut profile: Profile) -> Profile {
+        if let Some(max_tokens) = self.max_tokens {
+            profile.max_tokens = Some(max_tokens);
+        }
+        if let Some(stop_sequences) = &self.stop_sequences {
+            profile.stop_sequences = stop_sequences.clone();
+        }
+        profile
+    }
+}
+
+/// Everything a provider needs to produce one response. Each provider maps
+/// the fields onto its own API, since they disagree on where e.g. the system
+/// prompt goes.
+#[derive(Clone, Default)]
+pub struct ChatRequest {
+    pub system: Option<String>,
+    /// `{"role": "user" | "assistant", "content": ...}` objects, oldest first.
+    /// Messages with images also carry `"images": [{"media_type", "data"}]`
+    /// with base64 data, which each provider maps to its own content format.
+    /// Assistant messages may carry `"tool_calls": [{"id", "name", "input"}]`,
+    /// answered by a following `{"role": "tool", "results": [{"id", "content",
+    /// "is_error"}]}` message; these only reach providers that support tools.
+    /// Such assistant messages may also carry the signed `"reasoning": {"text",
+    /// "signature"}` Claude produced with the calls. Reasoning is otherwise
+    /// never sent back.
+    pub messages: Vec<Value>,
+    /// Overrides the provider's current model for this request only.
+    pub model: Option<String>,
+    pub options: RequestOptions,
+    /// Tools the model may call; empty for none.
+    pub tools: Vec<ToolSpec>,
+}
+
+impl ChatRequest {
+    pub fn new(system: Option<String>, messages: Vec<Value>) -> Self {
+        Self { system: system.filter(|s| !s.trim().is_empty()), messages, model: None, options: RequestOptions::default(), tools: Vec::new() }
+    }
+
+    pub fn with_model(mut self, model: String) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_tools(mut self, tools: Vec<ToolSpec>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// The messages with the system prompt as a leading `system` role
+    /// message, as the OpenAI-style APIs (and Ollama) expect it. Images are
+    /// left in our own format.
+    pub fn messages_with_system_role(&self) -> Vec<Value> {
+        self.system.iter()
+            .map(|system| json!({ "role": "system", "content": system }))
+            .chain(self.messages.iter().cloned())
+            .collect()
+    }
+}
@@ -1,10 +1,30 @@
 use super::{ProviderError, ProviderTrait, BaseProvider};
+use super::registry::{Capabilities, ProviderConfig, ProviderInfo};
+use super::base_provider::{get_json, known_token_limit};
+use super::chat_request::ChatRequest;
+use super::stream_event::{spawn_stream, StopReason, StreamEvent, Usage};
+use super::sse::forward_sse;
+use super::tool_calls::ToolCallBuffer;
+use super::retry::send_with_retry;
+use async_trait::async_trait;
 use serde_json::{json, Value};
 use tokio::sync::mpsc;
 use std::fmt;
 use std::sync::{Arc, Mutex};
-use futures_util::StreamExt;
-use crate::app::ProfileType;
+use super::parameters::{self, ParamSpec};
+use crate::app::Profile;
+
+pub const INFO: ProviderInfo = ProviderInfo {
+    id: "claude",
+    display_name: "Claude",
+    key_name: Some("claude_api_key"),
+    default_url: "https://api.anthropic.com/v1",
+    capabilities: Capabilities { lists_models: true },
+    build: |config| Box::new(Claude::new(config)),
+};
+
+// JSON mode forces Claude to call this tool, whose input schema is the chat's.
+const JSON_TOOL: &str = "json_response";
 
 pub struct Claude {
     base: Arc<Mutex<BaseProvider>>,
@@ -12,103 +32,258 @@ pub struct Claude {
 }
 
 impl Claude {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(config: ProviderConfig) -> Self {
         Self {
-            base: Arc::new(Mutex::new(BaseProvider::new(api_key))),
+            base: Arc::new(Mutex::new(BaseProvider::new(config))),
             current_model: Arc::new(Mutex::new("claude-3-5-sonnet-20240620".to_string())),
         }
     }
 }
 
+/// Our messages as Claude content blocks: images ahead of the text, as
+/// Anthropic recommends, tool calls after it, and tool results in a user turn.
+/// With `thinking`, turns with tool calls start with their signed thinking,
+/// which Claude requires to continue a tool round.
+fn to_claude_messages(messages: &[Value], thinking: bool) -> Vec<Value> {
+    messages.iter().map(|message| {
+        if message["role"] == "tool" {
+            let results = message["results"].as_array().into_iter().flatten().map(|result| json!({
+                "type": "tool_result",
+                "tool_use_id": result["id"],
+                "content": result["content"],
+                "is_error": result["is_error"].as_bool().unwrap_or(false),
+            }));
+            return json!({ "role": "user", "content": results.collect::<Vec<_>>() });
+        }
+        if message.get("images").is_none() && message.get("tool_calls").is_none() {
+            return message.clone();
+        }
+        let images = message["images"].as_array().into_iter().flatten().map(|image| json!({
+            "type": "image",
+            "source": { "type": "base64", "media_type": image["media_type"], "data": image["data"] }
+        }));
+        // Claude rejects empty text blocks, which a turn with only tool calls would have.
+        let text = message["content"].as_str().filter(|text| !text.is_empty()).map(|text| json!({ "type": "text", "text": text }));
+        let tool_calls = message["tool_calls"].as_array().into_iter().flatten().map(|call| json!({
+            "type": "tool_use",
+            "id": call["id"],
+            "name": call["name"],
+            // Claude only takes objects, and malformed arguments are kept as a string.
+            "input": if call["input"].is_object() { call["input"].clone() } else { json!({}) },
+        }));
+        let reasoning = &message["reasoning"];
+        let thought = (thinking && reasoning["signature"].is_string())
+            .then(|| json!({ "type": "thinking", "thinking": reasoning["text"], "signature": reasoning["signature"] }));
+        json!({ "role": message["role"], "content": thought.into_iter().chain(images).chain(text).chain(tool_calls).collect::<Vec<_>>() })
+    }).collect()
+}
+
+/// What a stream has told so far about its content blocks.
+#[derive(Default)]
+struct Blocks {
+    /// Tool calls, gathered until their block ends.
+    tool_calls: ToolCallBuffer,
+    /// The call of the JSON mode tool, whose input is the reply itself.
+    json_reply: Option<u64>,
+}
+
+/// Turns one Messages API stream event into our events.
+fn parse_event(json: &Value, blocks: &mut Blocks) -> Vec<StreamEvent> {
+    let index = json["index"].as_u64().unwrap_or(0);
+    match json["type"].as_str() {
+        Some("message_start") => vec![StreamEvent::Usage(Usage {
+            input_tokens: json["message"]["usage"]["input_tokens"].as_u64(),
+            output_tokens: None,
+        })],
+        Some("content_block_start") if json["content_block"]["type"] == "tool_use" => {
+            if json["content_block"]["name"] == JSON_TOOL {
+                blocks.json_reply = Some(index);
+            } else {
+                blocks.tool_calls.update(index, json["content_block"]["id"].as_str(), json["content_block"]["name"].as_str(), "");
+            }
+            Vec::new()
+        }
+        Some("content_block_delta") if json["delta"]["type"] == "thinking_delta" => {
+            json["delta"]["thinking"].as_str().map(|text| StreamEvent::ReasoningDelta(text.to_string())).into_iter().collect()
+        }
+        Some("content_block_delta") if json["delta"]["type"] == "signature_delta" => {
+            json["delta"]["signature"].as_str().map(|signature| StreamEvent::ReasoningSignature(signature.to_string())).into_iter().collect()
+        }
+        Some("content_block_delta") => match (json["delta"]["text"].as_str(), json["delta"]["partial_json"].as_str()) {
+            (Some(text), _) => vec![StreamEvent::TextDelta(text.to_string())],
+            (None, Some(partial_json)) if blocks.json_reply == Some(index) => vec![StreamEvent::TextDelta(partial_json.to_string())],
+            (None, Some(partial_json)) => {
+                blocks.tool_calls.update(index, None, None, partial_json);
+                Vec::new()
+            }
+            (None, None) => Vec::new(),
+        },
+        Some("content_block_stop") => blocks.tool_calls.finish(index).into_iter().collect(),
+        Some("message_delta") => {
+            let mut events = Vec::new();
+            if let Some(reason) = json["delta"]["stop_reason"].as_str() {
+                // The JSON mode tool is how Claude answers, not a call to run.
+                let reason = if reason == "tool_use" && blocks.json_reply.is_some() { "end_turn" } else { reason };
+                events.push(StreamEvent::StopReason(StopReason::from_api(reason)));
+            }
+            if let Some(output_tokens) = json["usage"]["output_tokens"].as_u64() {
+                events.push(StreamEvent::Usage(Usage { input_tokens: None, output_tokens: Some(output_tokens) }));
+            }
+            events
+        }
+        Some("message_stop") => vec![StreamEvent::Done],
+        Some("error") => {
+            let message = json["error"]["message"].as_str().unwrap_or("Unknown error").to_string();
+            vec![StreamEvent::Error(ProviderError::StreamError(message))]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Completes the thinking settings `parameters::apply` wrote, if the profile
+/// has a budget. Claude can't think with a prefilled reply or a forced tool,
+/// and while thinking it only takes a top P of 0.95 or more.
+fn enable_thinking(body: &mut Value, request: &ChatRequest) -> bool {
+    let Some(budget) = body["thinking"]["budget_tokens"].as_u64() else {
+        return false;
+    };
+    let Some(fields) = body.as_object_mut() else {
+        return false;
+    };
+    let prefilled = request.messages.last().is_some_and(|message| message["role"] == "assistant");
+    if prefilled || request.options.json_schema.is_some() {
+        println!("Debug: Thinking is off for this request");
+        fields.remove("thinking");
+        return false;
+    }
+    fields.remove("temperature");
+    fields.remove("top_k");
+    if fields.get("top_p").and_then(Value::as_f64).is_some_and(|top_p| top_p < 0.95) {
+        fields.remove("top_p");
+    }
+    // The budget is part of max_tokens, which has to leave room for the answer.
+    if fields.get("max_tokens").and_then(Value::as_u64).unwrap_or(0) <= budget {
+        fields.insert("max_tokens".to_string(), json!((budget + 4096).min(64_000)));
+    }
+    fields.insert("thinking".to_string(), json!({ "type": "enabled", "budget_tokens": budget }));
+    true
+}
+
+/// Extended thinking came with Claude 3.7; older models reject it.
+fn can_think(model: &str) -> bool {
+    !["claude-2", "claude-instant", "claude-3-opus", "claude-3-sonnet", "claude-3-haiku", "claude-3-5"]
+        .iter()
+        .any(|old| model.starts_with(old))
+}
+
+#[async_trait]
 impl ProviderTrait for Claude {
-    fn name(&self) -> &'static str {
-        "Claude"
+    fn name(&self) -> &str {
+        INFO.display_name
     }
 
-    fn models(&self) -> Vec<(&'static str, usize)> {
-        vec![
+    fn models(&self) -> Vec<(String, usize)> {
+        self.base.lock().unwrap().models_or(self.default_models())
+    }
+
+    fn default_models(&self) -> Vec<(String, usize)> {
+        [
             ("claude-3-5-sonnet-20240620", 8192),
+            ("claude-3-7-sonnet-20250219", 64000),
             ("claude-3-haiku-20240307", 4096),
         ]
+        .into_iter()
+        .map(|(name, tokens)| (name.to_string(), tokens))
+        .collect()
+    }
+
+    async fn fetch_models(&self) -> Result<Vec<(String, usize)>, ProviderError> {
+        let (client, api_key, base_url) = {
+            let base = self.base.lock().unwrap();
+            (base.get_client()?, base.get_api_key(), base.get_base_url())
+        };
+        if api_key.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let json = get_json(client
+            .get(format!("{}/models?limit=100", base_url))
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01"))
+            .await?;
+
+        let defaults = self.default_models();
+        Ok(json["data"].as_array().into_iter().flatten()
+            .filter_map(|m| m["id"].as_str())
+            .map(|id| (id.to_string(), known_token_limit(&defaults, id, 8192)))
+            .collect())
+    }
+
+    fn set_models(&self, models: Vec<(String, usize)>) {
+        self.base.lock().unwrap().set_models(models);
     }
 
-    fn stream_response(&self, messages: Vec<Value>) -> Result<mpsc::Receiver<String>, ProviderError> {
-        let model = self.current_model.lock().unwrap().clone();
-        let (top_p, top_k, repetition_penalty, creativity) = self.base.lock().unwrap().get_parameters();
-        let client = self.base.lock().unwrap().get_client();
+    fn stream_response(&self, request: ChatRequest) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
+        let model = request.model.clone().unwrap_or_else(|| self.current_model.lock().unwrap().clone());
+        let profile = request.options.applied_to(self.base.lock().unwrap().get_profile());
+        let client = self.base.lock().unwrap().get_client()?;
+        let retry_policy = self.base.lock().unwrap().get_retry_policy();
         let api_key = self.base.lock().unwrap().get_api_key();
+        let base_url = self.base.lock().unwrap().get_base_url();
 
-        let json_body = json!({
+        let mut json_body = json!({
             "model": model,
-            "messages": messages,
-            "max_tokens": 4096,
             "stream": true,
-            "temperature": creativity,
-            "top_p": top_p,
-            "top_k": top_k,
         });
+        // Claude rejects requests without max_tokens.
+        parameters::apply(&mut json_body, self.parameter_specs(&model), &profile, Some(4096));
+        let thinking = enable_thinking(&mut json_body, &request);
+        json_body["messages"] = json!(to_claude_messages(&request.messages, thinking));
+
+        // Claude takes the system prompt as a top-level field, not as a message.
+        if let Some(system) = request.system {
+            json_body["system"] = json!(system);
+        }
+        // Claude has no JSON mode; forcing a call to a tool that takes the schema as input does the same.
+        // The forced call leaves no room for other tools, so they are not offered.
+        let tools: Vec<Value> = match &request.options.json_schema {
+            Some(schema) => {
+                json_body["tool_choice"] = json!({ "type": "tool", "name": JSON_TOOL });
+                vec![json!({ "name": JSON_TOOL, "description": "Gives your reply as JSON matching the schema.", "input_schema": schema })]
+            }
+            None => request.tools.iter()
+                .map(|tool| json!({ "name": tool.name, "description": tool.description, "input_schema": tool.input_schema }))
+                .collect(),
+        };
+        if !tools.is_empty() {
+            json_body["tools"] = json!(tools);
+        }
 
-        println!("Debug: Model parameters - top_p: {}, top_k: {}, repetition_penalty: {}, creativity: {}", top_p, top_k, repetition_penalty, creativity);
+        println!("Debug: Model parameters - {} profile for {}", profile.name, model);
 
         let (tx, rx) = mpsc::channel(1024);
         
-        tokio::task::spawn(async move {
-            let response = match client
-                .post("https://api.anthropic.com/v1/messages")
+        spawn_stream(tx.clone(), async move {
+            let request = client
+                .post(format!("{}/messages", base_url))
                 .header("x-api-key", api_key)
                 .header("anthropic-version", "2023-06-01")
                 .header("content-type", "application/json")
-                .json(&json_body)
-                .send()
-                .await
-            {
+                .json(&json_body);
+            let response = match send_with_retry(request, retry_policy, &tx).await {
                 Ok(resp) => resp,
                 Err(e) => {
-                    let _ = tx.send(format!("Error: {}", ProviderError::RequestError(e.to_string()))).await;
+                    let _ = tx.send(StreamEvent::Error(e)).await;
                     return;
                 }
             };
 
-            if !response.status().is_success() {
-                let error_body = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                let _ = tx.send(format!("Error: {}", ProviderError::ResponseError(error_body))).await;
-                return;
-            }
-
-            let mut stream = response.bytes_stream();
-            let mut buffer = String::new();
-            
-            while let Some(item) = stream.next().await {
-                match item {
-                    Ok(chunk) => {
-                        buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-                        while let Some(newline_pos) = buffer.find('\n') {
-                            let line = buffer[..newline_pos].trim().to_string();
-                            buffer = buffer[newline_pos + 1..].to_string();
-
-                            if line.starts_with("data: ") {
-                                let data = &line["data: ".len()..];
-                                if data == "[DONE]" {
-                                    return;
-                                }
-
-                                if let Ok(json) = serde_json::from_str::<Value>(data) {
-                                    if let Some(content) = json["delta"]["text"].as_str() {
-                                        if tx.send(content.to_string()).await.is_err() {
-                                            return;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        let _ = tx.send(format!("Error: {}", ProviderError::StreamError(e.to_string()))).await;
-                        return;
-                    }
-                }
-            }
+            let mut blocks = Blocks::default();
+            forward_sse(response, &tx, |event| {
+                serde_json::from_str::<Value>(&event.data)
+                    .map(|json| parse_event(&json, &mut blocks))
+                    .unwrap_or_default()
+            }).await;
         });
 
         Ok(rx)
@@ -118,12 +293,28 @@ impl ProviderTrait for Claude {
         *self.current_model.lock().unwrap() = model;
     }
 
-    fn update_profile(&self, profile: ProfileType) {
+    fn update_profile(&self, profile: Profile) {
         self.base.lock().unwrap().update_profile(profile);
     }
 
-    fn get_parameters(&self) -> (f32, u32, f32, f32) {
-        self.base.lock().unwrap().get_parameters()
+    fn supports_prefill(&self) -> bool {
+        true
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        true
+    }
+
+    fn parameter_specs(&self, model: &str) -> &'static [ParamSpec] {
+        if can_think(model) {
+            parameters::CLAUDE_THINKING
+        } else {
+            parameters::CLAUDE
+        }
     }
 }
 
@@ -131,4 +322,155 @@ impl fmt::Display for Claude {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Claude")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fixture_server::{collect, Fixture, FixtureServer, CLAUDE_JSON_STREAM, CLAUDE_STREAM, CLAUDE_THINKING_STREAM, CLAUDE_TOOL_STREAM};
+    use crate::providers::RequestOptions;
+    use crate::tools::{ToolCall, ToolSpec};
+
+    #[tokio::test]
+    async fn streams_a_recorded_response() {
+        let server = FixtureServer::start(vec![Fixture::sse(CLAUDE_STREAM)]).await;
+        let claude = Claude::new(ProviderConfig { api_key: "test-key".into(), base_url: server.base_url.clone(), ..Default::default() });
+
+        let request = ChatRequest::new(Some("Be brief.".into()), vec![json!({ "role": "user", "content": "Hi" })]);
+        let collected = collect(claude.stream_response(request).unwrap()).await;
+        assert_eq!(collected.text, "Grüße aus Zürich 🦀");
+        assert_eq!(collected.usage, Usage { input_tokens: Some(25), output_tokens: Some(12) });
+        assert_eq!(collected.stop_reason, Some(StopReason::EndTurn));
+        assert!(collected.done && collected.error.is_none());
+
+        let sent = &server.requests()[0];
+        assert_eq!((sent.method.as_str(), sent.path.as_str()), ("POST", "/messages"));
+        assert_eq!(sent.header("x-api-key"), Some("test-key"));
+        assert_eq!(sent.body["system"], "Be brief.");
+        assert_eq!(sent.body["messages"][0]["content"], "Hi");
+        assert_eq!(sent.body["max_tokens"], 4096);
+    }
+
+    #[tokio::test]
+    async fn sends_images_as_base64_blocks_ahead_of_the_text() {
+        let server = FixtureServer::start(vec![Fixture::sse(CLAUDE_STREAM)]).await;
+        let claude = Claude::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+
+        let message = json!({ "role": "user", "content": "What is this?", "images": [{ "media_type": "image/png", "data": "iVBORw0KGgo=" }] });
+        collect(claude.stream_response(ChatRequest::new(None, vec![message])).unwrap()).await;
+        assert_eq!(server.requests()[0].body["messages"][0]["content"], json!([
+            { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo=" } },
+            { "type": "text", "text": "What is this?" }
+        ]));
+    }
+
+    #[tokio::test]
+    async fn reports_an_error_event() {
+        let body = "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n";
+        let server = FixtureServer::start(vec![Fixture::sse(body)]).await;
+        let claude = Claude::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+
+        let collected = collect(claude.stream_response(ChatRequest::default()).unwrap()).await;
+        assert!(matches!(collected.error, Some(ProviderError::StreamError(message)) if message == "Overloaded"));
+        assert!(!collected.done);
+    }
+
+    #[tokio::test]
+    async fn offers_tools_and_streams_the_calls() {
+        let server = FixtureServer::start(vec![Fixture::sse(CLAUDE_TOOL_STREAM)]).await;
+        let claude = Claude::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+
+        let messages = vec![
+            json!({ "role": "user", "content": "What is in notes.txt?" }),
+            json!({ "role": "assistant", "content": "", "tool_calls": [{ "id": "toolu_1", "name": "read_file", "input": { "path": "notes.txt" } }] }),
+            json!({ "role": "tool", "results": [{ "id": "toolu_1", "content": "No such file", "is_error": true }] }),
+        ];
+        let tool = ToolSpec { name: "calculator".into(), description: "Does math".into(), input_schema: json!({ "type": "object" }) };
+        let collected = collect(claude.stream_response(ChatRequest::new(None, messages).with_tools(vec![tool])).unwrap()).await;
+        assert_eq!(collected.text, "Let me work that out.");
+        assert_eq!(collected.tool_calls, vec![
+            ToolCall { id: "toolu_01T1x1fJ34qAmk2tNTrN7Up6".into(), name: "calculator".into(), input: json!({ "expression": "1234 * 5678" }) },
+        ]);
+        assert_eq!(collected.stop_reason, Some(StopReason::ToolUse));
+
+        let body = &server.requests()[0].body;
+        assert_eq!(body["tools"], json!([{ "name": "calculator", "description": "Does math", "input_schema": { "type": "object" } }]));
+        assert_eq!(body["messages"][1], json!({
+            "role": "assistant",
+            "content": [{ "type": "tool_use", "id": "toolu_1", "name": "read_file", "input": { "path": "notes.txt" } }]
+        }));
+        assert_eq!(body["messages"][2], json!({
+            "role": "user",
+            "content": [{ "type": "tool_result", "tool_use_id": "toolu_1", "content": "No such file", "is_error": true }]
+        }));
+    }
+
+    #[tokio::test]
+    async fn answers_in_json_mode_through_a_forced_tool() {
+        let server = FixtureServer::start(vec![Fixture::sse(CLAUDE_JSON_STREAM)]).await;
+        let claude = Claude::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+
+        let schema = json!({ "type": "object", "properties": { "name": { "type": "string" }, "born": { "type": "integer" } } });
+        let options = RequestOptions { json_schema: Some(schema.clone()), ..RequestOptions::default() };
+        let tool = ToolSpec { name: "calculator".into(), description: "Does math".into(), input_schema: json!({ "type": "object" }) };
+        let request = ChatRequest::new(None, vec![json!({ "role": "user", "content": "Who wrote the first program?" })])
+            .with_options(options)
+            .with_tools(vec![tool]);
+        let collected = collect(claude.stream_response(request).unwrap()).await;
+        assert_eq!(collected.text, "{\"name\": \"Ada Lovelace\", \"born\": 1815}");
+        assert!(collected.tool_calls.is_empty());
+        assert_eq!(collected.stop_reason, Some(StopReason::EndTurn));
+
+        let body = &server.requests()[0].body;
+        assert_eq!(body["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(body["tools"][0]["input_schema"], schema);
+        assert_eq!(body["tool_choice"], json!({ "type": "tool", "name": "json_response" }));
+    }
+
+    #[tokio::test]
+    async fn thinks_within_the_profile_budget() {
+        let server = FixtureServer::start(vec![Fixture::sse(CLAUDE_THINKING_STREAM), Fixture::sse(CLAUDE_STREAM)]).await;
+        let claude = Claude::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+        claude.update_profile(Profile { thinking_budget: Some(8000), ..Profile::default() });
+
+        let messages = vec![
+            json!({ "role": "user", "content": "What is 27 * 453?" }),
+            json!({
+                "role": "assistant",
+                "content": "",
+                "reasoning": { "text": "Use the calculator.", "signature": "sig" },
+                "tool_calls": [{ "id": "toolu_1", "name": "calculator", "input": { "expression": "27 * 453" } }]
+            }),
+            json!({ "role": "tool", "results": [{ "id": "toolu_1", "content": "12231", "is_error": false }] }),
+        ];
+        let request = ChatRequest::new(None, messages).with_model("claude-3-7-sonnet-20250219".into());
+        let collected = collect(claude.stream_response(request).unwrap()).await;
+        assert_eq!(collected.reasoning, "27 * 453 = 27 * 450 + 27 * 3 = 12150 + 81 = 12231.");
+        assert_eq!(collected.signature.as_deref(), Some("EqQBCgIYAhIM1gbcDa9GJwZA2b3hGgxBdjrkzLoky3dl1pkiMOYds"));
+        assert_eq!(collected.text, "27 × 453 = 12231");
+
+        let body = &server.requests()[0].body;
+        assert_eq!(body["thinking"], json!({ "type": "enabled", "budget_tokens": 8000 }));
+        assert_eq!(body["max_tokens"], 12096);
+        assert!(body.get("temperature").is_none() && body.get("top_k").is_none() && body.get("top_p").is_none());
+        assert_eq!(body["messages"][1]["content"][0], json!({ "type": "thinking", "thinking": "Use the calculator.", "signature": "sig" }));
+
+        // Models from before extended thinking never get the field.
+        let request = ChatRequest::new(None, vec![json!({ "role": "user", "content": "Hi" })]).with_model("claude-3-5-sonnet-20240620".into());
+        collect(claude.stream_response(request).unwrap()).await;
+        assert!(server.requests()[1].body.get("thinking").is_none());
+    }
+
+    #[test]
+    fn leaves_thinking_off_when_claude_cannot_think() {
+        let body = || json!({ "thinking": { "budget_tokens": 2048 }, "temperature": 0.7 });
+        let prefilled = ChatRequest::new(None, vec![json!({ "role": "user", "content": "Hi" }), json!({ "role": "assistant", "content": "Hel" })]);
+        let options = RequestOptions { json_schema: Some(json!({ "type": "object" })), ..RequestOptions::default() };
+        let json_mode = ChatRequest::new(None, vec![json!({ "role": "user", "content": "Hi" })]).with_options(options);
+        for request in [prefilled, json_mode] {
+            let mut sent = body();
+            assert!(!enable_thinking(&mut sent, &request));
+            assert_eq!(sent, json!({ "temperature": 0.7 }));
+        }
+    }
+}
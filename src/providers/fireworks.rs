@@ -1,10 +1,28 @@
 use super::{ProviderError, ProviderTrait, BaseProvider};
+use super::registry::{Capabilities, ProviderConfig, ProviderInfo};
+use super::base_provider::{get_json, known_token_limit};
+use super::openai_format;
+use super::chat_request::ChatRequest;
+use super::stream_event::{spawn_stream, StreamEvent};
+use super::sse::forward_sse;
+use super::tool_calls::ToolCallBuffer;
+use super::retry::send_with_retry;
+use async_trait::async_trait;
 use serde_json::{json, Value};
 use tokio::sync::mpsc;
 use std::fmt;
 use std::sync::{Arc, Mutex};
-use crate::app::ProfileType;
-use futures_util::StreamExt;
+use super::parameters::{self, ParamSpec};
+use crate::app::Profile;
+
+pub const INFO: ProviderInfo = ProviderInfo {
+    id: "fireworks",
+    display_name: "Fireworks",
+    key_name: Some("fireworks_api_key"),
+    default_url: "https://api.fireworks.ai/inference/v1",
+    capabilities: Capabilities { lists_models: true },
+    build: |config| Box::new(Fireworks::new(config)),
+};
 
 pub struct Fireworks {
     base: Arc<Mutex<BaseProvider>>,
@@ -12,127 +30,133 @@ pub struct Fireworks {
 }
 
 impl Fireworks {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(config: ProviderConfig) -> Self {
         Self {
-            base: Arc::new(Mutex::new(BaseProvider::new(api_key))),
+            base: Arc::new(Mutex::new(BaseProvider::new(config))),
             current_model: Arc::new(Mutex::new("accounts/fireworks/models/llama-v3p1-70b-instruct".to_string())),
         }
     }
 }
 
+#[async_trait]
 impl ProviderTrait for Fireworks {
-    fn name(&self) -> &'static str {
-        "Fireworks"
+    fn name(&self) -> &str {
+        INFO.display_name
     }
 
-    fn models(&self) -> Vec<(&'static str, usize)> {
-        vec![
+    fn models(&self) -> Vec<(String, usize)> {
+        self.base.lock().unwrap().models_or(self.default_models())
+    }
+
+    fn default_models(&self) -> Vec<(String, usize)> {
+        [
             ("llama-v3p1-405b-instruct", 16384),
             ("llama-v3p1-70b-instruct", 16384),
             ("llama-v3p1-8b-instruct", 16384),
             ("llama-v3p2-11b-vision-instruct", 16384),
             ("llama-v3p2-3b-instruct", 16384)
         ]
+        .into_iter()
+        .map(|(name, tokens)| (name.to_string(), tokens))
+        .collect()
     }
 
-    fn stream_response(&self, messages: Vec<Value>) -> Result<mpsc::Receiver<String>, ProviderError> {
-        let model = self.current_model.lock().unwrap().clone();
-        let (top_p, top_k, repetition_penalty, creativity) = self.base.lock().unwrap().get_parameters();
-        let client = self.base.lock().unwrap().get_client();
+    async fn fetch_models(&self) -> Result<Vec<(String, usize)>, ProviderError> {
+        let (client, api_key, base_url) = {
+            let base = self.base.lock().unwrap();
+            (base.get_client()?, base.get_api_key(), base.get_base_url())
+        };
+        if api_key.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let json = get_json(client
+            .get(format!("{}/models", base_url))
+            .header("Authorization", format!("Bearer {}", api_key)))
+            .await?;
+
+        let defaults = self.default_models();
+        Ok(json["data"].as_array().into_iter().flatten()
+            .filter(|m| m["supports_chat"].as_bool() != Some(false))
+            .filter_map(|m| m["id"].as_str())
+            .map(|id| id.trim_start_matches("accounts/fireworks/models/"))
+            .map(|id| (id.to_string(), known_token_limit(&defaults, id, 16384)))
+            .collect())
+    }
+
+    fn set_models(&self, models: Vec<(String, usize)>) {
+        self.base.lock().unwrap().set_models(models);
+    }
+
+    fn stream_response(&self, request: ChatRequest) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
+        let model = request.model.as_deref().map(full_model_name).unwrap_or_else(|| self.current_model.lock().unwrap().clone());
+        let profile = request.options.applied_to(self.base.lock().unwrap().get_profile());
+        let client = self.base.lock().unwrap().get_client()?;
+        let retry_policy = self.base.lock().unwrap().get_retry_policy();
         let api_key = self.base.lock().unwrap().get_api_key();
+        let base_url = self.base.lock().unwrap().get_base_url();
 
-        let json_body = json!({
+        let mut json_body = json!({
             "model": model,
-            "max_tokens": 16384,
-            "top_p": top_p,
-            "top_k": top_k,
-            "presence_penalty": 0,
-            "frequency_penalty": repetition_penalty,
-            "temperature": creativity,
-            "messages": messages,
-            "stream": true
+            "messages": openai_format::messages(&request),
+            "stream": true,
+            "stream_options": { "include_usage": true }
         });
+        parameters::apply(&mut json_body, self.parameter_specs(&model), &profile, Some(16384));
+        openai_format::add_tools(&mut json_body, &request);
+        openai_format::add_response_format(&mut json_body, &request);
 
-        println!("Debug: Model parameters - top_p: {}, top_k: {}, frequency_penalty: {}, creativity: {}", top_p, top_k, repetition_penalty, creativity);
+        println!("Debug: Model parameters - {} profile for {}", profile.name, model);
         let (tx, rx) = mpsc::channel(1024);
         
-        tokio::task::spawn(async move {
-            let url = "https://api.fireworks.ai/inference/v1/chat/completions";
+        spawn_stream(tx.clone(), async move {
+            let url = format!("{}/chat/completions", base_url);
 
-            let response = match client
+            let request = client
                 .post(url)
                 .header("Authorization", format!("Bearer {}", api_key))
                 .header("Content-Type", "application/json")
-                .json(&json_body)
-                .send()
-                .await
-            {
+                .json(&json_body);
+            let response = match send_with_retry(request, retry_policy, &tx).await {
                 Ok(resp) => resp,
                 Err(e) => {
-                    let _ = tx.send(format!("Error: {}", ProviderError::RequestError(e.to_string()))).await;
+                    let _ = tx.send(StreamEvent::Error(e)).await;
                     return;
                 }
             };
 
-            if !response.status().is_success() {
-                let error_body = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                let _ = tx.send(format!("Error: {}", ProviderError::ResponseError(error_body))).await;
-                return;
-            }
-
-            let mut stream = response.bytes_stream();
-            let mut buffer = String::new();
-
-            while let Some(item) = stream.next().await {
-                match item {
-                    Ok(chunk) => {
-                        buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-                        while let Some(newline_pos) = buffer.find('\n') {
-                            let line = buffer[..newline_pos].trim().to_string();
-                            buffer = buffer[newline_pos + 1..].to_string();
-
-                            if line.starts_with("data: ") {
-                                let data = &line["data: ".len()..];
-                                if data == "[DONE]" {
-                                    return;
-                                }
-                                if let Ok(json) = serde_json::from_str::<Value>(data) {
-                                    if let Some(content) = json["choices"][0]["delta"]["content"].as_str() {
-                                        if tx.send(content.to_string()).await.is_err() {
-                                            return;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        let _ = tx.send(format!("Error: {}", ProviderError::StreamError(e.to_string()))).await;
-                        return;
-                    }
+            let mut tool_calls = ToolCallBuffer::default();
+            forward_sse(response, &tx, |event| {
+                if event.data == "[DONE]" {
+                    return vec![StreamEvent::Done];
                 }
-            }
+                serde_json::from_str::<Value>(&event.data)
+                    .map(|json| openai_format::parse_chunk(&json, &mut tool_calls))
+                    .unwrap_or_default()
+            }).await;
         });
 
         Ok(rx)
     }
 
     fn set_current_model(&self, model: String) {
-        let full_model_name = if !model.starts_with("accounts/fireworks/models/") {
-            format!("accounts/fireworks/models/{}", model)
-        } else {
-            model
-        };
-        *self.current_model.lock().unwrap() = full_model_name.clone();
+        *self.current_model.lock().unwrap() = full_model_name(&model);
     }
 
-    fn update_profile(&self, profile: ProfileType) {
+    fn update_profile(&self, profile: Profile) {
         self.base.lock().unwrap().update_profile(profile);
     }
 
-    fn get_parameters(&self) -> (f32, u32, f32, f32) {
-        self.base.lock().unwrap().get_parameters()
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        true
+    }
+
+    fn parameter_specs(&self, _model: &str) -> &'static [ParamSpec] {
+        parameters::FIREWORKS
     }
 }
 
@@ -140,4 +164,38 @@ impl fmt::Display for Fireworks {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Fireworks")
     }
-}
\ No newline at end of file
+}
+
+fn full_model_name(model: &str) -> String {
+    if !model.starts_with("accounts/fireworks/models/") {
+        format!("accounts/fireworks/models/{}", model)
+    } else {
+        model.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fixture_server::{collect, Fixture, FixtureServer, FIREWORKS_STREAM};
+    use crate::providers::{StopReason, Usage};
+
+    #[tokio::test]
+    async fn streams_a_recorded_response() {
+        let server = FixtureServer::start(vec![Fixture::sse(FIREWORKS_STREAM)]).await;
+        let fireworks = Fireworks::new(ProviderConfig { api_key: "test-key".into(), base_url: server.base_url.clone(), ..Default::default() });
+
+        let request = ChatRequest::new(None, vec![json!({ "role": "user", "content": "Hi" })])
+            .with_model("llama-v3p1-8b-instruct".into());
+        let collected = collect(fireworks.stream_response(request).unwrap()).await;
+        assert_eq!(collected.text, "Grüße aus Zürich 🦀");
+        assert_eq!(collected.usage, Usage { input_tokens: Some(25), output_tokens: Some(12) });
+        assert_eq!(collected.stop_reason, Some(StopReason::EndTurn));
+        assert!(collected.done && collected.error.is_none());
+
+        let sent = &server.requests()[0];
+        assert_eq!(sent.header("authorization"), Some("Bearer test-key"));
+        assert_eq!(sent.body["model"], "accounts/fireworks/models/llama-v3p1-8b-instruct");
+        assert_eq!(sent.body["max_tokens"], 16384);
+    }
+}
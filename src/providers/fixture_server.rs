@@ -0,0 +1,231 @@
+//! A local HTTP server that stands in for a provider's API in tests. It
+//! answers each request with the next queued fixture, streamed in small
+//! chunks so that lines and multi-byte characters get split the way they
+//! do over a real network, and records what it was sent.
+
+use super::stream_event::{StopReason, StreamEvent, Usage};
+use super::ProviderError;
+use crate::tools::ToolCall;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+pub const CLAUDE_STREAM: &str = include_str!("fixtures/claude_stream.sse");
+pub const OPENAI_STREAM: &str = include_str!("fixtures/openai_stream.sse");
+pub const FIREWORKS_STREAM: &str = include_str!("fixtures/fireworks_stream.sse");
+pub const CLAUDE_TOOL_STREAM: &str = include_str!("fixtures/claude_tool_use.sse");
+pub const OPENAI_TOOL_STREAM: &str = include_str!("fixtures/openai_tool_calls.sse");
+pub const CLAUDE_JSON_STREAM: &str = include_str!("fixtures/claude_json_mode.sse");
+pub const CLAUDE_THINKING_STREAM: &str = include_str!("fixtures/claude_thinking.sse");
+pub const GEMINI_STREAM: &str = include_str!("fixtures/gemini_stream.sse");
+pub const OLLAMA_STREAM: &str = include_str!("fixtures/ollama_stream.ndjson");
+
+/// A canned HTTP response.
+#[derive(Clone)]
+pub struct Fixture {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// Bytes per chunk of the chunked response body.
+    pub chunk_size: usize,
+    /// Wait before the response headers are sent.
+    pub delay: Duration,
+}
+
+impl Fixture {
+    pub fn sse(body: &str) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "text/event-stream".to_string())],
+            body: body.to_string(),
+            chunk_size: 7,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Newline-delimited JSON, as Ollama streams it.
+    pub fn ndjson(body: &str) -> Self {
+        Self {
+            headers: vec![("Content-Type".to_string(), "application/x-ndjson".to_string())],
+            ..Self::sse(body)
+        }
+    }
+
+    pub fn status(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string(),
+            chunk_size: 1024,
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// A request as the server received it.
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased.
+    pub headers: Vec<(String, String)>,
+    /// The JSON body, or `Value::Null` if there was none.
+    pub body: Value,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+pub struct FixtureServer {
+    pub base_url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl FixtureServer {
+    /// Starts serving `fixtures` in order, one per request. Requests beyond
+    /// the last fixture get a 500.
+    pub async fn start(fixtures: Vec<Fixture>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind fixture server");
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let fixtures = Arc::new(Mutex::new(VecDeque::from(fixtures)));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let fixtures = Arc::clone(&fixtures);
+                let recorded = Arc::clone(&recorded);
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, fixtures, recorded).await {
+                        eprintln!("Fixture server connection failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        Self { base_url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn serve(mut stream: TcpStream, fixtures: Arc<Mutex<VecDeque<Fixture>>>, recorded: Arc<Mutex<Vec<RecordedRequest>>>) -> std::io::Result<()> {
+    let request = read_request(&mut stream).await?;
+    recorded.lock().unwrap().push(request);
+    let fixture = fixtures.lock().unwrap().pop_front()
+        .unwrap_or_else(|| Fixture::status(500, r#"{"error":{"message":"no fixture left"}}"#));
+
+    tokio::time::sleep(fixture.delay).await;
+    let mut head = format!("HTTP/1.1 {} Fixture\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n", fixture.status);
+    for (name, value) in &fixture.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+
+    for chunk in fixture.body.as_bytes().chunks(fixture.chunk_size.max(1)) {
+        stream.write_all(format!("{:x}\r\n", chunk.len()).as_bytes()).await?;
+        stream.write_all(chunk).await?;
+        stream.write_all(b"\r\n").await?;
+        stream.flush().await?;
+    }
+    stream.write_all(b"0\r\n\r\n").await?;
+    stream.shutdown().await
+}
+
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<RecordedRequest> {
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 4096];
+    let head_end = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        data.extend_from_slice(&buffer[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers.iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = data[head_end + 4..].to_vec();
+    while body.len() < content_length {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&buffer[..read]);
+    }
+
+    Ok(RecordedRequest {
+        method,
+        path,
+        headers,
+        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+    })
+}
+
+/// Everything a response stream produced, gathered up for assertions.
+#[derive(Debug, Default)]
+pub struct Collected {
+    pub text: String,
+    pub reasoning: String,
+    pub signature: Option<String>,
+    pub tool_calls: Vec<ToolCall>,
+    pub usage: Usage,
+    pub stop_reason: Option<StopReason>,
+    pub error: Option<ProviderError>,
+    pub retries: u32,
+    pub done: bool,
+}
+
+pub async fn collect(mut rx: mpsc::Receiver<StreamEvent>) -> Collected {
+    let mut collected = Collected::default();
+    while let Some(event) = rx.recv().await {
+        match event {
+            StreamEvent::TextDelta(text) => collected.text.push_str(&text),
+            StreamEvent::ReasoningDelta(text) => collected.reasoning.push_str(&text),
+            StreamEvent::ReasoningSignature(signature) => collected.signature = Some(signature),
+            StreamEvent::ToolCall(call) => collected.tool_calls.push(call),
+            StreamEvent::Usage(usage) => collected.usage.merge(usage),
+            StreamEvent::StopReason(reason) => collected.stop_reason = Some(reason),
+            StreamEvent::Retrying { .. } => collected.retries += 1,
+            StreamEvent::Error(e) => collected.error = Some(e),
+            StreamEvent::Done => collected.done = true,
+        }
+    }
+    collected
+}
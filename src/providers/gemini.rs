@@ -0,0 +1,230 @@
+use super::{ProviderError, ProviderTrait, BaseProvider};
+use super::registry::{Capabilities, ProviderConfig, ProviderInfo};
+use super::base_provider::get_json;
+use super::chat_request::ChatRequest;
+use super::stream_event::{spawn_stream, StopReason, StreamEvent, Usage};
+use super::sse::forward_sse;
+use super::retry::send_with_retry;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use super::parameters::{self, ParamSpec};
+use crate::app::Profile;
+
+pub const INFO: ProviderInfo = ProviderInfo {
+    id: "gemini",
+    display_name: "Gemini",
+    key_name: Some("gemini_api_key"),
+    default_url: "https://generativelanguage.googleapis.com/v1beta",
+    capabilities: Capabilities { lists_models: true },
+    build: |config| Box::new(Gemini::new(config)),
+};
+
+pub struct Gemini {
+    base: Arc<Mutex<BaseProvider>>,
+    current_model: Arc<Mutex<String>>,
+}
+
+impl Gemini {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self {
+            base: Arc::new(Mutex::new(BaseProvider::new(config))),
+            current_model: Arc::new(Mutex::new("gemini-1.5-pro".to_string())),
+        }
+    }
+}
+
+/// Maps our `{"role", "content"}` messages to Gemini's `contents`/`parts`,
+/// where the assistant side is called `model` and images are `inline_data`.
+fn to_gemini_contents(messages: &[Value]) -> Vec<Value> {
+    messages.iter().map(|m| {
+        let role = if m["role"] == "assistant" { "model" } else { "user" };
+        let images: Vec<Value> = m["images"].as_array().into_iter().flatten()
+            .map(|image| json!({ "inline_data": { "mime_type": image["media_type"], "data": image["data"] } }))
+            .collect();
+        // Gemini rejects empty text parts, which a message with only images would have.
+        let text = m["content"].as_str().unwrap_or_default();
+        let text = (!text.is_empty() || images.is_empty()).then(|| json!({ "text": text }));
+        json!({
+            "role": role,
+            "parts": images.into_iter().chain(text).collect::<Vec<_>>()
+        })
+    }).collect()
+}
+
+/// Turns one streamed `GenerateContentResponse` into our events.
+fn parse_chunk(json: &Value) -> Vec<StreamEvent> {
+    if let Some(error) = json.get("error") {
+        let message = error["message"].as_str().unwrap_or("Unknown error").to_string();
+        return vec![StreamEvent::Error(ProviderError::StreamError(message))];
+    }
+
+    let candidate = &json["candidates"][0];
+    let mut events: Vec<StreamEvent> = candidate["content"]["parts"].as_array().into_iter().flatten()
+        .filter_map(|part| part["text"].as_str())
+        .map(|text| StreamEvent::TextDelta(text.to_string()))
+        .collect();
+    if let Some(reason) = candidate["finishReason"].as_str() {
+        events.push(StreamEvent::StopReason(StopReason::from_api(reason)));
+    }
+    if let Some(usage) = json.get("usageMetadata") {
+        events.push(StreamEvent::Usage(Usage {
+            input_tokens: usage["promptTokenCount"].as_u64(),
+            output_tokens: usage["candidatesTokenCount"].as_u64(),
+        }));
+    }
+    events
+}
+
+#[async_trait]
+impl ProviderTrait for Gemini {
+    fn name(&self) -> &str {
+        INFO.display_name
+    }
+
+    fn models(&self) -> Vec<(String, usize)> {
+        self.base.lock().unwrap().models_or(self.default_models())
+    }
+
+    fn default_models(&self) -> Vec<(String, usize)> {
+        [
+            ("gemini-1.5-pro", 8192),
+            ("gemini-1.5-flash", 8192),
+            ("gemini-1.5-flash-8b", 8192),
+        ]
+        .into_iter()
+        .map(|(name, tokens)| (name.to_string(), tokens))
+        .collect()
+    }
+
+    async fn fetch_models(&self) -> Result<Vec<(String, usize)>, ProviderError> {
+        let (client, api_key, base_url) = {
+            let base = self.base.lock().unwrap();
+            (base.get_client()?, base.get_api_key(), base.get_base_url())
+        };
+        if api_key.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let json = get_json(client
+            .get(format!("{}/models?pageSize=1000", base_url))
+            .header("x-goog-api-key", api_key))
+            .await?;
+
+        Ok(json["models"].as_array().into_iter().flatten()
+            .filter(|m| m["supportedGenerationMethods"].as_array().is_some_and(|methods| methods.iter().any(|method| method == "generateContent")))
+            .filter_map(|m| {
+                let name = m["name"].as_str()?.trim_start_matches("models/");
+                Some((name.to_string(), m["outputTokenLimit"].as_u64().unwrap_or(8192) as usize))
+            })
+            .collect())
+    }
+
+    fn set_models(&self, models: Vec<(String, usize)>) {
+        self.base.lock().unwrap().set_models(models);
+    }
+
+    fn stream_response(&self, request: ChatRequest) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
+        let model = request.model.clone().unwrap_or_else(|| self.current_model.lock().unwrap().clone());
+        let profile = request.options.applied_to(self.base.lock().unwrap().get_profile());
+        let client = self.base.lock().unwrap().get_client()?;
+        let retry_policy = self.base.lock().unwrap().get_retry_policy();
+        let api_key = self.base.lock().unwrap().get_api_key();
+        let base_url = self.base.lock().unwrap().get_base_url();
+
+        let mut json_body = json!({
+            "contents": to_gemini_contents(&request.messages),
+        });
+        parameters::apply(&mut json_body, self.parameter_specs(&model), &profile, Some(8192));
+
+        if let Some(system) = request.system {
+            json_body["systemInstruction"] = json!({ "parts": [{ "text": system }] });
+        }
+
+        println!("Debug: Model parameters - {} profile for {}", profile.name, model);
+
+        let (tx, rx) = mpsc::channel(1024);
+
+        spawn_stream(tx.clone(), async move {
+            let url = format!("{}/models/{}:streamGenerateContent?alt=sse", base_url, model);
+
+            let request = client
+                .post(url)
+                .header("x-goog-api-key", api_key)
+                .header("Content-Type", "application/json")
+                .json(&json_body);
+            let response = match send_with_retry(request, retry_policy, &tx).await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    let _ = tx.send(StreamEvent::Error(e)).await;
+                    return;
+                }
+            };
+
+            forward_sse(response, &tx, |event| {
+                serde_json::from_str::<Value>(&event.data)
+                    .map(|json| parse_chunk(&json))
+                    .unwrap_or_default()
+            }).await;
+        });
+
+        Ok(rx)
+    }
+
+    fn set_current_model(&self, model: String) {
+        *self.current_model.lock().unwrap() = model;
+    }
+
+    fn update_profile(&self, profile: Profile) {
+        self.base.lock().unwrap().update_profile(profile);
+    }
+
+    fn parameter_specs(&self, _model: &str) -> &'static [ParamSpec] {
+        parameters::GEMINI
+    }
+}
+
+impl fmt::Display for Gemini {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Gemini")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fixture_server::{collect, Fixture, FixtureServer, GEMINI_STREAM};
+
+    #[tokio::test]
+    async fn streams_a_recorded_response() {
+        let server = FixtureServer::start(vec![Fixture::sse(GEMINI_STREAM)]).await;
+        let gemini = Gemini::new(ProviderConfig { api_key: "test-key".into(), base_url: server.base_url.clone(), ..Default::default() });
+
+        let request = ChatRequest::new(Some("Be brief.".into()), vec![json!({ "role": "user", "content": "Hi" })])
+            .with_model("gemini-1.5-flash".into());
+        let collected = collect(gemini.stream_response(request).unwrap()).await;
+        assert_eq!(collected.text, "Grüße aus Zürich 🦀");
+        assert_eq!(collected.usage, Usage { input_tokens: Some(9), output_tokens: Some(8) });
+        assert_eq!(collected.stop_reason, Some(StopReason::MaxTokens));
+        assert!(collected.done && collected.error.is_none());
+
+        let sent = &server.requests()[0];
+        assert_eq!(sent.path, "/models/gemini-1.5-flash:streamGenerateContent?alt=sse");
+        assert_eq!(sent.header("x-goog-api-key"), Some("test-key"));
+        assert_eq!(sent.body["systemInstruction"]["parts"][0]["text"], "Be brief.");
+    }
+
+    #[test]
+    fn sends_images_without_an_empty_text_part() {
+        let messages = vec![
+            json!({ "role": "user", "content": "", "images": [{ "media_type": "image/png", "data": "iVBORw0KGgo=" }] }),
+            json!({ "role": "assistant", "content": "A cat." }),
+        ];
+        assert_eq!(to_gemini_contents(&messages), vec![
+            json!({ "role": "user", "parts": [{ "inline_data": { "mime_type": "image/png", "data": "iVBORw0KGgo=" } }] }),
+            json!({ "role": "model", "parts": [{ "text": "A cat." }] }),
+        ]);
+    }
+}
@@ -1,10 +1,31 @@
 use super::{ProviderError, ProviderTrait, BaseProvider};
+use super::registry::{Capabilities, ProviderConfig, ProviderInfo};
+use super::base_provider::{get_json, known_token_limit};
+use super::openai_format;
+use super::chat_request::ChatRequest;
+use super::stream_event::{spawn_stream, StreamEvent};
+use super::sse::forward_sse;
+use super::tool_calls::ToolCallBuffer;
+use super::retry::send_with_retry;
+use async_trait::async_trait;
 use serde_json::{json, Value};
 use tokio::sync::mpsc;
 use std::fmt;
 use std::sync::{Arc, Mutex};
-use crate::app::ProfileType;
-use futures_util::StreamExt;
+use super::parameters::{self, ParamSpec};
+use crate::app::Profile;
+
+pub const INFO: ProviderInfo = ProviderInfo {
+    id: "gpt",
+    display_name: "GPT",
+    key_name: Some("gpt_api_key"),
+    default_url: "https://api.openai.com/v1",
+    capabilities: Capabilities { lists_models: true },
+    build: |config| Box::new(GPT::new(config)),
+};
+
+// Name prefixes of the reasoning models, which take different parameters.
+const REASONING_MODELS: &[&str] = &["o1", "o3", "o4", "gpt-5"];
 
 pub struct GPT {
     base: Arc<Mutex<BaseProvider>>,
@@ -12,21 +33,30 @@ pub struct GPT {
 }
 
 impl GPT {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(config: ProviderConfig) -> Self {
         Self {
-            base: Arc::new(Mutex::new(BaseProvider::new(api_key))),
+            base: Arc::new(Mutex::new(BaseProvider::new(config))),
             current_model: Arc::new(Mutex::new("gpt-3.5-turbo".to_string())),
         }
     }
 }
 
+#[async_trait]
 impl ProviderTrait for GPT {
-    fn name(&self) -> &'static str {
-        "GPT"
+    fn name(&self) -> &str {
+        INFO.display_name
     }
 
-    fn models(&self) -> Vec<(&'static str, usize)> {
-        vec![
+    fn models(&self) -> Vec<(String, usize)> {
+        self.base.lock().unwrap().models_or(self.default_models())
+    }
+
+    fn default_models(&self) -> Vec<(String, usize)> {
+        [
+            ("gpt-5", 128000),
+            ("gpt-5-mini", 128000),
+            ("gpt-4.1", 32768),
+            ("gpt-4.1-mini", 32768),
             ("gpt-4o", 4096),
             ("gpt-4", 4096),
             ("gpt-4o-mini", 16384),
@@ -34,84 +64,90 @@ impl ProviderTrait for GPT {
             ("chatgpt-4o-latest", 16384),
             ("o1-preview", 32768),
             ("o1-mini", 65536),
+            ("o3", 100000),
+            ("o3-mini", 100000),
+            ("o4-mini", 100000),
         ]
+        .into_iter()
+        .map(|(name, tokens)| (name.to_string(), tokens))
+        .collect()
+    }
+
+    async fn fetch_models(&self) -> Result<Vec<(String, usize)>, ProviderError> {
+        let (client, api_key, base_url) = {
+            let base = self.base.lock().unwrap();
+            (base.get_client()?, base.get_api_key(), base.get_base_url())
+        };
+        if api_key.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let json = get_json(client
+            .get(format!("{}/models", base_url))
+            .header("Authorization", format!("Bearer {}", api_key)))
+            .await?;
+
+        // The listing also contains embedding, audio and image models.
+        let defaults = self.default_models();
+        let mut models: Vec<(String, usize)> = json["data"].as_array().into_iter().flatten()
+            .filter_map(|m| m["id"].as_str())
+            .filter(|id| ["gpt-", "chatgpt-"].iter().chain(REASONING_MODELS).any(|prefix| id.starts_with(prefix)))
+            .filter(|id| !["audio", "realtime", "instruct", "transcribe", "tts"].iter().any(|kind| id.contains(kind)))
+            .map(|id| (id.to_string(), known_token_limit(&defaults, id, 4096)))
+            .collect();
+        models.sort();
+        Ok(models)
     }
 
+    fn set_models(&self, models: Vec<(String, usize)>) {
+        self.base.lock().unwrap().set_models(models);
+    }
 
-    fn stream_response(&self, messages: Vec<Value>) -> Result<mpsc::Receiver<String>, ProviderError> {
-        let model = self.current_model.lock().unwrap().clone();
-        let (top_p, top_k, repetition_penalty, creativity) = self.base.lock().unwrap().get_parameters();
-        let client = self.base.lock().unwrap().get_client();
+    fn stream_response(&self, request: ChatRequest) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
+        let model = request.model.clone().unwrap_or_else(|| self.current_model.lock().unwrap().clone());
+        let profile = request.options.applied_to(self.base.lock().unwrap().get_profile());
+        let client = self.base.lock().unwrap().get_client()?;
+        let retry_policy = self.base.lock().unwrap().get_retry_policy();
         let api_key = self.base.lock().unwrap().get_api_key();
+        let base_url = self.base.lock().unwrap().get_base_url();
 
-        let json_body = json!({
+        let mut json_body = json!({
             "model": model,
-            "messages": messages,
+            "messages": openai_format::messages(&request),
             "stream": true,
-            "temperature": creativity,
-            "top_p": top_p,
-            "frequency_penalty": repetition_penalty,
+            "stream_options": { "include_usage": true },
         });
+        parameters::apply(&mut json_body, self.parameter_specs(&model), &profile, None);
+        openai_format::add_tools(&mut json_body, &request);
+        openai_format::add_response_format(&mut json_body, &request);
 
-        println!("Debug: Model parameters - top_p: {}, top_k: {}, repetition_penalty: {}, creativity: {}", top_p, top_k, repetition_penalty, creativity);
+        println!("Debug: Model parameters - {} profile for {}", profile.name, model);
         
         let (tx, rx) = mpsc::channel(1024);
         
-        tokio::task::spawn(async move {
-            let response = match client
-                .post("https://api.openai.com/v1/chat/completions")
+        spawn_stream(tx.clone(), async move {
+            let request = client
+                .post(format!("{}/chat/completions", base_url))
                 .header("Authorization", format!("Bearer {}", api_key))
                 .header("Content-Type", "application/json")
-                .json(&json_body)
-                .send()
-                .await
-            {
+                .json(&json_body);
+            let response = match send_with_retry(request, retry_policy, &tx).await {
                 Ok(resp) => resp,
                 Err(e) => {
-                    let _ = tx.send(format!("Error: {}", ProviderError::RequestError(e.to_string()))).await;
+                    let _ = tx.send(StreamEvent::Error(e)).await;
                     return;
                 }
             };
 
-            if !response.status().is_success() {
-                let error_body = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                let _ = tx.send(format!("Error: {}", ProviderError::ResponseError(error_body))).await;
-                return;
-            }
-
-            let mut stream = response.bytes_stream();
-            let mut buffer = String::new();
-
-            while let Some(item) = stream.next().await {
-                match item {
-                    Ok(chunk) => {
-                        buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-                        while let Some(newline_pos) = buffer.find('\n') {
-                            let line = buffer[..newline_pos].trim().to_string();
-                            buffer = buffer[newline_pos + 1..].to_string();
-
-                            if line.starts_with("data: ") {
-                                let data = &line["data: ".len()..];
-                                if data == "[DONE]" {
-                                    return;
-                                }
-                                if let Ok(json) = serde_json::from_str::<Value>(data) {
-                                    if let Some(content) = json["choices"][0]["delta"]["content"].as_str() {
-                                        if tx.send(content.to_string()).await.is_err() {
-                                            return;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        let _ = tx.send(format!("Error: {}", ProviderError::StreamError(e.to_string()))).await;
-                        return;
-                    }
+            let mut tool_calls = ToolCallBuffer::default();
+            forward_sse(response, &tx, |event| {
+                if event.data == "[DONE]" {
+                    return vec![StreamEvent::Done];
                 }
-            }
+                serde_json::from_str::<Value>(&event.data)
+                    .map(|json| openai_format::parse_chunk(&json, &mut tool_calls))
+                    .unwrap_or_default()
+            }).await;
         });
 
         Ok(rx)
@@ -122,12 +158,24 @@ impl ProviderTrait for GPT {
         *self.current_model.lock().unwrap() = model;
     }
 
-    fn update_profile(&self, profile: ProfileType) {
+    fn update_profile(&self, profile: Profile) {
         self.base.lock().unwrap().update_profile(profile);
     }
 
-    fn get_parameters(&self) -> (f32, u32, f32, f32) {
-        self.base.lock().unwrap().get_parameters()
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        true
+    }
+
+    fn parameter_specs(&self, model: &str) -> &'static [ParamSpec] {
+        if REASONING_MODELS.iter().any(|prefix| model.starts_with(prefix)) {
+            parameters::OPENAI_REASONING
+        } else {
+            parameters::OPENAI
+        }
     }
 }
 
@@ -135,4 +183,117 @@ impl fmt::Display for GPT {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "GPT")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fixture_server::{collect, Fixture, FixtureServer, OPENAI_STREAM, OPENAI_TOOL_STREAM};
+    use crate::tools::{ToolCall, ToolSpec};
+    use crate::providers::{RequestOptions, StopReason, Usage};
+
+    #[tokio::test]
+    async fn streams_a_recorded_response() {
+        let server = FixtureServer::start(vec![Fixture::sse(OPENAI_STREAM)]).await;
+        let gpt = GPT::new(ProviderConfig { api_key: "test-key".into(), base_url: server.base_url.clone(), ..Default::default() });
+
+        let request = ChatRequest::new(Some("Be brief.".into()), vec![json!({ "role": "user", "content": "Hi" })])
+            .with_model("gpt-4o".into());
+        let collected = collect(gpt.stream_response(request).unwrap()).await;
+        assert_eq!(collected.text, "Grüße aus Zürich 🦀");
+        assert_eq!(collected.usage, Usage { input_tokens: Some(25), output_tokens: Some(12) });
+        assert_eq!(collected.stop_reason, Some(StopReason::MaxTokens));
+        assert!(collected.done && collected.error.is_none());
+
+        let sent = &server.requests()[0];
+        assert_eq!(sent.path, "/chat/completions");
+        assert_eq!(sent.header("authorization"), Some("Bearer test-key"));
+        assert_eq!(sent.body["model"], "gpt-4o");
+        assert_eq!(sent.body["messages"][0], json!({ "role": "system", "content": "Be brief." }));
+        assert_eq!(sent.body["stream_options"]["include_usage"], true);
+    }
+
+    #[tokio::test]
+    async fn sends_images_as_image_url_parts() {
+        let server = FixtureServer::start(vec![Fixture::sse(OPENAI_STREAM)]).await;
+        let gpt = GPT::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+
+        let messages = vec![
+            json!({ "role": "user", "content": "What is this?", "images": [{ "media_type": "image/jpeg", "data": "/9j/4AAQ" }] }),
+            json!({ "role": "assistant", "content": "A cat." }),
+        ];
+        collect(gpt.stream_response(ChatRequest::new(None, messages)).unwrap()).await;
+        let sent = &server.requests()[0].body["messages"];
+        assert_eq!(sent[0]["content"], json!([
+            { "type": "text", "text": "What is this?" },
+            { "type": "image_url", "image_url": { "url": "data:image/jpeg;base64,/9j/4AAQ" } }
+        ]));
+        assert!(sent[0].get("images").is_none());
+        assert_eq!(sent[1], json!({ "role": "assistant", "content": "A cat." }));
+    }
+
+    #[tokio::test]
+    async fn reasoning_models_get_only_their_parameters() {
+        let server = FixtureServer::start(vec![Fixture::sse(OPENAI_STREAM)]).await;
+        let gpt = GPT::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+        gpt.update_profile(Profile { max_tokens: Some(1000), ..Profile::default() });
+
+        collect(gpt.stream_response(ChatRequest::default().with_model("o1-mini".into())).unwrap()).await;
+        let body = &server.requests()[0].body;
+        assert_eq!(body["max_completion_tokens"], 1000);
+        assert!(body.get("temperature").is_none() && body.get("max_tokens").is_none());
+    }
+
+    #[test]
+    fn knows_each_reasoning_model_family() {
+        let gpt = GPT::new(ProviderConfig::default());
+        for model in ["o1-mini", "o3-mini", "o4-mini", "gpt-5-mini"] {
+            assert_eq!(gpt.parameter_specs(model).len(), parameters::OPENAI_REASONING.len(), "{}", model);
+        }
+        assert_eq!(gpt.parameter_specs("gpt-4o").len(), parameters::OPENAI.len());
+    }
+
+    #[tokio::test]
+    async fn sends_the_json_schema_as_response_format() {
+        let server = FixtureServer::start(vec![Fixture::sse(OPENAI_STREAM)]).await;
+        let gpt = GPT::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+
+        let schema = json!({ "type": "object", "properties": { "name": { "type": "string" } } });
+        let options = RequestOptions { json_schema: Some(schema.clone()), ..RequestOptions::default() };
+        collect(gpt.stream_response(ChatRequest::default().with_options(options)).unwrap()).await;
+        assert_eq!(server.requests()[0].body["response_format"], json!({
+            "type": "json_schema",
+            "json_schema": { "name": "response", "schema": schema, "strict": false }
+        }));
+    }
+
+    #[tokio::test]
+    async fn offers_tools_and_streams_the_calls() {
+        let server = FixtureServer::start(vec![Fixture::sse(OPENAI_TOOL_STREAM)]).await;
+        let gpt = GPT::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+
+        let messages = vec![
+            json!({ "role": "user", "content": "How many files?" }),
+            json!({ "role": "assistant", "content": "", "tool_calls": [{ "id": "call_1", "name": "list_directory", "input": {} }] }),
+            json!({ "role": "tool", "results": [{ "id": "call_1", "content": "a.txt", "is_error": false }] }),
+        ];
+        let tool = ToolSpec { name: "calculator".into(), description: "Does math".into(), input_schema: json!({ "type": "object" }) };
+        let collected = collect(gpt.stream_response(ChatRequest::new(None, messages).with_tools(vec![tool])).unwrap()).await;
+        assert_eq!(collected.tool_calls, vec![
+            ToolCall { id: "call_Yx3bQm8uRk".into(), name: "list_directory".into(), input: json!({ "path": "src" }) },
+            ToolCall { id: "call_Pw2vLr5nTz".into(), name: "calculator".into(), input: json!({ "expression": "2^10" }) },
+        ]);
+        assert_eq!(collected.stop_reason, Some(StopReason::ToolUse));
+
+        let body = &server.requests()[0].body;
+        assert_eq!(body["tools"], json!([
+            { "type": "function", "function": { "name": "calculator", "description": "Does math", "parameters": { "type": "object" } } }
+        ]));
+        assert_eq!(body["messages"][1], json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{ "id": "call_1", "type": "function", "function": { "name": "list_directory", "arguments": "{}" } }]
+        }));
+        assert_eq!(body["messages"][2], json!({ "role": "tool", "tool_call_id": "call_1", "content": "a.txt" }));
+    }
+}
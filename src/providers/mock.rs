@@ -0,0 +1,180 @@
+use super::{ProviderError, ProviderTrait};
+use super::chat_request::ChatRequest;
+use super::stream_event::{forward, spawn_stream, StopReason, StreamEvent, Usage};
+use super::parameters::{self, ParamSpec};
+use crate::app::Profile;
+use crate::tools::ToolCall;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// One scripted part of a mock response, played in order.
+#[derive(Debug, Clone)]
+pub enum MockStep {
+    Text(String),
+    Reasoning(String),
+    ToolCall(ToolCall),
+    Delay(Duration),
+    Usage(Usage),
+    Stop(StopReason),
+    /// Ends the response with this error instead of `Done`.
+    Error(ProviderError),
+}
+
+/// A provider that answers each request with the next scripted response
+/// instead of calling an API, and keeps the requests it got for inspection.
+pub struct Mock {
+    name: String,
+    script: Arc<Mutex<VecDeque<Vec<MockStep>>>>,
+    requests: Arc<Mutex<Vec<ChatRequest>>>,
+    profile: Arc<Mutex<Profile>>,
+}
+
+impl Mock {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            script: Arc::new(Mutex::new(VecDeque::new())),
+            requests: Arc::new(Mutex::new(Vec::new())),
+            profile: Arc::new(Mutex::new(Profile::default())),
+        }
+    }
+
+    /// Queues the response to the next request that has none yet.
+    pub fn reply(self, steps: Vec<MockStep>) -> Self {
+        self.script.lock().unwrap().push_back(steps);
+        self
+    }
+
+    /// Queues a response that streams `text` in one piece and ends the turn.
+    pub fn reply_text(self, text: &str) -> Self {
+        self.reply(vec![MockStep::Text(text.to_string()), MockStep::Stop(StopReason::EndTurn)])
+    }
+
+    pub fn requests(&self) -> Vec<ChatRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl ProviderTrait for Mock {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn models(&self) -> Vec<(String, usize)> {
+        self.default_models()
+    }
+
+    fn default_models(&self) -> Vec<(String, usize)> {
+        vec![("mock-model".to_string(), 8192)]
+    }
+
+    fn set_models(&self, _models: Vec<(String, usize)>) {
+        // The mock serves a fixed model list
+    }
+
+    fn stream_response(&self, request: ChatRequest) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
+        self.requests.lock().unwrap().push(request);
+        let steps = self.script.lock().unwrap().pop_front()
+            .ok_or_else(|| ProviderError::RequestError("The mock provider has no scripted response left".to_string()))?;
+
+        let (tx, rx) = mpsc::channel(1024);
+        spawn_stream(tx.clone(), async move {
+            for step in steps {
+                let event = match step {
+                    MockStep::Text(text) => StreamEvent::TextDelta(text),
+                    MockStep::Reasoning(text) => StreamEvent::ReasoningDelta(text),
+                    MockStep::ToolCall(call) => StreamEvent::ToolCall(call),
+                    MockStep::Delay(delay) => {
+                        tokio::time::sleep(delay).await;
+                        continue;
+                    }
+                    MockStep::Usage(usage) => StreamEvent::Usage(usage),
+                    MockStep::Stop(reason) => StreamEvent::StopReason(reason),
+                    MockStep::Error(e) => StreamEvent::Error(e),
+                };
+                if !forward(&tx, vec![event]).await {
+                    return;
+                }
+            }
+            let _ = tx.send(StreamEvent::Done).await;
+        });
+        Ok(rx)
+    }
+
+    fn set_current_model(&self, _model: String) {
+        // The mock has a single model
+    }
+
+    fn update_profile(&self, profile: Profile) {
+        *self.profile.lock().unwrap() = profile;
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn parameter_specs(&self, _model: &str) -> &'static [ParamSpec] {
+        parameters::OPENAI
+    }
+}
+
+impl fmt::Display for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fixture_server::collect;
+
+    #[tokio::test]
+    async fn plays_scripted_responses_in_order() {
+        let mock = Mock::new("Mock").reply_text("first").reply(vec![
+            MockStep::Reasoning("thinking".into()),
+            MockStep::ToolCall(ToolCall { id: "call_1".into(), name: "calculator".into(), input: serde_json::json!({}) }),
+            MockStep::Text("sec".into()),
+            MockStep::Delay(Duration::from_millis(5)),
+            MockStep::Text("ond".into()),
+            MockStep::Usage(Usage { input_tokens: Some(3), output_tokens: Some(2) }),
+            MockStep::Stop(StopReason::MaxTokens),
+        ]);
+
+        let first = collect(mock.stream_response(ChatRequest::default()).unwrap()).await;
+        assert_eq!(first.text, "first");
+        assert_eq!(first.stop_reason, Some(StopReason::EndTurn));
+        assert!(first.done);
+
+        let second = collect(mock.stream_response(ChatRequest::default().with_model("m".into())).unwrap()).await;
+        assert_eq!(second.text, "second");
+        assert_eq!(second.usage, Usage { input_tokens: Some(3), output_tokens: Some(2) });
+        assert_eq!(second.stop_reason, Some(StopReason::MaxTokens));
+        assert_eq!(second.tool_calls[0].name, "calculator");
+        assert_eq!(mock.requests()[1].model.as_deref(), Some("m"));
+    }
+
+    #[tokio::test]
+    async fn an_error_step_ends_the_stream() {
+        let mock = Mock::new("Mock").reply(vec![
+            MockStep::Text("partial".into()),
+            MockStep::Error(ProviderError::StreamError("overloaded".into())),
+            MockStep::Text(" never sent".into()),
+        ]);
+        let collected = collect(mock.stream_response(ChatRequest::default()).unwrap()).await;
+        assert_eq!(collected.text, "partial");
+        assert!(matches!(collected.error, Some(ProviderError::StreamError(m)) if m == "overloaded"));
+        assert!(!collected.done);
+    }
+
+    #[test]
+    fn running_out_of_script_is_an_error() {
+        let mock = Mock::new("Mock");
+        assert!(matches!(mock.stream_response(ChatRequest::default()), Err(ProviderError::RequestError(_))));
+    }
+}
@@ -2,16 +2,39 @@ pub mod fireworks;
 pub mod claude;
 pub mod none;
 pub mod gpt;
+pub mod gemini;
+pub mod ollama;
+pub mod openai_compatible;
 pub mod base_provider;
+pub mod model_cache;
+pub mod registry;
+pub mod stream_event;
+pub mod openai_format;
+pub mod sse;
+pub mod retry;
+pub mod chat_request;
+pub mod pricing;
+pub mod usage_ledger;
+pub mod model_limits;
+pub mod parameters;
+pub mod network;
+pub mod tool_calls;
+#[cfg(test)]
+pub mod mock;
+#[cfg(test)]
+pub mod fixture_server;
 
 use std::fmt::Display;
+use std::sync::Arc;
+use std::collections::HashMap;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ProviderError {
     RequestError(String),
     ResponseError(String),
     StreamError(String),
+    NotConfigured(String),
 }
 
 impl std::error::Error for ProviderError {}
@@ -22,18 +45,43 @@ impl Display for ProviderError {
             ProviderError::RequestError(e) => write!(f, "Request error: {}", e),
             ProviderError::ResponseError(e) => write!(f, "Response error: {}", e),
             ProviderError::StreamError(e) => write!(f, "Stream error: {}", e),
+            ProviderError::NotConfigured(e) => write!(f, "{}", e),
         }
     }
 }
 
 pub use base_provider::{BaseProvider, ProviderTrait};
+pub use openai_compatible::EndpointConfig;
+pub use registry::{ProviderConfig, ProviderInfo, PROVIDERS};
+pub use retry::RetryPolicy;
+pub use network::NetworkConfig;
+pub use chat_request::{ChatRequest, RequestOptions};
+pub use stream_event::{StopReason, StreamEvent, Usage};
 
-pub fn get_providers(api_keys: String) -> Vec<Box<dyn ProviderTrait + Send + Sync>> {
-    let keys: Vec<String> = api_keys.split(',').map(String::from).collect();
-    vec![
-        Box::new(none::None::new()),
-        Box::new(fireworks::Fireworks::new(keys.get(0).cloned().unwrap_or_default())),
-        Box::new(claude::Claude::new(keys.get(1).cloned().unwrap_or_default())),
-        Box::new(gpt::GPT::new(keys.get(2).cloned().unwrap_or_default())),
-    ]
+pub fn get_providers(configs: &HashMap<String, ProviderConfig>, endpoints: &[EndpointConfig], retry: RetryPolicy, network: &NetworkConfig) -> Vec<Box<dyn ProviderTrait + Send + Sync>> {
+    let mut providers: Vec<Box<dyn ProviderTrait + Send + Sync>> = vec![Box::new(none::None::new())];
+    for info in registry::PROVIDERS {
+        let config = configs.get(info.id).cloned().unwrap_or_else(|| ProviderConfig {
+            api_key: String::new(),
+            base_url: info.default_url.to_string(),
+            retry,
+            network: network.clone(),
+            headers: String::new(),
+        });
+        providers.push((info.build)(config));
+    }
+    for endpoint in endpoints {
+        providers.push(Box::new(openai_compatible::OpenAICompatible::new(endpoint.clone(), retry, network.clone())));
+    }
+    providers
+}
+
+/// Finds the provider serving `model`, preferring `preferred_provider` since
+/// several OpenAI-compatible endpoints may expose the same model name.
+pub fn find_provider_for_model<'a>(providers: &'a [Arc<dyn ProviderTrait + Send + Sync>], preferred_provider: &str, model: &str) -> Option<&'a Arc<dyn ProviderTrait + Send + Sync>> {
+    let serves_model = |p: &&Arc<dyn ProviderTrait + Send + Sync>| p.models().iter().any(|(model_name, _)| model_name == model);
+    providers.iter()
+        .filter(serves_model)
+        .find(|p| p.name() == preferred_provider)
+        .or_else(|| providers.iter().find(serves_model))
 }
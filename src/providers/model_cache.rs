@@ -0,0 +1,96 @@
+use super::{registry, ProviderTrait};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::runtime::Runtime;
+
+const MODEL_CACHE_FILE: &str = "model_cache.json";
+const MAX_CACHE_AGE_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedModels {
+    fetched_at: u64,
+    models: Vec<(String, usize)>,
+}
+
+/// Discovered model lists per provider name, persisted so the model dropdown
+/// is filled immediately on startup and providers aren't queried on every launch.
+#[derive(Serialize, Deserialize, Default)]
+pub struct ModelCache {
+    providers: HashMap<String, CachedModels>,
+}
+
+impl ModelCache {
+    pub fn load() -> Self {
+        std::fs::read_to_string(MODEL_CACHE_FILE)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        match serde_json::to_string_pretty(self) {
+            Ok(content) => {
+                if let Err(e) = std::fs::write(MODEL_CACHE_FILE, content) {
+                    eprintln!("Failed to write model cache: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Failed to serialize model cache: {}", e),
+        }
+    }
+
+    fn get(&self, provider: &str) -> Option<&CachedModels> {
+        self.providers.get(provider)
+    }
+
+    fn insert(&mut self, provider: &str, models: Vec<(String, usize)>) {
+        self.providers.insert(provider.to_string(), CachedModels { fetched_at: now_secs(), models });
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Applies cached model lists right away, then queries every provider whose
+/// cache entry is missing or older than a day (or all of them when `force` is set).
+/// Failed lookups leave the cached or static list in place.
+pub fn refresh_models(providers: &[Arc<dyn ProviderTrait + Send + Sync>], runtime: &Runtime, force: bool) {
+    let cache = ModelCache::load();
+    let now = now_secs();
+    let mut stale = Vec::new();
+
+    for provider in providers {
+        if registry::find_info(provider.name()).is_some_and(|info| !info.capabilities.lists_models) {
+            continue;
+        }
+        match cache.get(provider.name()) {
+            Some(cached) => {
+                provider.set_models(cached.models.clone());
+                if force || now.saturating_sub(cached.fetched_at) > MAX_CACHE_AGE_SECS {
+                    stale.push(Arc::clone(provider));
+                }
+            }
+            None => stale.push(Arc::clone(provider)),
+        }
+    }
+
+    let cache = Arc::new(Mutex::new(cache));
+    for provider in stale {
+        let cache = Arc::clone(&cache);
+        runtime.spawn(async move {
+            match provider.fetch_models().await {
+                Ok(models) if !models.is_empty() => {
+                    println!("Debug: Discovered {} models for {}", models.len(), provider.name());
+                    provider.set_models(models.clone());
+                    let mut cache = cache.lock().unwrap();
+                    cache.insert(provider.name(), models);
+                    cache.save();
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to fetch models for {}: {}", provider.name(), e),
+            }
+        });
+    }
+}
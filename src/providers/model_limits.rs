@@ -0,0 +1,103 @@
+use super::ProviderTrait;
+
+/// Context window sizes in tokens, by model name prefix (longest match wins,
+/// so `gpt-4.1` isn't taken for `gpt-4`).
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("claude-", 200_000),
+    ("gpt-5", 400_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4.5", 128_000),
+    ("gpt-4o", 128_000),
+    ("chatgpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o1-preview", 128_000),
+    ("o1-mini", 128_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("gemini-1.5-pro", 2_097_152),
+    ("gemini-1.5-flash", 1_048_576),
+    ("gemini-2.0-flash", 1_048_576),
+    ("llama-v3p1-", 131_072),
+    ("llama-v3p2-", 131_072),
+    ("qwen2p5-72b", 32_768),
+];
+
+/// Reply limits of model families whose listed names carry dates or sizes,
+/// so that they can't be found in a provider's static list.
+const OUTPUT_LIMITS: &[(&str, u32)] = &[
+    ("gpt-5", 128_000),
+    ("gpt-4.1", 32_768),
+    ("o3", 100_000),
+    ("o4", 100_000),
+];
+
+const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+
+fn longest_prefix_match<T: Copy>(table: &[(&str, T)], model: &str) -> Option<T> {
+    let short_name = model.trim_start_matches("accounts/fireworks/models/");
+    table.iter()
+        .filter(|(prefix, _)| short_name.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, value)| *value)
+}
+
+/// The longest reply `model` can write, where its provider lists one.
+pub fn max_output_tokens(provider: &dyn ProviderTrait, model: &str) -> Option<u32> {
+    if !provider.lists_output_limits() {
+        return None;
+    }
+    if let Some(limit) = longest_prefix_match(OUTPUT_LIMITS, model) {
+        return Some(limit);
+    }
+    provider.models().into_iter()
+        .find(|(name, _)| name == model)
+        .map(|(_, limit)| limit as u32)
+        .filter(|limit| *limit > 0)
+}
+
+/// How many tokens `model` accepts in one request, prompt and reply together.
+/// The numbers `ProviderTrait::models()` lists are only used where they are
+/// context lengths; elsewhere they are reply limits.
+pub fn context_window(provider: &dyn ProviderTrait, model: &str) -> usize {
+    longest_prefix_match(CONTEXT_WINDOWS, model)
+        .or_else(|| {
+            let listed = provider.models().into_iter().find(|(name, _)| name == model);
+            listed.filter(|_| !provider.lists_output_limits()).map(|(_, length)| length)
+        })
+        .filter(|window| *window > 0)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::gpt::GPT;
+    use crate::providers::ollama::Ollama;
+    use crate::providers::registry::ProviderConfig;
+
+    #[test]
+    fn takes_no_output_limit_from_ollama_context_lengths() {
+        let ollama = Ollama::new(ProviderConfig::default());
+        ollama.set_models(vec![("llama3.1:8b".to_string(), 131_072)]);
+        assert_eq!(max_output_tokens(&ollama, "llama3.1:8b"), None);
+        assert_eq!(context_window(&ollama, "llama3.1:8b"), 131_072);
+    }
+
+    #[test]
+    fn knows_newer_openai_families() {
+        let gpt = GPT::new(ProviderConfig::default());
+        gpt.set_models(vec![("gpt-4.1-mini".to_string(), 4096), ("gpt-5-2025-08-07".to_string(), 4096), ("my-fine-tune".to_string(), 4096)]);
+        assert_eq!(context_window(&gpt, "gpt-4.1-mini"), 1_047_576);
+        assert_eq!(context_window(&gpt, "gpt-4-0613"), 8_192);
+        assert_eq!(context_window(&gpt, "gpt-5-2025-08-07"), 400_000);
+        assert_eq!(context_window(&gpt, "o4-mini"), 200_000);
+        assert_eq!(context_window(&gpt, "o3"), 200_000);
+        assert_eq!(context_window(&gpt, "my-fine-tune"), DEFAULT_CONTEXT_WINDOW);
+        assert_eq!(max_output_tokens(&gpt, "gpt-5-2025-08-07"), Some(128_000));
+        assert_eq!(max_output_tokens(&gpt, "o4-mini"), Some(100_000));
+        assert_eq!(max_output_tokens(&gpt, "my-fine-tune"), Some(4096));
+    }
+}
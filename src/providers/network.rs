@@ -0,0 +1,108 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, NoProxy, Proxy};
+use std::time::Duration;
+
+/// Connection settings shared by every provider's HTTP client.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetworkConfig {
+    /// `http://`, `https://` or `socks5://` URL; empty connects directly.
+    pub proxy: String,
+    /// Comma-separated hosts, domains or IP ranges that bypass the proxy.
+    pub no_proxy: String,
+    /// PEM file with extra root certificates to trust, e.g. a corporate CA.
+    pub ca_file: String,
+    /// Seconds to wait for a connection and for the response to start; 0 waits indefinitely.
+    pub timeout_secs: u32,
+}
+
+impl NetworkConfig {
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(u64::from(self.timeout_secs)))
+    }
+}
+
+/// Builds a client that goes through the configured proxy, trusts the extra
+/// certificates and sends `headers` (one `Name: value` per line) with every request.
+pub fn build_client(network: &NetworkConfig, headers: &str) -> Result<Client, String> {
+    let mut builder = Client::builder().default_headers(parse_headers(headers)?);
+
+    let proxy_url = network.proxy.trim();
+    if !proxy_url.is_empty() {
+        let proxy = Proxy::all(proxy_url).map_err(|e| format!("Invalid proxy \"{}\": {}", proxy_url, e))?;
+        builder = builder.proxy(proxy.no_proxy(NoProxy::from_string(&network.no_proxy)));
+    }
+
+    let ca_file = network.ca_file.trim();
+    if !ca_file.is_empty() {
+        let pem = std::fs::read(ca_file).map_err(|e| format!("Can't read certificate file \"{}\": {}", ca_file, e))?;
+        let certificates = Certificate::from_pem_bundle(&pem).map_err(|e| format!("Invalid certificate file \"{}\": {}", ca_file, e))?;
+        if certificates.is_empty() {
+            return Err(format!("No certificates found in \"{}\"", ca_file));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    // Only the connection gets a hard timeout; a total one would cut off long streamed replies.
+    if let Some(timeout) = network.timeout() {
+        builder = builder.connect_timeout(timeout);
+    }
+
+    builder.build().map_err(|e| format!("Can't create HTTP client: {}", e))
+}
+
+/// Parses `Name: value` lines; blank lines are skipped.
+pub fn parse_headers(text: &str) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let (name, value) = line.split_once(':').ok_or_else(|| format!("Header \"{}\" needs a colon between name and value", line))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| format!("Invalid header name \"{}\"", name.trim()))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|_| format!("Invalid value for header \"{}\"", name))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fixture_server::{Fixture, FixtureServer};
+
+    #[tokio::test]
+    async fn sends_extra_headers_with_every_request() {
+        let server = FixtureServer::start(vec![Fixture::status(200, "{}")]).await;
+        let client = build_client(&NetworkConfig::default(), "X-Team: research\n\nX-Trace:  abc ").unwrap();
+
+        client.get(&server.base_url).send().await.unwrap();
+        let sent = &server.requests()[0];
+        assert_eq!(sent.header("x-team"), Some("research"));
+        assert_eq!(sent.header("x-trace"), Some("abc"));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert!(parse_headers("X-Team research").is_err());
+        assert!(parse_headers("Bad Name: value").is_err());
+        assert_eq!(parse_headers("").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn rejects_unusable_settings() {
+        let proxy = NetworkConfig { proxy: "not a url".into(), ..Default::default() };
+        assert!(build_client(&proxy, "").is_err());
+        let ca_file = NetworkConfig { ca_file: "/nonexistent/ca.pem".into(), ..Default::default() };
+        assert!(build_client(&ca_file, "").is_err());
+        let socks = NetworkConfig { proxy: "socks5://127.0.0.1:1080".into(), no_proxy: "localhost".into(), timeout_secs: 5, ..Default::default() };
+        assert!(build_client(&socks, "").is_ok());
+    }
+
+    #[tokio::test]
+    async fn providers_refuse_to_connect_without_their_network_settings() {
+        use crate::providers::{claude::Claude, ChatRequest, ProviderConfig, ProviderError, ProviderTrait};
+        let network = NetworkConfig { proxy: "not a url".into(), ..Default::default() };
+        let claude = Claude::new(ProviderConfig { api_key: "key".into(), network, ..Default::default() });
+        assert!(matches!(claude.stream_response(ChatRequest::default()), Err(ProviderError::NotConfigured(_))));
+        assert!(matches!(claude.fetch_models().await, Err(ProviderError::NotConfigured(_))));
+    }
+}
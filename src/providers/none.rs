@@ -1,8 +1,11 @@
 use super::{ProviderError, ProviderTrait};
+use super::chat_request::ChatRequest;
+use super::stream_event::{spawn_stream, StreamEvent};
+use async_trait::async_trait;
 use std::fmt;
-use serde_json::Value;
 use tokio::sync::mpsc;
-use crate::app::ProfileType;
+use super::parameters::ParamSpec;
+use crate::app::Profile;
 
 pub struct None;
 
@@ -12,19 +15,28 @@ impl None {
     }
 }
 
+#[async_trait]
 impl ProviderTrait for None {
-    fn name(&self) -> &'static str {
+    fn name(&self) -> &str {
         "Select a provider"
     }
 
-    fn models(&self) -> Vec<(&'static str, usize)> {
-        vec![("Then select model", 0)]
+    fn models(&self) -> Vec<(String, usize)> {
+        self.default_models()
     }
 
-    fn stream_response(&self, _messages: Vec<Value>) -> Result<mpsc::Receiver<String>, ProviderError> {
+    fn default_models(&self) -> Vec<(String, usize)> {
+        vec![("Then select model".to_string(), 0)]
+    }
+
+    fn set_models(&self, _models: Vec<(String, usize)>) {
+        // Do nothing for None provider
+    }
+
+    fn stream_response(&self, _request: ChatRequest) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
         let (tx, rx) = mpsc::channel(1);
-        tokio::task::spawn(async move {
-            let _ = tx.send("API key goes in Settings. Then select a provider and model.".to_string()).await;
+        spawn_stream(tx.clone(), async move {
+            let _ = tx.send(StreamEvent::Error(ProviderError::NotConfigured("API key goes in Settings. Then select a provider and model.".to_string()))).await;
         });
         Ok(rx)
     }
@@ -33,11 +45,12 @@ impl ProviderTrait for None {
         // Do nothing for None provider
     }
 
-    fn update_profile(&self, _profile: ProfileType) {
+    fn update_profile(&self, _profile: Profile) {
         // Do nothing for None provider
     }
-    fn get_parameters(&self) -> (f32, u32, f32, f32) {
-        (1.0, 1, 1.0, 1.0)
+
+    fn parameter_specs(&self, _model: &str) -> &'static [ParamSpec] {
+        &[]
     }
 }
 
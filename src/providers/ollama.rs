@@ -0,0 +1,216 @@
+use super::{ProviderError, ProviderTrait, BaseProvider};
+use super::registry::{Capabilities, ProviderConfig, ProviderInfo};
+use super::base_provider::get_json;
+use super::chat_request::ChatRequest;
+use super::stream_event::{spawn_stream, StopReason, StreamEvent, Usage};
+use super::sse::forward_lines;
+use super::retry::send_with_retry;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use super::parameters::{self, ParamSpec};
+use crate::app::Profile;
+use reqwest::Client;
+
+const DEFAULT_CONTEXT_LENGTH: usize = 2048;
+
+pub const INFO: ProviderInfo = ProviderInfo {
+    id: "ollama",
+    display_name: "Ollama",
+    key_name: None,
+    default_url: "http://localhost:11434",
+    capabilities: Capabilities { lists_models: true },
+    build: |config| Box::new(Ollama::new(config)),
+};
+
+pub struct Ollama {
+    base: Arc<Mutex<BaseProvider>>,
+    current_model: Arc<Mutex<String>>,
+}
+
+impl Ollama {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self {
+            base: Arc::new(Mutex::new(BaseProvider::new(config))),
+            current_model: Arc::new(Mutex::new(String::new())),
+        }
+    }
+}
+
+async fn fetch_context_length(client: &Client, base_url: &str, model: &str) -> Option<usize> {
+    let details = get_json(client
+        .post(format!("{}/api/show", base_url))
+        .json(&json!({ "model": model })))
+        .await
+        .ok()?;
+
+    details["model_info"]
+        .as_object()?
+        .iter()
+        .find(|(key, _)| key.ends_with(".context_length"))
+        .and_then(|(_, value)| value.as_u64())
+        .map(|length| length as usize)
+}
+
+/// The request's messages for `/api/chat`, which takes images as a plain list of base64 strings.
+fn to_ollama_messages(request: &ChatRequest) -> Vec<Value> {
+    request.messages_with_system_role().into_iter().map(|mut message| {
+        if let Some(images) = message["images"].as_array() {
+            message["images"] = images.iter().map(|image| image["data"].clone()).collect();
+        }
+        message
+    }).collect()
+}
+
+/// Turns one line of the `/api/chat` stream into our events.
+fn parse_line(json: &Value) -> Vec<StreamEvent> {
+    if let Some(error) = json["error"].as_str() {
+        return vec![StreamEvent::Error(ProviderError::ResponseError(error.to_string()))];
+    }
+
+    let mut events = Vec::new();
+    if let Some(thinking) = json["message"]["thinking"].as_str().filter(|t| !t.is_empty()) {
+        events.push(StreamEvent::ReasoningDelta(thinking.to_string()));
+    }
+    if let Some(content) = json["message"]["content"].as_str().filter(|c| !c.is_empty()) {
+        events.push(StreamEvent::TextDelta(content.to_string()));
+    }
+    if json["done"].as_bool() == Some(true) {
+        if let Some(reason) = json["done_reason"].as_str() {
+            events.push(StreamEvent::StopReason(StopReason::from_api(reason)));
+        }
+        events.push(StreamEvent::Usage(Usage {
+            input_tokens: json["prompt_eval_count"].as_u64(),
+            output_tokens: json["eval_count"].as_u64(),
+        }));
+        events.push(StreamEvent::Done);
+    }
+    events
+}
+
+#[async_trait]
+impl ProviderTrait for Ollama {
+    fn name(&self) -> &str {
+        INFO.display_name
+    }
+
+    fn models(&self) -> Vec<(String, usize)> {
+        self.base.lock().unwrap().models_or(self.default_models())
+    }
+
+    // There is no sensible static list for a local server; the installed
+    // models always come from discovery.
+    fn default_models(&self) -> Vec<(String, usize)> {
+        vec![("No local models found".to_string(), 0)]
+    }
+
+    async fn fetch_models(&self) -> Result<Vec<(String, usize)>, ProviderError> {
+        let (client, base_url) = {
+            let base = self.base.lock().unwrap();
+            (base.get_client()?, base.get_base_url())
+        };
+        let tags = get_json(client.get(format!("{}/api/tags", base_url))).await?;
+
+        let mut models = Vec::new();
+        for name in tags["models"].as_array().into_iter().flatten().filter_map(|m| m["name"].as_str()) {
+            let context_length = fetch_context_length(&client, &base_url, name).await.unwrap_or(DEFAULT_CONTEXT_LENGTH);
+            models.push((name.to_string(), context_length));
+        }
+        Ok(models)
+    }
+
+    fn set_models(&self, models: Vec<(String, usize)>) {
+        self.base.lock().unwrap().set_models(models);
+    }
+
+    fn stream_response(&self, request: ChatRequest) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
+        let model = request.model.clone().unwrap_or_else(|| self.current_model.lock().unwrap().clone());
+        let profile = request.options.applied_to(self.base.lock().unwrap().get_profile());
+        let client = self.base.lock().unwrap().get_client()?;
+        let retry_policy = self.base.lock().unwrap().get_retry_policy();
+        let url = format!("{}/api/chat", self.base.lock().unwrap().get_base_url());
+
+        let mut json_body = json!({
+            "model": model,
+            "messages": to_ollama_messages(&request),
+            "stream": true,
+        });
+        parameters::apply(&mut json_body, self.parameter_specs(&model), &profile, None);
+
+        println!("Debug: Model parameters - {} profile for {}", profile.name, model);
+
+        let (tx, rx) = mpsc::channel(1024);
+
+        spawn_stream(tx.clone(), async move {
+            let request = client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .json(&json_body);
+            let response = match send_with_retry(request, retry_policy, &tx).await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    let _ = tx.send(StreamEvent::Error(e)).await;
+                    return;
+                }
+            };
+
+            // Ollama streams newline-delimited JSON rather than SSE.
+            forward_lines(response, &tx, |line| {
+                serde_json::from_str::<Value>(line)
+                    .map(|json| parse_line(&json))
+                    .unwrap_or_default()
+            }).await;
+        });
+
+        Ok(rx)
+    }
+
+    fn set_current_model(&self, model: String) {
+        *self.current_model.lock().unwrap() = model;
+    }
+
+    fn update_profile(&self, profile: Profile) {
+        self.base.lock().unwrap().update_profile(profile);
+    }
+
+    fn lists_output_limits(&self) -> bool {
+        false
+    }
+
+    fn parameter_specs(&self, _model: &str) -> &'static [ParamSpec] {
+        parameters::OLLAMA
+    }
+}
+
+impl fmt::Display for Ollama {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Ollama")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fixture_server::{collect, Fixture, FixtureServer, OLLAMA_STREAM};
+
+    #[tokio::test]
+    async fn streams_a_recorded_response() {
+        let server = FixtureServer::start(vec![Fixture::ndjson(OLLAMA_STREAM)]).await;
+        let ollama = Ollama::new(ProviderConfig { base_url: server.base_url.clone(), ..Default::default() });
+
+        let request = ChatRequest::new(None, vec![json!({ "role": "user", "content": "Hi" })])
+            .with_model("llama3.1:8b".into());
+        let collected = collect(ollama.stream_response(request).unwrap()).await;
+        assert_eq!(collected.text, "Grüße aus Zürich 🦀");
+        assert_eq!(collected.usage, Usage { input_tokens: Some(26), output_tokens: Some(12) });
+        assert_eq!(collected.stop_reason, Some(StopReason::MaxTokens));
+        assert!(collected.done && collected.error.is_none());
+
+        let sent = &server.requests()[0];
+        assert_eq!(sent.path, "/api/chat");
+        assert_eq!(sent.body["model"], "llama3.1:8b");
+        assert_eq!(sent.body["stream"], true);
+    }
+}
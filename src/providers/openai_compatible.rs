@@ -0,0 +1,213 @@
+use super::{ProviderError, ProviderTrait, BaseProvider};
+use super::base_provider::get_json;
+use super::registry::ProviderConfig;
+use super::retry::{send_with_retry, RetryPolicy};
+use super::network::NetworkConfig;
+use super::openai_format;
+use super::chat_request::ChatRequest;
+use super::stream_event::{spawn_stream, StreamEvent};
+use super::sse::forward_sse;
+use super::tool_calls::ToolCallBuffer;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use super::parameters::{self, ParamSpec};
+use crate::app::Profile;
+
+const DEFAULT_MAX_TOKENS: usize = 8192;
+
+/// A user-defined endpoint that speaks the OpenAI `/chat/completions` dialect,
+/// e.g. llama.cpp server, vLLM, LM Studio, LiteLLM or an internal gateway.
+#[derive(Clone, Default)]
+pub struct EndpointConfig {
+    pub name: String,
+    pub base_url: String,
+    pub api_key: String,
+    /// Comma-separated model names, as entered in Settings.
+    pub models: String,
+    /// Extra headers sent with every request, one `Name: value` per line.
+    pub headers: String,
+}
+
+impl EndpointConfig {
+    pub fn model_names(&self) -> Vec<String> {
+        self.models
+            .split(',')
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty())
+            .collect()
+    }
+}
+
+pub struct OpenAICompatible {
+    base: Arc<Mutex<BaseProvider>>,
+    current_model: Arc<Mutex<String>>,
+    name: String,
+    models: Vec<String>,
+}
+
+impl OpenAICompatible {
+    pub fn new(config: EndpointConfig, retry: RetryPolicy, network: NetworkConfig) -> Self {
+        let models = config.model_names();
+        let provider_config = ProviderConfig { api_key: config.api_key, base_url: config.base_url, retry, network, headers: config.headers };
+        Self {
+            base: Arc::new(Mutex::new(BaseProvider::new(provider_config))),
+            current_model: Arc::new(Mutex::new(models.first().cloned().unwrap_or_default())),
+            name: config.name,
+            models,
+        }
+    }
+}
+
+#[async_trait]
+impl ProviderTrait for OpenAICompatible {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Models entered in Settings take precedence; discovery fills in for endpoints without any.
+    fn models(&self) -> Vec<(String, usize)> {
+        if !self.models.is_empty() {
+            return self.default_models();
+        }
+        self.base.lock().unwrap().models_or(self.default_models())
+    }
+
+    fn default_models(&self) -> Vec<(String, usize)> {
+        if self.models.is_empty() {
+            vec![("No models configured".to_string(), 0)]
+        } else {
+            self.models.iter().map(|m| (m.clone(), DEFAULT_MAX_TOKENS)).collect()
+        }
+    }
+
+    async fn fetch_models(&self) -> Result<Vec<(String, usize)>, ProviderError> {
+        if !self.models.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (client, api_key, base_url) = {
+            let base = self.base.lock().unwrap();
+            (base.get_client()?, base.get_api_key(), base.get_base_url())
+        };
+
+        let mut request = client.get(format!("{}/models", base_url));
+        if !api_key.is_empty() {
+            request = request.header("Authorization", format!("Bearer {}", api_key));
+        }
+        let json = get_json(request).await?;
+
+        Ok(json["data"].as_array().into_iter().flatten()
+            .filter_map(|m| m["id"].as_str())
+            .map(|id| (id.to_string(), DEFAULT_MAX_TOKENS))
+            .collect())
+    }
+
+    fn set_models(&self, models: Vec<(String, usize)>) {
+        self.base.lock().unwrap().set_models(models);
+    }
+
+    fn stream_response(&self, request: ChatRequest) -> Result<mpsc::Receiver<StreamEvent>, ProviderError> {
+        let model = request.model.clone().unwrap_or_else(|| self.current_model.lock().unwrap().clone());
+        let profile = request.options.applied_to(self.base.lock().unwrap().get_profile());
+        let client = self.base.lock().unwrap().get_client()?;
+        let retry_policy = self.base.lock().unwrap().get_retry_policy();
+        let api_key = self.base.lock().unwrap().get_api_key();
+        let url = format!("{}/chat/completions", self.base.lock().unwrap().get_base_url());
+
+        let mut json_body = json!({
+            "model": model,
+            "messages": openai_format::messages(&request),
+            "stream": true,
+            "stream_options": { "include_usage": true },
+        });
+        parameters::apply(&mut json_body, self.parameter_specs(&model), &profile, None);
+        openai_format::add_tools(&mut json_body, &request);
+        openai_format::add_response_format(&mut json_body, &request);
+
+        println!("Debug: Model parameters - {} profile for {}", profile.name, model);
+
+        let (tx, rx) = mpsc::channel(1024);
+
+        spawn_stream(tx.clone(), async move {
+            let mut request = client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .json(&json_body);
+            // Local servers usually run without authentication.
+            if !api_key.is_empty() {
+                request = request.header("Authorization", format!("Bearer {}", api_key));
+            }
+
+            let response = match send_with_retry(request, retry_policy, &tx).await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    let _ = tx.send(StreamEvent::Error(e)).await;
+                    return;
+                }
+            };
+
+            let mut tool_calls = ToolCallBuffer::default();
+            forward_sse(response, &tx, |event| {
+                if event.data == "[DONE]" {
+                    return vec![StreamEvent::Done];
+                }
+                serde_json::from_str::<Value>(&event.data)
+                    .map(|json| openai_format::parse_chunk(&json, &mut tool_calls))
+                    .unwrap_or_default()
+            }).await;
+        });
+
+        Ok(rx)
+    }
+
+    fn set_current_model(&self, model: String) {
+        *self.current_model.lock().unwrap() = model;
+    }
+
+    fn update_profile(&self, profile: Profile) {
+        self.base.lock().unwrap().update_profile(profile);
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        true
+    }
+
+    fn parameter_specs(&self, _model: &str) -> &'static [ParamSpec] {
+        parameters::OPENAI
+    }
+}
+
+impl fmt::Display for OpenAICompatible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(models: &str) -> OpenAICompatible {
+        let config = EndpointConfig { name: "Local".to_string(), base_url: "http://localhost:1".to_string(), models: models.to_string(), ..EndpointConfig::default() };
+        OpenAICompatible::new(config, RetryPolicy::default(), NetworkConfig::default())
+    }
+
+    #[tokio::test]
+    async fn prefers_configured_models_over_discovered_ones() {
+        let configured = endpoint("qwen2.5-coder, llama3.1");
+        configured.set_models(vec![("some-embedding-model".to_string(), DEFAULT_MAX_TOKENS)]);
+        let names: Vec<String> = configured.models().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["qwen2.5-coder", "llama3.1"]);
+        assert!(configured.fetch_models().await.unwrap().is_empty());
+
+        let unconfigured = endpoint("");
+        unconfigured.set_models(vec![("discovered".to_string(), DEFAULT_MAX_TOKENS)]);
+        assert_eq!(unconfigured.models()[0].0, "discovered");
+    }
+}
@@ -0,0 +1,104 @@
+use super::chat_request::ChatRequest;
+use super::stream_event::{StopReason, StreamEvent, Usage};
+use super::tool_calls::ToolCallBuffer;
+use super::ProviderError;
+use serde_json::{json, Value};
+
+/// The request's messages for `/chat/completions`, with images as `image_url`
+/// parts and each tool result as its own `tool` message.
+pub fn messages(request: &ChatRequest) -> Vec<Value> {
+    request.messages_with_system_role().into_iter().flat_map(|mut message| {
+        if message["role"] == "tool" {
+            return message["results"].as_array().into_iter().flatten().map(|result| json!({
+                "role": "tool",
+                "tool_call_id": result["id"],
+                "content": result["content"],
+            })).collect();
+        }
+        if let Some(Value::Array(images)) = message.as_object_mut().and_then(|m| m.remove("images")) {
+            let text = json!({ "type": "text", "text": message["content"] });
+            let images = images.iter().map(|image| json!({
+                "type": "image_url",
+                "image_url": { "url": format!("data:{};base64,{}", image["media_type"].as_str().unwrap_or_default(), image["data"].as_str().unwrap_or_default()) }
+            }));
+            message["content"] = std::iter::once(text).chain(images).collect();
+        }
+        // Only Claude takes its reasoning back.
+        if let Some(fields) = message.as_object_mut() {
+            fields.remove("reasoning");
+        }
+        if let Some(Value::Array(calls)) = message.as_object_mut().and_then(|m| m.remove("tool_calls")) {
+            message["tool_calls"] = calls.iter().map(|call| json!({
+                "id": call["id"],
+                "type": "function",
+                "function": { "name": call["name"], "arguments": call["input"].to_string() }
+            })).collect();
+            if message["content"] == "" {
+                message["content"] = Value::Null;
+            }
+        }
+        vec![message]
+    }).collect()
+}
+
+/// Adds the request's tools to a `/chat/completions` body as functions.
+pub fn add_tools(body: &mut Value, request: &ChatRequest) {
+    if request.tools.is_empty() {
+        return;
+    }
+    body["tools"] = request.tools.iter().map(|tool| json!({
+        "type": "function",
+        "function": { "name": tool.name, "description": tool.description, "parameters": tool.input_schema }
+    })).collect();
+}
+
+/// Asks for a reply matching the chat's JSON Schema, if it is in JSON mode.
+/// The schema isn't strict, since strict mode only takes a subset of JSON Schema.
+pub fn add_response_format(body: &mut Value, request: &ChatRequest) {
+    if let Some(schema) = &request.options.json_schema {
+        body["response_format"] = json!({
+            "type": "json_schema",
+            "json_schema": { "name": "response", "schema": schema, "strict": false }
+        });
+    }
+}
+
+/// Turns one `chat.completion.chunk` from an OpenAI-style stream into events.
+/// Shared by GPT, Fireworks and the user-defined OpenAI-compatible endpoints.
+/// Tool calls are gathered in `tool_calls` until the choice finishes.
+pub fn parse_chunk(json: &Value, tool_calls: &mut ToolCallBuffer) -> Vec<StreamEvent> {
+    let mut events = Vec::new();
+
+    if let Some(error) = json.get("error") {
+        let message = error["message"].as_str().map(str::to_string).unwrap_or_else(|| error.to_string());
+        events.push(StreamEvent::Error(ProviderError::ResponseError(message)));
+        return events;
+    }
+
+    let choice = &json["choices"][0];
+    // DeepSeek, vLLM and others stream chain-of-thought next to the answer.
+    for field in ["reasoning_content", "reasoning"] {
+        if let Some(reasoning) = choice["delta"][field].as_str().filter(|r| !r.is_empty()) {
+            events.push(StreamEvent::ReasoningDelta(reasoning.to_string()));
+        }
+    }
+    if let Some(content) = choice["delta"]["content"].as_str().filter(|c| !c.is_empty()) {
+        events.push(StreamEvent::TextDelta(content.to_string()));
+    }
+    for call in choice["delta"]["tool_calls"].as_array().into_iter().flatten() {
+        let function = &call["function"];
+        tool_calls.update(call["index"].as_u64().unwrap_or(0), call["id"].as_str(), function["name"].as_str(), function["arguments"].as_str().unwrap_or_default());
+    }
+    if let Some(reason) = choice["finish_reason"].as_str() {
+        events.extend(tool_calls.finish_all());
+        events.push(StreamEvent::StopReason(StopReason::from_api(reason)));
+    }
+    if let Some(usage) = json.get("usage").filter(|u| u.is_object()) {
+        events.push(StreamEvent::Usage(Usage {
+            input_tokens: usage["prompt_tokens"].as_u64(),
+            output_tokens: usage["completion_tokens"].as_u64(),
+        }));
+    }
+
+    events
+}
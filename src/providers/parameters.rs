@@ -0,0 +1,200 @@
+use crate::app::Profile;
+use serde_json::{json, Value};
+
+/// A sampling setting from a generation profile.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Param {
+    Temperature,
+    TopP,
+    TopK,
+    FrequencyPenalty,
+    PresencePenalty,
+    MaxTokens,
+    StopSequences,
+    ThinkingBudget,
+}
+
+impl Param {
+    pub const ALL: [Param; 8] = [
+        Param::Temperature,
+        Param::TopP,
+        Param::TopK,
+        Param::FrequencyPenalty,
+        Param::PresencePenalty,
+        Param::MaxTokens,
+        Param::StopSequences,
+        Param::ThinkingBudget,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Param::Temperature => "temperature",
+            Param::TopP => "top P",
+            Param::TopK => "top K",
+            Param::FrequencyPenalty => "frequency penalty",
+            Param::PresencePenalty => "presence penalty",
+            Param::MaxTokens => "max tokens",
+            Param::StopSequences => "stop sequences",
+            Param::ThinkingBudget => "thinking budget",
+        }
+    }
+}
+
+/// How one API takes a sampling parameter. Parameters a model has no spec
+/// for are left out of its requests.
+#[derive(Clone, Copy, Debug)]
+pub struct ParamSpec {
+    pub param: Param,
+    /// Field in the request body; `/` separates nested objects.
+    pub field: &'static str,
+    /// Valid range; profile values outside it are clamped. For stop
+    /// sequences, `max` is how many the API accepts.
+    pub min: f32,
+    pub max: f32,
+    /// Added to the clamped value, for APIs whose field is centered elsewhere.
+    pub offset: f32,
+}
+
+const fn spec(param: Param, field: &'static str, min: f32, max: f32) -> ParamSpec {
+    ParamSpec { param, field, min, max, offset: 0.0 }
+}
+
+const fn shifted(param: Param, field: &'static str, min: f32, max: f32, offset: f32) -> ParamSpec {
+    ParamSpec { param, field, min, max, offset }
+}
+
+pub const OPENAI: &[ParamSpec] = &[
+    spec(Param::Temperature, "temperature", 0.0, 2.0),
+    spec(Param::TopP, "top_p", 0.0, 1.0),
+    spec(Param::FrequencyPenalty, "frequency_penalty", -2.0, 2.0),
+    spec(Param::PresencePenalty, "presence_penalty", -2.0, 2.0),
+    spec(Param::MaxTokens, "max_tokens", 1.0, 128_000.0),
+    spec(Param::StopSequences, "stop", 0.0, 4.0),
+];
+
+/// Reasoning models (o1, o3, o4, GPT-5) reject sampling settings and take `max_completion_tokens`.
+pub const OPENAI_REASONING: &[ParamSpec] = &[
+    spec(Param::MaxTokens, "max_completion_tokens", 1.0, 100_000.0),
+];
+
+pub const CLAUDE: &[ParamSpec] = &[
+    spec(Param::Temperature, "temperature", 0.0, 1.0),
+    spec(Param::TopP, "top_p", 0.0, 1.0),
+    spec(Param::TopK, "top_k", 1.0, 500.0),
+    spec(Param::MaxTokens, "max_tokens", 1.0, 64_000.0),
+    spec(Param::StopSequences, "stop_sequences", 0.0, 16.0),
+];
+
+/// Claude 3.7 and later can think before answering, within a token budget.
+pub const CLAUDE_THINKING: &[ParamSpec] = &[
+    spec(Param::Temperature, "temperature", 0.0, 1.0),
+    spec(Param::TopP, "top_p", 0.0, 1.0),
+    spec(Param::TopK, "top_k", 1.0, 500.0),
+    spec(Param::MaxTokens, "max_tokens", 1.0, 64_000.0),
+    spec(Param::StopSequences, "stop_sequences", 0.0, 16.0),
+    spec(Param::ThinkingBudget, "thinking/budget_tokens", 1024.0, 60_000.0),
+];
+
+pub const GEMINI: &[ParamSpec] = &[
+    spec(Param::Temperature, "generationConfig/temperature", 0.0, 2.0),
+    spec(Param::TopP, "generationConfig/topP", 0.0, 1.0),
+    spec(Param::TopK, "generationConfig/topK", 1.0, 100.0),
+    spec(Param::MaxTokens, "generationConfig/maxOutputTokens", 1.0, 65_536.0),
+    spec(Param::StopSequences, "generationConfig/stopSequences", 0.0, 5.0),
+];
+
+pub const FIREWORKS: &[ParamSpec] = &[
+    spec(Param::Temperature, "temperature", 0.0, 2.0),
+    spec(Param::TopP, "top_p", 0.0, 1.0),
+    spec(Param::TopK, "top_k", 1.0, 100.0),
+    spec(Param::FrequencyPenalty, "frequency_penalty", -2.0, 2.0),
+    spec(Param::PresencePenalty, "presence_penalty", -2.0, 2.0),
+    spec(Param::MaxTokens, "max_tokens", 1.0, 131_072.0),
+    spec(Param::StopSequences, "stop", 0.0, 4.0),
+];
+
+pub const OLLAMA: &[ParamSpec] = &[
+    spec(Param::Temperature, "options/temperature", 0.0, 2.0),
+    spec(Param::TopP, "options/top_p", 0.0, 1.0),
+    spec(Param::TopK, "options/top_k", 1.0, 1000.0),
+    // llama.cpp runners ignore frequency_penalty; repeat_penalty 1.0 is no penalty.
+    shifted(Param::FrequencyPenalty, "options/repeat_penalty", -1.0, 2.0, 1.0),
+    spec(Param::PresencePenalty, "options/presence_penalty", -2.0, 2.0),
+    spec(Param::MaxTokens, "options/num_predict", 1.0, 1_000_000.0),
+    spec(Param::StopSequences, "options/stop", 0.0, 16.0),
+];
+
+pub fn find(specs: &[ParamSpec], param: Param) -> Option<&ParamSpec> {
+    specs.iter().find(|spec| spec.param == param)
+}
+
+/// The value `profile` has for `param`, as a number; `None` for unset max
+/// tokens and thinking budget, and for stop sequences.
+pub fn profile_value(profile: &Profile, param: Param) -> Option<f32> {
+    match param {
+        Param::Temperature => Some(profile.temperature),
+        Param::TopP => Some(profile.top_p),
+        Param::TopK => Some(profile.top_k as f32),
+        Param::FrequencyPenalty => Some(profile.frequency_penalty),
+        Param::PresencePenalty => Some(profile.presence_penalty),
+        Param::MaxTokens => profile.max_tokens.map(|tokens| tokens as f32),
+        Param::StopSequences => None,
+        Param::ThinkingBudget => profile.thinking_budget.map(|tokens| tokens as f32),
+    }
+}
+
+/// Writes the profile's values for the parameters in `specs` into `body`.
+/// `default_max_tokens` is sent when the profile leaves the reply length
+/// open, for APIs that require one.
+pub fn apply(body: &mut Value, specs: &[ParamSpec], profile: &Profile, default_max_tokens: Option<u32>) {
+    for spec in specs {
+        let clamp = |value: f32| value.clamp(spec.min, spec.max);
+        let value = match spec.param {
+            Param::TopK => json!(clamp(profile.top_k as f32) as u32),
+            Param::MaxTokens => match profile.max_tokens.or(default_max_tokens) {
+                Some(tokens) => json!(clamp(tokens as f32) as u32),
+                None => continue,
+            },
+            Param::ThinkingBudget => match profile.thinking_budget {
+                Some(tokens) => json!(clamp(tokens as f32) as u32),
+                None => continue,
+            },
+            Param::StopSequences => {
+                if profile.stop_sequences.is_empty() {
+                    continue;
+                }
+                json!(profile.stop_sequences.iter().take(spec.max as usize).collect::<Vec<_>>())
+            }
+            param => match profile_value(profile, param) {
+                Some(value) => json!(clamp(value) + spec.offset),
+                None => continue,
+            },
+        };
+        set_field(body, spec.field, value);
+    }
+}
+
+fn set_field(body: &mut Value, field: &str, value: Value) {
+    let mut target = body;
+    for part in field.split('/') {
+        // Indexing a missing key inserts it, creating nested objects as needed.
+        target = &mut target[part];
+    }
+    *target = value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_the_frequency_penalty_to_ollama_as_repeat_penalty() {
+        let mut body = json!({});
+        apply(&mut body, OLLAMA, &Profile { frequency_penalty: 0.5, ..Profile::default() }, None);
+        assert_eq!(body["options"]["repeat_penalty"], 1.5);
+        assert!(body["options"].get("frequency_penalty").is_none());
+
+        apply(&mut body, OLLAMA, &Profile { frequency_penalty: -2.0, ..Profile::default() }, None);
+        assert_eq!(body["options"]["repeat_penalty"], 0.0);
+    }
+}
@@ -0,0 +1,61 @@
+use super::stream_event::Usage;
+
+/// USD per million input and output tokens, by model name prefix. The
+/// longest matching prefix wins, so "gpt-4o-mini" isn't billed as "gpt-4o".
+/// Prices are list prices and need updating when providers change them.
+const PRICES: &[(&str, f64, f64)] = &[
+    // Anthropic
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-opus", 15.0, 75.0),
+    ("claude-opus-4", 15.0, 75.0),
+    // OpenAI
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("gpt-4-turbo", 10.0, 30.0),
+    ("gpt-4", 30.0, 60.0),
+    ("gpt-3.5-turbo", 0.5, 1.5),
+    ("o1", 15.0, 60.0),
+    ("o1-mini", 3.0, 12.0),
+    ("o3-mini", 1.1, 4.4),
+    // Google
+    ("gemini-1.5-pro", 1.25, 5.0),
+    ("gemini-1.5-flash", 0.075, 0.3),
+    ("gemini-2.0-flash", 0.1, 0.4),
+    // Fireworks
+    ("llama-v3p1-405b", 3.0, 3.0),
+    ("llama-v3p1-70b", 0.9, 0.9),
+    ("llama-v3p1-8b", 0.2, 0.2),
+    ("qwen2p5-72b", 0.9, 0.9),
+];
+
+/// Cost of a response, or `None` when the model isn't in the price table or
+/// the provider didn't report usage. Models run by Ollama are free.
+pub fn cost_usd(provider: &str, model: &str, usage: &Usage) -> Option<f64> {
+    if usage.is_empty() {
+        return None;
+    }
+    if provider == super::ollama::INFO.display_name {
+        return Some(0.0);
+    }
+    // Fireworks models are listed both with and without their account path.
+    let model = model.trim_start_matches("accounts/fireworks/models/");
+    let (_, input_price, output_price) = PRICES.iter()
+        .filter(|(prefix, _, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _, _)| prefix.len())?;
+    let tokens = |count: Option<u64>| count.unwrap_or(0) as f64 / 1_000_000.0;
+    Some(tokens(usage.input_tokens) * input_price + tokens(usage.output_tokens) * output_price)
+}
+
+pub fn format_cost(cost_usd: f64) -> String {
+    if cost_usd == 0.0 {
+        "$0".to_string()
+    } else if cost_usd < 0.01 {
+        format!("${:.4}", cost_usd)
+    } else {
+        format!("${:.2}", cost_usd)
+    }
+}
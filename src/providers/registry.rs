@@ -0,0 +1,45 @@
+use super::{claude, fireworks, gemini, gpt, ollama, ProviderTrait};
+use super::retry::RetryPolicy;
+use super::network::NetworkConfig;
+
+/// Per-instance settings handed to a provider's constructor.
+#[derive(Clone, Default)]
+pub struct ProviderConfig {
+    pub api_key: String,
+    pub base_url: String,
+    pub retry: RetryPolicy,
+    pub network: NetworkConfig,
+    /// Extra headers sent with every request, one `Name: value` per line.
+    pub headers: String,
+}
+
+/// What a provider supports beyond plain chat streaming.
+#[derive(Clone, Copy)]
+pub struct Capabilities {
+    pub lists_models: bool,
+}
+
+/// Static description of a built-in provider. Settings, keyring storage and
+/// the provider dropdown are all driven from these entries, so adding a
+/// provider means writing its module and listing its `INFO` in `PROVIDERS`.
+pub struct ProviderInfo {
+    pub id: &'static str,
+    pub display_name: &'static str,
+    /// Keyring entry holding the API key, or `None` for providers that need no key.
+    pub key_name: Option<&'static str>,
+    pub default_url: &'static str,
+    pub capabilities: Capabilities,
+    pub build: fn(ProviderConfig) -> Box<dyn ProviderTrait + Send + Sync>,
+}
+
+pub const PROVIDERS: &[ProviderInfo] = &[
+    fireworks::INFO,
+    claude::INFO,
+    gpt::INFO,
+    gemini::INFO,
+    ollama::INFO,
+];
+
+pub fn find_info(display_name: &str) -> Option<&'static ProviderInfo> {
+    PROVIDERS.iter().find(|info| info.display_name == display_name)
+}
@@ -0,0 +1,198 @@
+use super::stream_event::StreamEvent;
+use super::ProviderError;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// How often and how long to wait before re-sending a chat request that
+/// failed with a rate limit, an overload or a dropped connection.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total number of tries, including the first one.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// How long to wait for a response to start before the try counts as failed.
+    pub response_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ATTEMPTS)
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            response_timeout: None,
+        }
+    }
+
+    pub fn with_response_timeout(self, response_timeout: Option<Duration>) -> Self {
+        Self { response_timeout, ..self }
+    }
+
+    /// Exponential backoff with "equal jitter": half the doubled delay is
+    /// fixed, the other half random, so clients that failed together spread out.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(1 << (attempt - 1).min(16)).min(self.max_delay);
+        let half = exponential / 2;
+        half + half.mul_f64(jitter())
+    }
+}
+
+// A value in [0, 1). Good enough to de-synchronize retries without pulling in a RNG crate.
+fn jitter() -> f64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    f64::from(nanos % 1_000_000) / 1_000_000.0
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    // 529 is Anthropic's "overloaded"; it's inside the 5xx range but worth naming.
+    status == StatusCode::TOO_MANY_REQUESTS || status.as_u16() == 529 || status.is_server_error()
+}
+
+/// Reads `retry-after-ms` (OpenAI) or `Retry-After` in either of its forms:
+/// delay seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = headers.get("retry-after-ms").and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO))
+}
+
+/// Sends `request`, retrying retryable failures according to `policy`. Each
+/// wait is announced on `tx` as a `StreamEvent::Retrying` so the chat can show it.
+pub async fn send_with_retry(request: RequestBuilder, policy: RetryPolicy, tx: &mpsc::Sender<StreamEvent>) -> Result<Response, ProviderError> {
+    let mut attempt = 1;
+    loop {
+        // Requests with a streaming body can't be cloned; those get a single try.
+        let Some(this_try) = request.try_clone() else {
+            return check_status(request.send().await.map_err(|e| ProviderError::RequestError(e.to_string()))?).await;
+        };
+
+        // `None` when the response didn't start within the policy's timeout.
+        let sent = match policy.response_timeout {
+            Some(limit) => tokio::time::timeout(limit, this_try.send()).await.ok(),
+            None => Some(this_try.send().await),
+        };
+
+        let failure = match sent {
+            Some(Ok(response)) if response.status().is_success() => return Ok(response),
+            Some(Ok(response)) if is_retryable(response.status()) && attempt < policy.max_attempts => {
+                let status = response.status();
+                let reason = status.canonical_reason().map_or_else(|| status.to_string(), |r| format!("{} {}", status.as_u16(), r));
+                (reason, retry_after(response.headers()))
+            }
+            Some(Ok(response)) => return check_status(response).await,
+            Some(Err(e)) if (e.is_connect() || e.is_timeout()) && attempt < policy.max_attempts => {
+                ("connection failed".to_string(), None)
+            }
+            Some(Err(e)) => return Err(ProviderError::RequestError(e.to_string())),
+            None if attempt < policy.max_attempts => ("timed out".to_string(), None),
+            None => return Err(ProviderError::RequestError("The provider didn't respond in time".to_string())),
+        };
+
+        retry_after_delay(failure, policy, attempt, tx).await?;
+        attempt += 1;
+    }
+}
+
+/// Announces the coming retry of a failed try and waits out its delay.
+async fn retry_after_delay((reason, server_delay): (String, Option<Duration>), policy: RetryPolicy, attempt: u32, tx: &mpsc::Sender<StreamEvent>) -> Result<(), ProviderError> {
+    let delay = server_delay.unwrap_or_else(|| policy.backoff(attempt)).min(policy.max_delay);
+    println!("Debug: Request failed ({}), retrying in {:.1}s (attempt {} of {})", reason, delay.as_secs_f32(), attempt + 1, policy.max_attempts);
+    let retrying = StreamEvent::Retrying { reason, delay, attempt: attempt + 1, max_attempts: policy.max_attempts };
+    if tx.send(retrying).await.is_err() {
+        return Err(ProviderError::RequestError("Request was cancelled".to_string()));
+    }
+    tokio::time::sleep(delay).await;
+    Ok(())
+}
+
+async fn check_status(response: Response) -> Result<Response, ProviderError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let error_body = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+    Err(ProviderError::ResponseError(error_body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fixture_server::{Fixture, FixtureServer};
+    use reqwest::Client;
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { base_delay: Duration::from_millis(1), ..RetryPolicy::new(max_attempts) }
+    }
+
+    #[tokio::test]
+    async fn retries_a_server_error_then_succeeds() {
+        let server = FixtureServer::start(vec![
+            Fixture::status(529, r#"{"error":{"message":"Overloaded"}}"#).with_header("retry-after-ms", "5"),
+            Fixture::status(200, "{}"),
+        ]).await;
+        let (tx, mut rx) = mpsc::channel(8);
+
+        let response = send_with_retry(Client::new().post(&server.base_url).body("{}"), fast_policy(3), &tx).await;
+        assert!(response.is_ok());
+        assert_eq!(server.requests().len(), 2);
+        assert!(matches!(rx.try_recv(), Ok(StreamEvent::Retrying { attempt: 2, max_attempts: 3, .. })));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let server = FixtureServer::start(vec![Fixture::status(503, "busy"), Fixture::status(503, "still busy")]).await;
+        let (tx, _rx) = mpsc::channel(8);
+
+        let response = send_with_retry(Client::new().post(&server.base_url).body("{}"), fast_policy(2), &tx).await;
+        assert!(matches!(response, Err(ProviderError::ResponseError(body)) if body == "still busy"));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_a_client_error() {
+        let server = FixtureServer::start(vec![Fixture::status(400, "bad request"), Fixture::status(200, "{}")]).await;
+        let (tx, _rx) = mpsc::channel(8);
+
+        let response = send_with_retry(Client::new().post(&server.base_url).body("{}"), fast_policy(3), &tx).await;
+        assert!(matches!(response, Err(ProviderError::ResponseError(body)) if body == "bad request"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_slow_response_counts_as_a_failed_try() {
+        let server = FixtureServer::start(vec![
+            Fixture::status(200, "{}").with_delay(Duration::from_secs(5)),
+            Fixture::status(200, "{}"),
+        ]).await;
+        let (tx, mut rx) = mpsc::channel(8);
+        let policy = fast_policy(2).with_response_timeout(Some(Duration::from_millis(100)));
+
+        let response = send_with_retry(Client::new().post(&server.base_url).body("{}"), policy, &tx).await;
+        assert!(response.is_ok());
+        assert!(matches!(rx.try_recv(), Ok(StreamEvent::Retrying { reason, .. }) if reason == "timed out"));
+    }
+
+    #[test]
+    fn reads_retry_after_in_seconds_and_milliseconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert(reqwest::header::RETRY_AFTER, "2".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+        headers.insert("retry-after-ms", "250".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(250)));
+    }
+}
@@ -0,0 +1,328 @@
+use super::stream_event::{forward, StreamEvent};
+use super::ProviderError;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+
+/// Splits a byte stream into lines. Bytes are buffered until a terminator
+/// arrives, so characters split across network chunks are never cut in half.
+/// Lines may end in LF, CRLF or a lone CR.
+#[derive(Default)]
+pub struct LineDecoder {
+    buffer: Vec<u8>,
+    // The previous chunk ended in CR, so a leading LF belongs to that line.
+    skip_lf: bool,
+}
+
+impl LineDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        let mut bytes = bytes;
+        if self.skip_lf && !bytes.is_empty() {
+            if bytes[0] == b'\n' {
+                bytes = &bytes[1..];
+            }
+            self.skip_lf = false;
+        }
+        self.buffer.extend_from_slice(bytes);
+
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+        while i < self.buffer.len() {
+            match self.buffer[i] {
+                b'\n' => {
+                    lines.push(String::from_utf8_lossy(&self.buffer[start..i]).into_owned());
+                    start = i + 1;
+                }
+                b'\r' => {
+                    lines.push(String::from_utf8_lossy(&self.buffer[start..i]).into_owned());
+                    match self.buffer.get(i + 1) {
+                        Some(b'\n') => i += 1,
+                        Some(_) => {}
+                        None => self.skip_lf = true,
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        self.buffer.drain(..start);
+        lines
+    }
+
+    /// Returns whatever is left once the stream has ended without a final terminator.
+    pub fn finish(&mut self) -> Option<String> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let line = String::from_utf8_lossy(&self.buffer).into_owned();
+        self.buffer.clear();
+        Some(line)
+    }
+}
+
+/// One dispatched server-sent event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    /// The `event:` field, `None` for the default "message" type.
+    pub event: Option<String>,
+    pub data: String,
+    pub id: Option<String>,
+    pub retry: Option<u64>,
+}
+
+/// Decoder for the `text/event-stream` format as described in the HTML spec:
+/// `event`, `data`, `id` and `retry` fields, comment lines, multi-line data
+/// and a leading byte order mark.
+#[derive(Default)]
+pub struct SseDecoder {
+    lines: LineDecoder,
+    started: bool,
+    event: Option<String>,
+    data: String,
+    has_data: bool,
+    last_id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        for line in self.lines.feed(bytes) {
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn process_line(&mut self, mut line: String) -> Option<SseEvent> {
+        if !self.started {
+            self.started = true;
+            if let Some(rest) = line.strip_prefix('\u{feff}') {
+                line = rest.to_string();
+            }
+        }
+
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_str(), ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+                self.has_data = true;
+            }
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(retry) = value.parse() {
+                    self.retry = Some(retry);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if !self.has_data {
+            return None;
+        }
+        self.has_data = false;
+        Some(SseEvent {
+            event,
+            data: std::mem::take(&mut self.data),
+            id: self.last_id.clone(),
+            retry: self.retry,
+        })
+    }
+}
+
+/// Reads an event-stream response to the end, handing each event to `parse`
+/// and forwarding the resulting events to the chat.
+pub async fn forward_sse<F>(response: reqwest::Response, tx: &mpsc::Sender<StreamEvent>, mut parse: F)
+where
+    F: FnMut(&SseEvent) -> Vec<StreamEvent>,
+{
+    let mut stream = response.bytes_stream();
+    let mut decoder = SseDecoder::new();
+
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(chunk) => {
+                for event in decoder.feed(&chunk) {
+                    if !forward(tx, parse(&event)).await {
+                        return;
+                    }
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(StreamEvent::Error(ProviderError::StreamError(e.to_string()))).await;
+                return;
+            }
+        }
+    }
+    let _ = tx.send(StreamEvent::Done).await;
+}
+
+/// Like [`forward_sse`], for newline-delimited streams such as Ollama's.
+pub async fn forward_lines<F>(response: reqwest::Response, tx: &mpsc::Sender<StreamEvent>, mut parse: F)
+where
+    F: FnMut(&str) -> Vec<StreamEvent>,
+{
+    let mut stream = response.bytes_stream();
+    let mut decoder = LineDecoder::new();
+
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(chunk) => {
+                for line in decoder.feed(&chunk) {
+                    if !forward(tx, parse(&line)).await {
+                        return;
+                    }
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(StreamEvent::Error(ProviderError::StreamError(e.to_string()))).await;
+                return;
+            }
+        }
+    }
+    if let Some(line) = decoder.finish() {
+        if !forward(tx, parse(&line)).await {
+            return;
+        }
+    }
+    let _ = tx.send(StreamEvent::Done).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        chunks.iter().flat_map(|chunk| decoder.feed(chunk)).collect()
+    }
+
+    fn data(events: &[SseEvent]) -> Vec<&str> {
+        events.iter().map(|e| e.data.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_a_single_event() {
+        let events = feed_all(&[b"data: hello\n\n"]);
+        assert_eq!(events, vec![SseEvent { event: None, data: "hello".into(), id: None, retry: None }]);
+    }
+
+    #[test]
+    fn keeps_multibyte_characters_split_across_chunks() {
+        let bytes = "data: héllo 🦀\n\n".as_bytes();
+        // Split inside both the two-byte "é" and the four-byte crab.
+        let e = bytes.iter().position(|&b| b == 0xC3).unwrap() + 1;
+        let crab = bytes.iter().position(|&b| b == 0xF0).unwrap() + 2;
+        let events = feed_all(&[&bytes[..e], &bytes[e..crab], &bytes[crab..]]);
+        assert_eq!(data(&events), vec!["héllo 🦀"]);
+    }
+
+    #[test]
+    fn handles_lines_split_across_chunks() {
+        let events = feed_all(&[b"da", b"ta: {\"a\"", b": 1}", b"\n", b"\ndata: x\n\n"]);
+        assert_eq!(data(&events), vec!["{\"a\": 1}", "x"]);
+    }
+
+    #[test]
+    fn feeding_one_byte_at_a_time_gives_the_same_result() {
+        let input = "event: message_start\r\ndata: ünïcødé\r\n\r\ndata: two\r\n\r\n".as_bytes();
+        let chunks: Vec<&[u8]> = input.chunks(1).collect();
+        let events = feed_all(&chunks);
+        assert_eq!(data(&events), vec!["ünïcødé", "two"]);
+        assert_eq!(events[0].event.as_deref(), Some("message_start"));
+        assert_eq!(events[1].event, None);
+    }
+
+    #[test]
+    fn accepts_crlf_lf_and_cr_line_endings() {
+        let events = feed_all(&[b"data: a\r\n\r\ndata: b\n\ndata: c\r\rdata: d\r\n\n"]);
+        assert_eq!(data(&events), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn crlf_split_between_chunks_is_one_line_ending() {
+        let events = feed_all(&[b"data: a\r", b"\ndata: b\r", b"\n\r", b"\n"]);
+        assert_eq!(data(&events), vec!["a\nb"]);
+    }
+
+    #[test]
+    fn joins_multi_line_data_with_newlines() {
+        let events = feed_all(&[b"data: first\ndata:second\ndata\n\n"]);
+        assert_eq!(data(&events), vec!["first\nsecond\n"]);
+    }
+
+    #[test]
+    fn reads_event_id_and_retry_fields() {
+        let events = feed_all(&[b"event: ping\nid: 7\nretry: 3000\ndata: {}\n\ndata: next\n\n"]);
+        assert_eq!(events[0].event.as_deref(), Some("ping"));
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(events[0].retry, Some(3000));
+        // The last event id carries over; the event type does not.
+        assert_eq!(events[1].id.as_deref(), Some("7"));
+        assert_eq!(events[1].event, None);
+    }
+
+    #[test]
+    fn ignores_comments_unknown_fields_and_empty_events() {
+        let events = feed_all(&[b": keep-alive\n\nevent: ping\n\nfoo: bar\ndata: x\n\n"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "x");
+        assert_eq!(events[0].event, None);
+    }
+
+    #[test]
+    fn strips_only_one_leading_space() {
+        let events = feed_all(&[b"data:  two spaces\n\n"]);
+        assert_eq!(data(&events), vec![" two spaces"]);
+    }
+
+    #[test]
+    fn strips_a_leading_byte_order_mark() {
+        let events = feed_all(&[b"\xEF\xBB", b"\xBFdata: x\n\n"]);
+        assert_eq!(data(&events), vec!["x"]);
+    }
+
+    #[test]
+    fn does_not_dispatch_an_unterminated_event() {
+        let events = feed_all(&[b"data: partial\n"]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn line_decoder_keeps_the_unterminated_tail_for_finish() {
+        let mut decoder = LineDecoder::new();
+        let mut lines = decoder.feed(&"{\"a\":\"ö".as_bytes()[..7]);
+        lines.extend(decoder.feed(&"{\"a\":\"ö\"}\n{\"b\":1}".as_bytes()[7..]));
+        assert_eq!(lines, vec!["{\"a\":\"ö\"}"]);
+        assert_eq!(decoder.finish().as_deref(), Some("{\"b\":1}"));
+        assert_eq!(decoder.finish(), None);
+    }
+}
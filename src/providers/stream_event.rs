@@ -0,0 +1,118 @@
+use super::ProviderError;
+use crate::tools::ToolCall;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Token counts reported by a provider. Providers report them at different
+/// points of the stream (Claude sends input tokens first and output tokens
+/// last), so each field is optional and later reports fill in the gaps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+}
+
+impl Usage {
+    pub fn merge(&mut self, other: Usage) {
+        if other.input_tokens.is_some() {
+            self.input_tokens = other.input_tokens;
+        }
+        if other.output_tokens.is_some() {
+            self.output_tokens = other.output_tokens;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.input_tokens.is_none() && self.output_tokens.is_none()
+    }
+}
+
+/// Why a response ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StopReason {
+    EndTurn,
+    MaxTokens,
+    StopSequence,
+    ToolUse,
+    ContentFilter,
+    Cancelled,
+    Other(String),
+}
+
+impl StopReason {
+    /// Maps the finish reasons used by the Anthropic, OpenAI, Gemini and Ollama APIs.
+    pub fn from_api(reason: &str) -> Self {
+        match reason {
+            "end_turn" | "stop" | "STOP" => StopReason::EndTurn,
+            "max_tokens" | "length" | "MAX_TOKENS" => StopReason::MaxTokens,
+            "stop_sequence" => StopReason::StopSequence,
+            "tool_use" | "tool_calls" | "function_call" => StopReason::ToolUse,
+            "content_filter" | "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" => StopReason::ContentFilter,
+            other => StopReason::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::EndTurn => write!(f, "finished"),
+            StopReason::MaxTokens => write!(f, "max tokens reached"),
+            StopReason::StopSequence => write!(f, "stop sequence"),
+            StopReason::ToolUse => write!(f, "tool use"),
+            StopReason::ContentFilter => write!(f, "content filter"),
+            StopReason::Cancelled => write!(f, "stopped by user"),
+            StopReason::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// One item of a streamed response. Errors travel as their own variant so
+/// they can never be mistaken for (and saved as) assistant text.
+#[derive(Debug)]
+pub enum StreamEvent {
+    TextDelta(String),
+    /// What the model thinks before it answers; kept apart from the answer.
+    ReasoningDelta(String),
+    /// Claude's signature over the reasoning so far.
+    ReasoningSignature(String),
+    /// A complete call, sent once all of its arguments have arrived.
+    ToolCall(ToolCall),
+    Usage(Usage),
+    StopReason(StopReason),
+    Error(ProviderError),
+    /// The request failed in a way worth retrying; it will be re-sent after `delay`.
+    Retrying { reason: String, delay: Duration, attempt: u32, max_attempts: u32 },
+    Done,
+}
+
+/// Runs a provider's request on its own task. When the chat drops the
+/// receiving end (the user pressed Stop) the task is dropped at its next
+/// await point, which closes the HTTP connection instead of reading the
+/// response to the end.
+pub fn spawn_stream<F>(tx: mpsc::Sender<StreamEvent>, request: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::task::spawn(async move {
+        tokio::select! {
+            _ = request => {}
+            _ = tx.closed() => println!("Debug: Response cancelled, closing the connection"),
+        }
+    });
+}
+
+/// Sends `events` on to the chat. Returns `false` once the stream is over,
+/// either because the receiver hung up or because a final event was sent.
+pub async fn forward(tx: &mpsc::Sender<StreamEvent>, events: Vec<StreamEvent>) -> bool {
+    for event in events {
+        let is_final = matches!(event, StreamEvent::Done | StreamEvent::Error(_));
+        if tx.send(event).await.is_err() || is_final {
+            return false;
+        }
+    }
+    true
+}
@@ -0,0 +1,109 @@
+use super::stream_event::Usage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USAGE_LEDGER_FILE: &str = "usage.json";
+
+// Responses can finish on several chats' runtimes at once; serialize the read-modify-write.
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+// Counts writes, so that views of the ledger know when to reload it.
+static REVISION: AtomicU64 = AtomicU64::new(0);
+
+/// Totals for one provider in one month.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ProviderUsage {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    /// Requests whose model isn't in the price table, so `cost_usd` is a lower bound.
+    pub unpriced_requests: u64,
+}
+
+/// Spend per month ("2024-07") and provider, kept across chats so deleting a
+/// chat doesn't hide what it cost.
+#[derive(Serialize, Deserialize, Default)]
+pub struct UsageLedger {
+    months: BTreeMap<String, BTreeMap<String, ProviderUsage>>,
+}
+
+impl UsageLedger {
+    pub fn load() -> Self {
+        std::fs::read_to_string(USAGE_LEDGER_FILE)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) {
+        match serde_json::to_string_pretty(self) {
+            Ok(content) => {
+                if let Err(e) = std::fs::write(USAGE_LEDGER_FILE, content) {
+                    eprintln!("Failed to write usage ledger: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Failed to serialize usage ledger: {}", e),
+        }
+    }
+
+    pub fn month(&self, month: &str) -> Option<&BTreeMap<String, ProviderUsage>> {
+        self.months.get(month)
+    }
+}
+
+/// The ledger as last read, read again only after `record` has written it.
+#[derive(Default)]
+pub struct CachedLedger {
+    revision: Option<u64>,
+    ledger: UsageLedger,
+}
+
+impl CachedLedger {
+    pub fn get(&mut self) -> &UsageLedger {
+        let revision = REVISION.load(Ordering::Relaxed);
+        if self.revision != Some(revision) {
+            self.ledger = UsageLedger::load();
+            self.revision = Some(revision);
+        }
+        &self.ledger
+    }
+
+    /// Makes the next `get` read the file, e.g. for changes by another instance.
+    pub fn invalidate(&mut self) {
+        self.revision = None;
+    }
+}
+
+/// Adds one response to the current month's totals for `provider`.
+pub fn record(provider: &str, usage: &Usage, cost_usd: Option<f64>) {
+    let _guard = LEDGER_LOCK.lock().unwrap();
+    let mut ledger = UsageLedger::load();
+    let entry = ledger.months.entry(current_month()).or_default().entry(provider.to_string()).or_default();
+    entry.requests += 1;
+    entry.input_tokens += usage.input_tokens.unwrap_or(0);
+    entry.output_tokens += usage.output_tokens.unwrap_or(0);
+    match cost_usd {
+        Some(cost) => entry.cost_usd += cost,
+        None => entry.unpriced_requests += 1,
+    }
+    ledger.save();
+    REVISION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The current month in UTC as "YYYY-MM".
+pub fn current_month() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 86_400).unwrap_or(0) as i64;
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}", year, month)
+}
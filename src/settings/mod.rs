@@ -2,52 +2,191 @@ mod settings_ui;
 mod settings_operations;
 
 use crate::ui::themes::{Theme, get_themes};
-use crate::providers::ProviderTrait;
+use crate::providers::{ProviderTrait, EndpointConfig, NetworkConfig, ProviderConfig, ProviderInfo, RetryPolicy, PROVIDERS};
+use crate::providers::retry::DEFAULT_MAX_ATTEMPTS;
+use crate::providers::registry;
+use crate::chat::compaction::{self, CompactionSettings};
+use crate::app::{Profile, DEFAULT_PROFILE};
+use crate::tools::Toolbox;
+use crate::providers::usage_ledger::CachedLedger;
+use crate::tools::mcp::{McpManager, McpServerConfig};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
 
 pub struct Settings {
     pub show_settings: bool,
-    api_keys: Arc<Mutex<ApiKeys>>,
+    /// API keys by provider id.
+    api_keys: Arc<Mutex<HashMap<String, String>>>,
+    /// Base URL overrides by provider id.
+    pub base_urls: HashMap<String, String>,
+    /// Extra request headers by provider id, one `Name: value` per line.
+    pub extra_headers: HashMap<String, String>,
+    /// Proxy, trusted certificates and timeout applied to every provider.
+    pub network: NetworkConfig,
+    pub network_error: Option<String>,
     themes: Vec<Theme>,
     current_theme_index: usize,
     pub api_keys_updated: bool,
     pub ui_scale: f32,
     pub temp_ui_scale: f32,
-}
-
-struct ApiKeys {
-    fireworks: String,
-    claude: String,
-    gpt: String,
+    pub endpoints: Vec<EndpointConfig>,
+    saved_endpoint_names: Vec<String>,
+    pub endpoint_error: Option<String>,
+    pub refresh_models_requested: bool,
+    /// Tries per chat request, including the first, before an error is shown.
+    pub max_attempts: u32,
+    /// System prompt new chats start with.
+    pub default_system_prompt: String,
+    /// Summarize older turns once a chat fills `compaction_threshold` of the context window.
+    pub compaction_enabled: bool,
+    pub compaction_threshold: f32,
+    /// Provider and model that write the summaries; empty means the chat's own.
+    pub compaction_provider: String,
+    pub compaction_model: String,
+    /// Continue replies cut off at the token limit without waiting for a click.
+    pub auto_continue: bool,
+    /// Offer the built-in tools to models that can call them.
+    pub tools_enabled: bool,
+    /// The only directory the file tools may read; empty leaves them out.
+    pub tool_directory: String,
+    pub tools_error: Option<String>,
+    /// MCP servers whose tools are offered alongside the built-in ones.
+    pub mcp_servers: Vec<McpServerConfig>,
+    saved_mcp_server_names: Vec<String>,
+    pub mcp_error: Option<String>,
+    pub mcp: McpManager,
+    /// Generation profiles, listed in the bottom panel.
+    pub profiles: Vec<Profile>,
+    saved_profile_names: Vec<String>,
+    pub profile_error: Option<String>,
+    /// Name of the profile applied to every provider.
+    pub current_profile: String,
+    /// The ledger shown under "Usage this month".
+    usage_ledger: CachedLedger,
 }
 
 impl Settings {
     pub fn new() -> Self {
         let themes = get_themes();
-        let api_keys = Arc::new(Mutex::new(ApiKeys {
-            fireworks: String::new(),
-            claude: String::new(),
-            gpt: String::new(),
-        }));
         let mut settings = Self {
             show_settings: false,
-            api_keys,
+            api_keys: Arc::new(Mutex::new(HashMap::new())),
+            base_urls: HashMap::new(),
+            extra_headers: HashMap::new(),
+            network: NetworkConfig::default(),
+            network_error: None,
             themes,
             current_theme_index: 0,
             api_keys_updated: false,
             ui_scale: 1.0,
             temp_ui_scale: 1.0,
+            endpoints: Vec::new(),
+            saved_endpoint_names: Vec::new(),
+            endpoint_error: None,
+            refresh_models_requested: false,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            default_system_prompt: String::new(),
+            compaction_enabled: false,
+            compaction_threshold: compaction::DEFAULT_THRESHOLD,
+            compaction_provider: String::new(),
+            compaction_model: String::new(),
+            auto_continue: false,
+            tools_enabled: false,
+            tool_directory: String::new(),
+            tools_error: None,
+            mcp_servers: Vec::new(),
+            saved_mcp_server_names: Vec::new(),
+            mcp_error: None,
+            mcp: McpManager::default(),
+            profiles: Vec::new(),
+            saved_profile_names: Vec::new(),
+            profile_error: None,
+            current_profile: DEFAULT_PROFILE.to_string(),
+            usage_ledger: CachedLedger::default(),
         };
         settings_operations::load_api_keys(&mut settings);
         settings_operations::load_theme(&mut settings);
         settings_operations::load_ui_scale(&mut settings);
+        settings_operations::load_base_urls(&mut settings);
+        settings_operations::load_endpoints(&mut settings);
+        settings_operations::load_network(&mut settings);
+        // Providers refuse to connect with settings that don't apply, so say why up front.
+        settings.network_error = settings_operations::validate_network(&settings).err();
+        settings_operations::load_max_attempts(&mut settings);
+        settings_operations::load_default_system_prompt(&mut settings);
+        settings_operations::load_compaction(&mut settings);
+        settings_operations::load_profiles(&mut settings);
+        settings_operations::load_auto_continue(&mut settings);
+        settings_operations::load_tools(&mut settings);
+        settings_operations::load_mcp_servers(&mut settings);
         settings.temp_ui_scale = settings.ui_scale;
         settings
     }
 
-    pub fn get_api_keys(&self) -> String {
+    pub fn get_provider_configs(&self) -> HashMap<String, ProviderConfig> {
         let keys = self.api_keys.lock().unwrap();
-        format!("{},{},{}", keys.fireworks, keys.claude, keys.gpt)
+        PROVIDERS.iter().map(|info| {
+            let base_url = self.base_urls.get(info.id)
+                .filter(|url| !url.trim().is_empty())
+                .cloned()
+                .unwrap_or_else(|| info.default_url.to_string());
+            (info.id.to_string(), ProviderConfig {
+                api_key: keys.get(info.id).cloned().unwrap_or_default(),
+                base_url,
+                retry: self.get_retry_policy(),
+                network: self.network.clone(),
+                headers: self.extra_headers.get(info.id).cloned().unwrap_or_default(),
+            })
+        }).collect()
+    }
+
+    pub fn get_retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::new(self.max_attempts).with_response_timeout(self.network.timeout())
+    }
+
+    pub fn get_compaction_settings(&self, providers: &[Arc<dyn ProviderTrait + Send + Sync>]) -> CompactionSettings {
+        let provider = providers.iter().find(|p| p.name() == self.compaction_provider).map(Arc::clone);
+        if provider.is_none() && !self.compaction_provider.is_empty() {
+            println!("Debug: Compaction provider {} not found, using the chat's provider", self.compaction_provider);
+        }
+        CompactionSettings {
+            enabled: self.compaction_enabled,
+            threshold: self.compaction_threshold,
+            provider,
+            model: Some(self.compaction_model.trim().to_string()).filter(|m| !m.is_empty()),
+        }
+    }
+
+    /// The tools chats offer to the model; none unless tools are enabled.
+    pub fn get_toolbox(&self) -> Toolbox {
+        if !self.tools_enabled {
+            return Toolbox::default();
+        }
+        let directory = self.tool_directory.trim();
+        let toolbox = Toolbox::builtin((!directory.is_empty()).then(|| PathBuf::from(directory)));
+        self.mcp.tools().into_iter().fold(toolbox, Toolbox::with)
+    }
+
+    /// Launches the saved MCP servers, or stops them all if tools are off.
+    pub fn start_mcp_servers(&self, runtime: &Runtime) {
+        let servers = if self.tools_enabled { self.mcp_servers.as_slice() } else { &[] };
+        self.mcp.connect(servers, runtime);
+    }
+
+    /// The selected profile, or the first one if it has been deleted.
+    pub fn get_current_profile(&self) -> Profile {
+        self.profiles.iter()
+            .find(|p| p.name == self.current_profile)
+            .or_else(|| self.profiles.first())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn select_profile(&mut self, name: &str) {
+        self.current_profile = name.to_string();
+        settings_operations::save_current_profile(self);
     }
 
     pub fn get_current_theme(&self) -> &Theme {
@@ -56,15 +195,12 @@ impl Settings {
 
     pub fn get_first_provider_with_key(&self, providers: &[Arc<dyn ProviderTrait + Send + Sync>]) -> Arc<dyn ProviderTrait + Send + Sync> {
         let keys = self.api_keys.lock().unwrap();
-        if !keys.fireworks.is_empty() {
-            return Arc::clone(&providers[1]);
-        } else if !keys.claude.is_empty() {
-            return Arc::clone(&providers[2]);
-        } else if !keys.gpt.is_empty() {
-            return Arc::clone(&providers[3]);
-        }
-        Arc::clone(&providers[0])
+        let has_key = |info: &ProviderInfo| info.key_name.is_some() && keys.get(info.id).is_some_and(|key| !key.is_empty());
+        providers.iter()
+            .find(|p| registry::find_info(p.name()).is_some_and(has_key))
+            .map(Arc::clone)
+            .unwrap_or_else(|| Arc::clone(&providers[0]))
     }
 }
 
-pub use settings_ui::render;
\ No newline at end of file
+pub use settings_ui::{render, stop_sequences_editor, CurrentModel};
\ No newline at end of file
@@ -2,36 +2,256 @@ use keyring::Entry;
 use super::Settings;
 use std::path::Path;
 use std::collections::HashMap;
+use crate::providers::{EndpointConfig, PROVIDERS};
+use crate::providers::network;
+use crate::providers::registry;
+use crate::app::Profile;
+use crate::tools::mcp::McpServerConfig;
 
 const SETTINGS_FILE: &str = "settings.ini";
+const ENDPOINT_SECTION_PREFIX: &str = "Endpoint:";
+const PROFILE_SECTION_PREFIX: &str = "Profile:";
+const MCP_SECTION_PREFIX: &str = "McpServer:";
 
 pub fn load_api_keys(settings: &mut Settings) {
     let mut keys = settings.api_keys.lock().unwrap();
-    if let Ok(entry) = Entry::new("rust_chatbot", "fireworks_api_key") {
-        keys.fireworks = entry.get_password().unwrap_or_default();
-    }
-    if let Ok(entry) = Entry::new("rust_chatbot", "claude_api_key") {
-        keys.claude = entry.get_password().unwrap_or_default();
-    }
-    if let Ok(entry) = Entry::new("rust_chatbot", "gpt_api_key") {
-        keys.gpt = entry.get_password().unwrap_or_default();
+    for info in PROVIDERS {
+        if let Some(Ok(entry)) = info.key_name.map(|key_name| Entry::new("rust_chatbot", key_name)) {
+            keys.insert(info.id.to_string(), entry.get_password().unwrap_or_default());
+        }
     }
 }
 
 pub fn save_api_keys(settings: &mut Settings) {
     let keys = settings.api_keys.lock().unwrap();
-    if let Ok(entry) = Entry::new("rust_chatbot", "fireworks_api_key") {
-        let _ = entry.set_password(&keys.fireworks);
+    for info in PROVIDERS {
+        if let Some(Ok(entry)) = info.key_name.map(|key_name| Entry::new("rust_chatbot", key_name)) {
+            let _ = entry.set_password(keys.get(info.id).map(String::as_str).unwrap_or_default());
+        }
+    }
+    settings.api_keys_updated = true;
+}
+
+pub fn load_base_urls(settings: &mut Settings) {
+    for info in PROVIDERS {
+        let url = get_ini_value("Settings", &format!("{}_url", info.id))
+            .unwrap_or_else(|| info.default_url.to_string());
+        settings.base_urls.insert(info.id.to_string(), url);
+    }
+}
+
+pub fn save_base_urls(settings: &Settings) {
+    for info in PROVIDERS {
+        if let Some(url) = settings.base_urls.get(info.id) {
+            set_ini_value("Settings", &format!("{}_url", info.id), url.trim());
+        }
+    }
+}
+
+pub fn load_endpoints(settings: &mut Settings) {
+    settings.endpoints = get_ini_sections_with_prefix(ENDPOINT_SECTION_PREFIX)
+        .into_iter()
+        .map(|(name, keys)| {
+            let api_key = Entry::new("rust_chatbot", &endpoint_key_name(&name))
+                .and_then(|entry| entry.get_password())
+                .unwrap_or_default();
+            EndpointConfig {
+                base_url: keys.get("base_url").cloned().unwrap_or_default(),
+                models: keys.get("models").cloned().unwrap_or_default(),
+                headers: keys.get("headers").map(|headers| unescape_ini_value(headers)).unwrap_or_default(),
+                api_key,
+                name,
+            }
+        })
+        .collect();
+    settings.saved_endpoint_names = settings.endpoints.iter().map(|e| e.name.clone()).collect();
+}
+
+pub fn save_endpoints(settings: &mut Settings) {
+    for removed in settings.saved_endpoint_names.iter().filter(|name| !settings.endpoints.iter().any(|e| &e.name == *name)) {
+        remove_ini_section(&format!("{}{}", ENDPOINT_SECTION_PREFIX, removed));
+        if let Ok(entry) = Entry::new("rust_chatbot", &endpoint_key_name(removed)) {
+            let _ = entry.delete_password();
+        }
+    }
+
+    for endpoint in settings.endpoints.iter_mut() {
+        endpoint.name = endpoint.name.trim().to_string();
+        let section = format!("{}{}", ENDPOINT_SECTION_PREFIX, endpoint.name);
+        set_ini_value(&section, "base_url", &endpoint.base_url);
+        set_ini_value(&section, "models", &endpoint.models);
+        set_ini_value(&section, "headers", &escape_ini_value(endpoint.headers.trim()));
+        if let Ok(entry) = Entry::new("rust_chatbot", &endpoint_key_name(&endpoint.name)) {
+            let _ = entry.set_password(&endpoint.api_key);
+        }
+    }
+    settings.saved_endpoint_names = settings.endpoints.iter().map(|e| e.name.clone()).collect();
+    settings.api_keys_updated = true;
+}
+
+pub fn validate_endpoints(endpoints: &[EndpointConfig]) -> Result<(), String> {
+    for (index, endpoint) in endpoints.iter().enumerate() {
+        let name = endpoint.name.trim();
+        if name.is_empty() {
+            return Err("Every endpoint needs a name.".to_string());
+        }
+        if name.contains(['[', ']']) {
+            return Err(format!("Endpoint name \"{}\" can't contain brackets.", name));
+        }
+        if name == "Select a provider" || registry::find_info(name).is_some() || endpoints[..index].iter().any(|e| e.name.trim() == name) {
+            return Err(format!("Endpoint name \"{}\" is already in use.", name));
+        }
+        if endpoint.base_url.trim().is_empty() {
+            return Err(format!("Endpoint \"{}\" needs a base URL.", name));
+        }
+        network::parse_headers(&endpoint.headers).map_err(|e| format!("Endpoint \"{}\": {}", name, e))?;
+    }
+    Ok(())
+}
+
+pub fn load_network(settings: &mut Settings) {
+    if let Some(proxy) = get_ini_value("Network", "proxy") {
+        settings.network.proxy = proxy;
+    }
+    if let Some(no_proxy) = get_ini_value("Network", "no_proxy") {
+        settings.network.no_proxy = no_proxy;
+    }
+    if let Some(ca_file) = get_ini_value("Network", "ca_file") {
+        settings.network.ca_file = ca_file;
+    }
+    if let Some(timeout) = get_ini_value("Network", "timeout_secs") {
+        if let Ok(timeout) = timeout.parse::<u32>() {
+            settings.network.timeout_secs = timeout.min(600);
+        }
+    }
+    for info in PROVIDERS {
+        if let Some(headers) = get_ini_value("Network", &format!("{}_headers", info.id)) {
+            settings.extra_headers.insert(info.id.to_string(), unescape_ini_value(&headers));
+        }
     }
-    if let Ok(entry) = Entry::new("rust_chatbot", "claude_api_key") {
-        let _ = entry.set_password(&keys.claude);
+}
+
+pub fn save_network(settings: &Settings) {
+    set_ini_value("Network", "proxy", settings.network.proxy.trim());
+    set_ini_value("Network", "no_proxy", settings.network.no_proxy.trim());
+    set_ini_value("Network", "ca_file", settings.network.ca_file.trim());
+    set_ini_value("Network", "timeout_secs", &settings.network.timeout_secs.to_string());
+    for info in PROVIDERS {
+        if let Some(headers) = settings.extra_headers.get(info.id) {
+            set_ini_value("Network", &format!("{}_headers", info.id), &escape_ini_value(headers.trim()));
+        }
+    }
+}
+
+/// Checks that the proxy, the certificate file and every provider's headers are usable.
+pub fn validate_network(settings: &Settings) -> Result<(), String> {
+    network::build_client(&settings.network, "")?;
+    for info in PROVIDERS {
+        if let Some(headers) = settings.extra_headers.get(info.id) {
+            network::parse_headers(headers).map_err(|e| format!("{}: {}", info.display_name, e))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn load_profiles(settings: &mut Settings) {
+    let sections = get_ini_sections_with_prefix(PROFILE_SECTION_PREFIX);
+    settings.saved_profile_names = sections.iter().map(|(name, _)| name.clone()).collect();
+    settings.profiles = if sections.is_empty() {
+        Profile::defaults()
+    } else {
+        sections.into_iter().map(|(name, keys)| profile_from_keys(name, &keys)).collect()
+    };
+    if let Some(current) = get_ini_value("Settings", "profile") {
+        settings.current_profile = current;
+    }
+}
+
+// Missing or unparsable keys fall back to the Normal profile's values.
+fn profile_from_keys(name: String, keys: &HashMap<String, String>) -> Profile {
+    let defaults = Profile::default();
+    let parse = |key: &str| keys.get(key).and_then(|value| value.parse::<f32>().ok());
+    Profile {
+        temperature: parse("temperature").unwrap_or(defaults.temperature),
+        top_p: parse("top_p").unwrap_or(defaults.top_p),
+        top_k: keys.get("top_k").and_then(|value| value.parse().ok()).unwrap_or(defaults.top_k),
+        frequency_penalty: parse("frequency_penalty").unwrap_or(defaults.frequency_penalty),
+        presence_penalty: parse("presence_penalty").unwrap_or(defaults.presence_penalty),
+        max_tokens: keys.get("max_tokens").and_then(|value| value.parse().ok()).filter(|tokens| *tokens > 0),
+        thinking_budget: keys.get("thinking_budget").and_then(|value| value.parse().ok()).filter(|tokens| *tokens > 0),
+        stop_sequences: keys.get("stop_sequences")
+            .and_then(|value| serde_json::from_str(value).ok())
+            .unwrap_or_default(),
+        name,
     }
-    if let Ok(entry) = Entry::new("rust_chatbot", "gpt_api_key") {
-        let _ = entry.set_password(&keys.gpt);
+}
+
+pub fn save_profiles(settings: &mut Settings) {
+    // Renaming the selected profile keeps it selected.
+    if settings.saved_profile_names.len() == settings.profiles.len() {
+        if let Some(index) = settings.saved_profile_names.iter().position(|name| *name == settings.current_profile) {
+            settings.current_profile = settings.profiles[index].name.trim().to_string();
+        }
     }
+    for removed in settings.saved_profile_names.iter().filter(|name| !settings.profiles.iter().any(|p| &p.name == *name)) {
+        remove_ini_section(&format!("{}{}", PROFILE_SECTION_PREFIX, removed));
+    }
+
+    for profile in settings.profiles.iter_mut() {
+        profile.name = profile.name.trim().to_string();
+        profile.stop_sequences.retain(|stop| !stop.is_empty());
+        let section = format!("{}{}", PROFILE_SECTION_PREFIX, profile.name);
+        set_ini_value(&section, "temperature", &profile.temperature.to_string());
+        set_ini_value(&section, "top_p", &profile.top_p.to_string());
+        set_ini_value(&section, "top_k", &profile.top_k.to_string());
+        set_ini_value(&section, "frequency_penalty", &profile.frequency_penalty.to_string());
+        set_ini_value(&section, "presence_penalty", &profile.presence_penalty.to_string());
+        set_ini_value(&section, "max_tokens", &profile.max_tokens.unwrap_or(0).to_string());
+        set_ini_value(&section, "thinking_budget", &profile.thinking_budget.unwrap_or(0).to_string());
+        // JSON keeps stop sequences with newlines on one line.
+        set_ini_value(&section, "stop_sequences", &serde_json::to_string(&profile.stop_sequences).unwrap_or_default());
+    }
+    settings.saved_profile_names = settings.profiles.iter().map(|p| p.name.clone()).collect();
+    if !settings.profiles.iter().any(|p| p.name == settings.current_profile) {
+        if let Some(first) = settings.profiles.first() {
+            settings.current_profile = first.name.clone();
+        }
+    }
+    save_current_profile(settings);
     settings.api_keys_updated = true;
 }
 
+pub fn save_current_profile(settings: &Settings) {
+    set_ini_value("Settings", "profile", &settings.current_profile);
+}
+
+pub fn validate_profiles(profiles: &[Profile]) -> Result<(), String> {
+    if profiles.is_empty() {
+        return Err("Keep at least one profile.".to_string());
+    }
+    for (index, profile) in profiles.iter().enumerate() {
+        let name = profile.name.trim();
+        if name.is_empty() {
+            return Err("Every profile needs a name.".to_string());
+        }
+        if name.contains(['[', ']']) {
+            return Err(format!("Profile name \"{}\" can't contain brackets.", name));
+        }
+        if profiles[..index].iter().any(|p| p.name.trim() == name) {
+            return Err(format!("Profile name \"{}\" is already in use.", name));
+        }
+    }
+    Ok(())
+}
+
+fn endpoint_key_name(endpoint_name: &str) -> String {
+    format!("endpoint_{}_api_key", endpoint_name)
+}
+
+fn mcp_env_key_name(server_name: &str) -> String {
+    format!("mcp_{}_env", server_name)
+}
+
 pub fn save_theme(settings: &Settings) {
     set_ini_value("Settings", "theme", &settings.current_theme_index.to_string());
 }
@@ -48,6 +268,183 @@ pub fn save_ui_scale(settings: &Settings) {
     set_ini_value("Settings", "ui_scale", &settings.ui_scale.to_string());
 }
 
+pub fn save_max_attempts(settings: &Settings) {
+    set_ini_value("Settings", "max_attempts", &settings.max_attempts.to_string());
+}
+
+pub fn load_max_attempts(settings: &mut Settings) {
+    if let Some(max_attempts) = get_ini_value("Settings", "max_attempts") {
+        if let Ok(attempts) = max_attempts.parse::<u32>() {
+            settings.max_attempts = attempts.clamp(1, 10);
+        }
+    }
+}
+
+pub fn save_auto_continue(settings: &Settings) {
+    set_ini_value("Settings", "auto_continue", &settings.auto_continue.to_string());
+}
+
+pub fn load_auto_continue(settings: &mut Settings) {
+    if let Some(auto_continue) = get_ini_value("Settings", "auto_continue") {
+        settings.auto_continue = auto_continue == "true";
+    }
+}
+
+pub fn save_default_system_prompt(settings: &Settings) {
+    set_ini_value("Settings", "default_system_prompt", &escape_ini_value(&settings.default_system_prompt));
+}
+
+pub fn load_default_system_prompt(settings: &mut Settings) {
+    if let Some(system_prompt) = get_ini_value("Settings", "default_system_prompt") {
+        settings.default_system_prompt = unescape_ini_value(&system_prompt);
+    }
+}
+
+pub fn save_compaction(settings: &Settings) {
+    set_ini_value("Settings", "compaction_enabled", &settings.compaction_enabled.to_string());
+    set_ini_value("Settings", "compaction_threshold", &settings.compaction_threshold.to_string());
+    set_ini_value("Settings", "compaction_provider", &settings.compaction_provider);
+    set_ini_value("Settings", "compaction_model", settings.compaction_model.trim());
+}
+
+pub fn load_compaction(settings: &mut Settings) {
+    if let Some(enabled) = get_ini_value("Settings", "compaction_enabled") {
+        settings.compaction_enabled = enabled == "true";
+    }
+    if let Some(threshold) = get_ini_value("Settings", "compaction_threshold") {
+        if let Ok(threshold) = threshold.parse::<f32>() {
+            settings.compaction_threshold = threshold.clamp(0.3, 0.95);
+        }
+    }
+    if let Some(provider) = get_ini_value("Settings", "compaction_provider") {
+        settings.compaction_provider = provider;
+    }
+    if let Some(model) = get_ini_value("Settings", "compaction_model") {
+        settings.compaction_model = model;
+    }
+}
+
+pub fn save_tools(settings: &Settings) {
+    set_ini_value("Settings", "tools_enabled", &settings.tools_enabled.to_string());
+    set_ini_value("Settings", "tool_directory", settings.tool_directory.trim());
+}
+
+pub fn load_tools(settings: &mut Settings) {
+    if let Some(enabled) = get_ini_value("Settings", "tools_enabled") {
+        settings.tools_enabled = enabled == "true";
+    }
+    if let Some(directory) = get_ini_value("Settings", "tool_directory") {
+        settings.tool_directory = directory;
+    }
+}
+
+pub fn validate_tools(settings: &Settings) -> Result<(), String> {
+    let directory = settings.tool_directory.trim();
+    if !directory.is_empty() && !std::path::Path::new(directory).is_dir() {
+        return Err(format!("The tool directory \"{}\" does not exist", directory));
+    }
+    Ok(())
+}
+
+pub fn load_mcp_servers(settings: &mut Settings) {
+    settings.mcp_servers = get_ini_sections_with_prefix(MCP_SECTION_PREFIX)
+        .into_iter()
+        .map(|(name, keys)| {
+            // Environment variables often hold tokens, so they live in the keyring.
+            // Older settings files kept them in the section; saving moves them over.
+            let env = Entry::new("rust_chatbot", &mcp_env_key_name(&name))
+                .and_then(|entry| entry.get_password())
+                .ok()
+                .or_else(|| keys.get("env").map(|env| unescape_ini_value(env)))
+                .unwrap_or_default();
+            McpServerConfig {
+                command: keys.get("command").cloned().unwrap_or_default(),
+                args: keys.get("args").map(|args| unescape_ini_value(args)).unwrap_or_default(),
+                env,
+                name,
+            }
+        })
+        .collect();
+    settings.saved_mcp_server_names = settings.mcp_servers.iter().map(|s| s.name.clone()).collect();
+}
+
+pub fn save_mcp_servers(settings: &mut Settings) {
+    for removed in settings.saved_mcp_server_names.iter().filter(|name| !settings.mcp_servers.iter().any(|s| &s.name == *name)) {
+        remove_ini_section(&format!("{}{}", MCP_SECTION_PREFIX, removed));
+        if let Ok(entry) = Entry::new("rust_chatbot", &mcp_env_key_name(removed)) {
+            let _ = entry.delete_password();
+        }
+    }
+
+    for server in settings.mcp_servers.iter_mut() {
+        server.name = server.name.trim().to_string();
+        server.command = server.command.trim().to_string();
+        let section = format!("{}{}", MCP_SECTION_PREFIX, server.name);
+        set_ini_value(&section, "command", &server.command);
+        set_ini_value(&section, "args", &escape_ini_value(server.args.trim()));
+        let entry = Entry::new("rust_chatbot", &mcp_env_key_name(&server.name));
+        match server.env.trim() {
+            "" => {
+                remove_ini_value(&section, "env");
+                if let Ok(entry) = entry {
+                    let _ = entry.delete_password();
+                }
+            }
+            env => match entry.and_then(|entry| entry.set_password(env)) {
+                Ok(()) => remove_ini_value(&section, "env"),
+                Err(e) => {
+                    // Without a keyring the variables stay in the settings file rather than getting lost.
+                    set_ini_value(&section, "env", &escape_ini_value(env));
+                    settings.mcp_error = Some(format!("Couldn't store the environment of MCP server \"{}\" in the keyring, so it is kept in the settings file: {}", server.name, e));
+                }
+            },
+        }
+    }
+    settings.saved_mcp_server_names = settings.mcp_servers.iter().map(|s| s.name.clone()).collect();
+}
+
+pub fn validate_mcp_servers(servers: &[McpServerConfig]) -> Result<(), String> {
+    for (index, server) in servers.iter().enumerate() {
+        let name = server.name.trim();
+        if name.is_empty() {
+            return Err("Every MCP server needs a name.".to_string());
+        }
+        if name.contains(['[', ']']) {
+            return Err(format!("MCP server name \"{}\" can't contain brackets.", name));
+        }
+        if servers[..index].iter().any(|s| s.name.trim() == name) {
+            return Err(format!("MCP server name \"{}\" is already in use.", name));
+        }
+        if server.command.trim().is_empty() {
+            return Err(format!("MCP server \"{}\" needs a command.", name));
+        }
+        server.env().map_err(|e| format!("MCP server \"{}\": {}", name, e))?;
+    }
+    Ok(())
+}
+
+// Ini values are single lines, so newlines (and the backslash used to escape them) are escaped.
+pub(super) fn escape_ini_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\r', "").replace('\n', "\\n")
+}
+
+pub(super) fn unescape_ini_value(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
 pub fn load_ui_scale(settings: &mut Settings) {
     if let Some(ui_scale) = get_ini_value("Settings", "ui_scale") {
         if let Ok(scale) = ui_scale.parse::<f32>() {
@@ -73,14 +470,8 @@ fn get_ini_value(section: &str, key: &str) -> Option<String> {
     None
 }
 
-fn set_ini_value(section: &str, key: &str, value: &str) {
-    let path = Path::new(SETTINGS_FILE);
-    let content = if path.exists() {
-        std::fs::read_to_string(path).unwrap_or_default()
-    } else {
-        String::new()
-    };
-
+fn read_ini_sections() -> HashMap<String, HashMap<String, String>> {
+    let content = std::fs::read_to_string(SETTINGS_FILE).unwrap_or_default();
     let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
     let mut current_section = String::new();
 
@@ -92,16 +483,15 @@ fn set_ini_value(section: &str, key: &str, value: &str) {
             let parts: Vec<&str> = line.splitn(2, '=').collect();
             if parts.len() == 2 {
                 sections.entry(current_section.clone())
-                    .or_insert_with(HashMap::new)
+                    .or_default()
                     .insert(parts[0].trim().to_string(), parts[1].trim().to_string());
             }
         }
     }
+    sections
+}
 
-    sections.entry(section.to_string())
-        .or_insert_with(HashMap::new)
-        .insert(key.to_string(), value.to_string());
-
+fn write_ini_sections(sections: HashMap<String, HashMap<String, String>>) {
     let mut new_content = String::new();
     for (section, keys) in sections {
         new_content.push_str(&format!("[{}]\n", section));
@@ -111,7 +501,38 @@ fn set_ini_value(section: &str, key: &str, value: &str) {
         new_content.push('\n');
     }
 
-    if let Err(e) = std::fs::write(path, new_content) {
+    if let Err(e) = std::fs::write(Path::new(SETTINGS_FILE), new_content) {
         eprintln!("Failed to write settings: {}", e);
     }
-}
\ No newline at end of file
+}
+
+fn set_ini_value(section: &str, key: &str, value: &str) {
+    let mut sections = read_ini_sections();
+    sections.entry(section.to_string())
+        .or_default()
+        .insert(key.to_string(), value.to_string());
+    write_ini_sections(sections);
+}
+
+fn remove_ini_value(section: &str, key: &str) {
+    let mut sections = read_ini_sections();
+    if sections.get_mut(section).and_then(|keys| keys.remove(key)).is_some() {
+        write_ini_sections(sections);
+    }
+}
+
+fn get_ini_sections_with_prefix(prefix: &str) -> Vec<(String, HashMap<String, String>)> {
+    let mut sections: Vec<(String, HashMap<String, String>)> = read_ini_sections()
+        .into_iter()
+        .filter_map(|(section, keys)| section.strip_prefix(prefix).map(|name| (name.to_string(), keys)))
+        .collect();
+    sections.sort_by(|a, b| a.0.cmp(&b.0));
+    sections
+}
+
+fn remove_ini_section(section: &str) {
+    let mut sections = read_ini_sections();
+    if sections.remove(section).is_some() {
+        write_ini_sections(sections);
+    }
+}
@@ -2,8 +2,20 @@ use eframe::egui::{self, Window, RichText};
 use crate::app::Icons;
 use super::Settings;
 use super::settings_operations;
+use crate::providers::{EndpointConfig, PROVIDERS};
+use crate::providers::pricing::format_cost;
+use crate::providers::usage_ledger;
+use crate::app::Profile;
+use crate::providers::parameters::{self, Param, ParamSpec};
+use crate::tools::mcp::{McpServerConfig, McpStatus};
 
-pub fn render(settings: &mut Settings, ctx: &egui::Context, _icons: &Icons) {
+/// The model the profile editor checks parameters against, with what it accepts.
+pub struct CurrentModel<'a> {
+    pub name: &'a str,
+    pub specs: &'a [ParamSpec],
+}
+
+pub fn render(settings: &mut Settings, ctx: &egui::Context, _icons: &Icons, model: &CurrentModel) {
     if settings.show_settings {
         let mut save_clicked = false;
         Window::new(RichText::new("Settings").color(settings.get_current_theme().settings_title_color))
@@ -13,21 +25,301 @@ pub fn render(settings: &mut Settings, ctx: &egui::Context, _icons: &Icons) {
                 let theme = &settings.themes[settings.current_theme_index];
                 let mut keys = settings.api_keys.lock().unwrap();
 
-                ui.horizontal(|ui| {
-                    ui.label(RichText::new("Fireworks API Key:").strong().color(theme.settings_text_color));
-                    ui.text_edit_singleline(&mut keys.fireworks);
+                for info in PROVIDERS.iter().filter(|info| info.key_name.is_some()) {
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(format!("{} API Key:", info.display_name)).strong().color(theme.settings_text_color));
+                        ui.text_edit_singleline(keys.entry(info.id.to_string()).or_default());
+                    });
+                }
+
+                ui.collapsing(RichText::new("Provider URLs").strong().color(theme.settings_text_color), |ui| {
+                    egui::Grid::new("provider_urls_grid").num_columns(2).show(ui, |ui| {
+                        for info in PROVIDERS {
+                            ui.label(RichText::new(format!("{}:", info.display_name)).color(theme.settings_text_color));
+                            ui.add(egui::TextEdit::singleline(settings.base_urls.entry(info.id.to_string()).or_default()).hint_text(info.default_url));
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                ui.collapsing(RichText::new("Network").strong().color(theme.settings_text_color), |ui| {
+                    egui::Grid::new("network_grid").num_columns(2).show(ui, |ui| {
+                        ui.label(RichText::new("Proxy:").color(theme.settings_text_color));
+                        ui.add(egui::TextEdit::singleline(&mut settings.network.proxy).hint_text("http://proxy:3128 or socks5://host:1080"));
+                        ui.end_row();
+                        ui.label(RichText::new("No proxy for:").color(theme.settings_text_color));
+                        ui.add(egui::TextEdit::singleline(&mut settings.network.no_proxy).hint_text("localhost, 127.0.0.1, .internal"));
+                        ui.end_row();
+                        ui.label(RichText::new("Trusted certificates:").color(theme.settings_text_color))
+                            .on_hover_text("PEM file with root certificates to trust in addition to the system ones");
+                        ui.add(egui::TextEdit::singleline(&mut settings.network.ca_file).hint_text("/path/to/ca-bundle.pem"));
+                        ui.end_row();
+                        ui.label(RichText::new("Timeout:").color(theme.settings_text_color))
+                            .on_hover_text("How long to wait for a connection and for a reply to start");
+                        ui.add(egui::Slider::new(&mut settings.network.timeout_secs, 0..=600)
+                            .custom_formatter(|value, _| if value == 0.0 { "off".to_string() } else { format!("{}s", value) }));
+                        ui.end_row();
+                    });
+                    ui.label(RichText::new("Extra headers, one \"Name: value\" per line:").color(theme.settings_text_color));
+                    egui::Grid::new("headers_grid").num_columns(2).show(ui, |ui| {
+                        for info in PROVIDERS {
+                            ui.label(RichText::new(format!("{}:", info.display_name)).color(theme.settings_text_color));
+                            ui.add(egui::TextEdit::multiline(settings.extra_headers.entry(info.id.to_string()).or_default()).desired_rows(1));
+                            ui.end_row();
+                        }
+                    });
                 });
 
+                if let Some(error) = &settings.network_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
                 ui.horizontal(|ui| {
-                    ui.label(RichText::new("Claude API Key:").strong().color(theme.settings_text_color));
-                    ui.text_edit_singleline(&mut keys.claude);
+                    ui.label(RichText::new("Model lists:").strong().color(theme.settings_text_color));
+                    if ui.button("Refresh now").clicked() {
+                        settings.refresh_models_requested = true;
+                    }
                 });
 
                 ui.horizontal(|ui| {
-                    ui.label(RichText::new("GPT API Key:").strong().color(theme.settings_text_color));
-                    ui.text_edit_singleline(&mut keys.gpt);
+                    ui.label(RichText::new("Request attempts:").strong().color(theme.settings_text_color))
+                        .on_hover_text("How often a request is tried when the provider is rate limited, overloaded or unreachable");
+                    ui.add(egui::Slider::new(&mut settings.max_attempts, 1..=10));
+                });
+
+                ui.checkbox(&mut settings.auto_continue, RichText::new("Continue cut-off replies automatically").strong().color(theme.settings_text_color))
+                    .on_hover_text("When a reply hits the token limit, ask the model to finish it (up to 3 times in a row)");
+
+                let usage = ui.collapsing(RichText::new("Usage this month").strong().color(theme.settings_text_color), |ui| {
+                    let month = usage_ledger::current_month();
+                    let ledger = settings.usage_ledger.get();
+                    match ledger.month(&month) {
+                        Some(providers) => {
+                            egui::Grid::new("usage_grid").num_columns(5).striped(true).show(ui, |ui| {
+                                for heading in ["Provider", "Requests", "Tokens in", "Tokens out", "Cost"] {
+                                    ui.label(RichText::new(heading).strong().color(theme.settings_text_color));
+                                }
+                                ui.end_row();
+                                for (provider, usage) in providers {
+                                    ui.label(RichText::new(provider).color(theme.settings_text_color));
+                                    ui.label(usage.requests.to_string());
+                                    ui.label(usage.input_tokens.to_string());
+                                    ui.label(usage.output_tokens.to_string());
+                                    let cost = ui.label(format_cost(usage.cost_usd));
+                                    if usage.unpriced_requests > 0 {
+                                        cost.on_hover_text(format!("Plus {} requests to models without a known price", usage.unpriced_requests));
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                        }
+                        None => {
+                            ui.label(RichText::new(format!("No usage recorded for {} yet.", month)).color(theme.settings_text_color));
+                        }
+                    }
+                });
+                if usage.body_returned.is_none() {
+                    settings.usage_ledger.invalidate();
+                }
+
+                ui.collapsing(RichText::new("Default system prompt").strong().color(theme.settings_text_color), |ui| {
+                    ui.label(RichText::new("New chats start with this system prompt. It can be changed per chat.").small().color(theme.settings_text_color));
+                    ui.add(egui::TextEdit::multiline(&mut settings.default_system_prompt)
+                        .desired_rows(4)
+                        .desired_width(f32::INFINITY)
+                        .hint_text("e.g. You are a concise assistant."));
+                });
+
+                ui.collapsing(RichText::new("Conversation compaction").strong().color(theme.settings_text_color), |ui| {
+                    ui.checkbox(&mut settings.compaction_enabled, RichText::new("Summarize older messages of long chats").color(theme.settings_text_color));
+                    egui::Grid::new("compaction_grid").num_columns(2).show(ui, |ui| {
+                        ui.label(RichText::new("Start at:").color(theme.settings_text_color))
+                            .on_hover_text("Share of the model's context window a chat fills before its older messages are summarized");
+                        ui.add(egui::Slider::new(&mut settings.compaction_threshold, 0.3..=0.95).custom_formatter(|value, _| format!("{:.0}%", value * 100.0)));
+                        ui.end_row();
+                        ui.label(RichText::new("Provider:").color(theme.settings_text_color));
+                        let selected = if settings.compaction_provider.is_empty() { "Same as chat" } else { settings.compaction_provider.as_str() };
+                        egui::ComboBox::from_id_source("compaction_provider")
+                            .selected_text(selected.to_string())
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut settings.compaction_provider, String::new(), "Same as chat");
+                                for info in PROVIDERS {
+                                    ui.selectable_value(&mut settings.compaction_provider, info.display_name.to_string(), info.display_name);
+                                }
+                                for endpoint in &settings.endpoints {
+                                    ui.selectable_value(&mut settings.compaction_provider, endpoint.name.clone(), &endpoint.name);
+                                }
+                            });
+                        ui.end_row();
+                        ui.label(RichText::new("Model:").color(theme.settings_text_color));
+                        ui.add(egui::TextEdit::singleline(&mut settings.compaction_model).hint_text("same as chat, e.g. gpt-4o-mini"));
+                        ui.end_row();
+                    });
                 });
 
+                ui.collapsing(RichText::new("Tools").strong().color(theme.settings_text_color), |ui| {
+                    ui.checkbox(&mut settings.tools_enabled, RichText::new("Let models call tools").color(theme.settings_text_color))
+                        .on_hover_text("Claude, GPT, Fireworks and OpenAI-compatible models can ask to run the tools below. Each call waits for your approval.");
+                    ui.label(RichText::new("Built in: a calculator, and reading and listing files in this directory:").small().color(theme.settings_text_color));
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut settings.tool_directory).hint_text("empty: no file access"));
+                        if ui.button("Browse…").clicked() {
+                            if let Some(directory) = rfd::FileDialog::new().pick_folder() {
+                                settings.tool_directory = directory.display().to_string();
+                            }
+                        }
+                    });
+                    if let Some(error) = &settings.tools_error {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
+
+                    ui.separator();
+                    ui.label(RichText::new("MCP servers, launched while tools are on:").small().color(theme.settings_text_color));
+                    let mut removed = None;
+                    for (index, server) in settings.mcp_servers.iter_mut().enumerate() {
+                        egui::Grid::new(("mcp_server_grid", index)).num_columns(2).show(ui, |ui| {
+                            ui.label(RichText::new("Name:").color(theme.settings_text_color));
+                            ui.text_edit_singleline(&mut server.name);
+                            ui.end_row();
+                            ui.label(RichText::new("Command:").color(theme.settings_text_color));
+                            ui.add(egui::TextEdit::singleline(&mut server.command).hint_text("npx"));
+                            ui.end_row();
+                            ui.label(RichText::new("Arguments:").color(theme.settings_text_color));
+                            ui.add(egui::TextEdit::multiline(&mut server.args).desired_rows(1).hint_text("one per line"));
+                            ui.end_row();
+                            ui.label(RichText::new("Environment:").color(theme.settings_text_color));
+                            ui.add(egui::TextEdit::multiline(&mut server.env).desired_rows(1).hint_text("NAME=value, one per line"))
+                                .on_hover_text("Kept in the system keyring, like API keys");
+                            ui.end_row();
+                            ui.label(RichText::new("Status:").color(theme.settings_text_color));
+                            mcp_status(ui, settings.mcp.status(&server.name), theme.settings_text_color);
+                            ui.end_row();
+                        });
+                        if ui.button("Remove server").clicked() {
+                            removed = Some(index);
+                        }
+                        ui.separator();
+                    }
+                    if let Some(index) = removed {
+                        settings.mcp_servers.remove(index);
+                    }
+                    if ui.button("Add MCP server").clicked() {
+                        settings.mcp_servers.push(McpServerConfig::default());
+                    }
+                    if let Some(error) = &settings.mcp_error {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
+                });
+
+                ui.collapsing(RichText::new("Generation profiles").strong().color(theme.settings_text_color), |ui| {
+                    let mut removed = None;
+                    let can_remove = settings.profiles.len() > 1;
+                    for (index, profile) in settings.profiles.iter_mut().enumerate() {
+                        egui::Grid::new(("profile_grid", index)).num_columns(2).show(ui, |ui| {
+                            ui.label(RichText::new("Name:").color(theme.settings_text_color));
+                            ui.text_edit_singleline(&mut profile.name);
+                            ui.end_row();
+                            let text_color = theme.settings_text_color;
+                            let value = |param| parameters::profile_value(profile, param);
+                            let (temperature, top_p, top_k) = (value(Param::Temperature), value(Param::TopP), value(Param::TopK));
+                            let (frequency_penalty, presence_penalty, max_tokens) = (value(Param::FrequencyPenalty), value(Param::PresencePenalty), value(Param::MaxTokens));
+                            let thinking_budget = value(Param::ThinkingBudget);
+                            let stop_count = Some(profile.stop_sequences.len() as f32);
+                            param_row(ui, text_color, model, Param::Temperature, temperature, |ui| {
+                                ui.add(egui::Slider::new(&mut profile.temperature, 0.0..=2.0).step_by(0.05));
+                            });
+                            param_row(ui, text_color, model, Param::TopP, top_p, |ui| {
+                                ui.add(egui::Slider::new(&mut profile.top_p, 0.0..=1.0).step_by(0.01));
+                            });
+                            param_row(ui, text_color, model, Param::TopK, top_k, |ui| {
+                                ui.add(egui::Slider::new(&mut profile.top_k, 1..=200));
+                            });
+                            param_row(ui, text_color, model, Param::FrequencyPenalty, frequency_penalty, |ui| {
+                                ui.add(egui::Slider::new(&mut profile.frequency_penalty, -2.0..=2.0).step_by(0.01));
+                            });
+                            param_row(ui, text_color, model, Param::PresencePenalty, presence_penalty, |ui| {
+                                ui.add(egui::Slider::new(&mut profile.presence_penalty, -2.0..=2.0).step_by(0.01));
+                            });
+                            param_row(ui, text_color, model, Param::MaxTokens, max_tokens, |ui| {
+                                ui.horizontal(|ui| {
+                                    let mut limited = profile.max_tokens.is_some();
+                                    if ui.checkbox(&mut limited, "").on_hover_text("Off uses the provider's default").changed() {
+                                        profile.max_tokens = limited.then_some(4096);
+                                    }
+                                    if let Some(max_tokens) = profile.max_tokens.as_mut() {
+                                        ui.add(egui::DragValue::new(max_tokens).range(1..=200_000));
+                                    }
+                                });
+                            });
+                            param_row(ui, text_color, model, Param::StopSequences, stop_count, |ui| {
+                                stop_sequences_editor(ui, &mut profile.stop_sequences);
+                            });
+                            param_row(ui, text_color, model, Param::ThinkingBudget, thinking_budget, |ui| {
+                                ui.horizontal(|ui| {
+                                    let mut thinking = profile.thinking_budget.is_some();
+                                    if ui.checkbox(&mut thinking, "").on_hover_text("Lets the model think before it answers").changed() {
+                                        profile.thinking_budget = thinking.then_some(4096);
+                                    }
+                                    if let Some(budget) = profile.thinking_budget.as_mut() {
+                                        ui.add(egui::DragValue::new(budget).range(1024..=60_000));
+                                    }
+                                });
+                            });
+                        });
+                        if ui.add_enabled(can_remove, egui::Button::new("Remove profile")).clicked() {
+                            removed = Some(index);
+                        }
+                        ui.separator();
+                    }
+                    if let Some(index) = removed {
+                        settings.profiles.remove(index);
+                    }
+                    if ui.button("Add profile").clicked() {
+                        settings.profiles.push(Profile { name: format!("Profile {}", settings.profiles.len() + 1), ..Profile::default() });
+                    }
+                });
+
+                if let Some(error) = &settings.profile_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
+                ui.collapsing(RichText::new("OpenAI-compatible endpoints").strong().color(theme.settings_text_color), |ui| {
+                    let mut removed = None;
+                    for (index, endpoint) in settings.endpoints.iter_mut().enumerate() {
+                        egui::Grid::new(("endpoint_grid", index)).num_columns(2).show(ui, |ui| {
+                            ui.label(RichText::new("Name:").color(theme.settings_text_color));
+                            ui.text_edit_singleline(&mut endpoint.name);
+                            ui.end_row();
+                            ui.label(RichText::new("Base URL:").color(theme.settings_text_color));
+                            ui.add(egui::TextEdit::singleline(&mut endpoint.base_url).hint_text("http://localhost:8080/v1"));
+                            ui.end_row();
+                            ui.label(RichText::new("API Key:").color(theme.settings_text_color));
+                            ui.add(egui::TextEdit::singleline(&mut endpoint.api_key).hint_text("optional"));
+                            ui.end_row();
+                            ui.label(RichText::new("Models:").color(theme.settings_text_color));
+                            ui.add(egui::TextEdit::singleline(&mut endpoint.models).hint_text("model-a, model-b"));
+                            ui.end_row();
+                            ui.label(RichText::new("Headers:").color(theme.settings_text_color));
+                            ui.add(egui::TextEdit::multiline(&mut endpoint.headers).desired_rows(1).hint_text("Name: value, one per line"));
+                            ui.end_row();
+                        });
+                        if ui.button("Remove endpoint").clicked() {
+                            removed = Some(index);
+                        }
+                        ui.separator();
+                    }
+                    if let Some(index) = removed {
+                        settings.endpoints.remove(index);
+                    }
+                    if ui.button("Add endpoint").clicked() {
+                        settings.endpoints.push(EndpointConfig::default());
+                    }
+                });
+
+                if let Some(error) = &settings.endpoint_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
                 ui.horizontal(|ui| {
                     ui.label(RichText::new("Theme:").strong().color(theme.settings_text_color));
                     
@@ -72,6 +364,31 @@ pub fn render(settings: &mut Settings, ctx: &egui::Context, _icons: &Icons) {
             });
 
         if save_clicked {
+            settings.endpoint_error = settings_operations::validate_endpoints(&settings.endpoints).err();
+            if settings.endpoint_error.is_none() {
+                settings_operations::save_endpoints(settings);
+            }
+            settings.profile_error = settings_operations::validate_profiles(&settings.profiles).err();
+            if settings.profile_error.is_none() {
+                settings_operations::save_profiles(settings);
+            }
+            settings.network_error = settings_operations::validate_network(settings).err();
+            if settings.network_error.is_none() {
+                settings_operations::save_network(settings);
+            }
+            settings_operations::save_base_urls(settings);
+            settings_operations::save_max_attempts(settings);
+            settings_operations::save_auto_continue(settings);
+            settings_operations::save_default_system_prompt(settings);
+            settings_operations::save_compaction(settings);
+            settings.tools_error = settings_operations::validate_tools(settings).err();
+            if settings.tools_error.is_none() {
+                settings_operations::save_tools(settings);
+            }
+            settings.mcp_error = settings_operations::validate_mcp_servers(&settings.mcp_servers).err();
+            if settings.mcp_error.is_none() {
+                settings_operations::save_mcp_servers(settings);
+            }
             settings_operations::save_api_keys(settings);
             settings_operations::save_theme(settings);
             settings.ui_scale = settings.temp_ui_scale;
@@ -79,4 +396,96 @@ pub fn render(settings: &mut Settings, ctx: &egui::Context, _icons: &Icons) {
             ctx.set_pixels_per_point(settings.ui_scale);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Whether an MCP server is running, with what it offers on hover.
+fn mcp_status(ui: &mut egui::Ui, status: Option<McpStatus>, text_color: egui::Color32) {
+    match status {
+        None => {
+            ui.label(RichText::new("Not started").color(text_color))
+                .on_hover_text("Servers start when they are saved with tools turned on");
+        }
+        Some(McpStatus::Starting) => {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(RichText::new("Starting…").color(text_color));
+            });
+        }
+        Some(McpStatus::Connected { tools, resources, prompts }) => {
+            let count = |n: usize, noun: &str| format!("{} {}{}", n, noun, if n == 1 { "" } else { "s" });
+            let summary = format!("Connected: {}, {}, {}", count(tools.len(), "tool"), count(resources.len(), "resource"), count(prompts.len(), "prompt"));
+            let mut details = Vec::new();
+            for (heading, names) in [("Tools", &tools), ("Resources", &resources), ("Prompts", &prompts)] {
+                if !names.is_empty() {
+                    details.push(format!("{}: {}", heading, names.join(", ")));
+                }
+            }
+            let response = ui.colored_label(egui::Color32::from_rgb(80, 180, 80), summary);
+            if !details.is_empty() {
+                response.on_hover_text(details.join("\n"));
+            }
+        }
+        Some(McpStatus::Failed(error)) => {
+            ui.colored_label(egui::Color32::RED, format!("Failed: {}", error));
+        }
+    }
+}
+
+/// One profile editor row. Parameters the current model ignores are dimmed,
+/// and the label's tooltip tells when a value is clamped or cut short.
+fn param_row(ui: &mut egui::Ui, text_color: egui::Color32, model: &CurrentModel, param: Param, value: Option<f32>, add_contents: impl FnOnce(&mut egui::Ui)) {
+    let spec = parameters::find(model.specs, param);
+    let note = match (spec, value) {
+        (None, _) => Some(format!("{} ignores this setting", model.name)),
+        (Some(spec), Some(count)) if param == Param::StopSequences && count > spec.max => {
+            Some(format!("{} takes up to {} stop sequences; the rest are not sent", model.name, spec.max))
+        }
+        (Some(spec), Some(value)) if param != Param::StopSequences && (value < spec.min || value > spec.max) => {
+            Some(format!("{} accepts {} to {}, so {} is sent as {}", model.name, spec.min, spec.max, value, value.clamp(spec.min, spec.max)))
+        }
+        _ => None,
+    };
+    let mut label = param.label().to_string();
+    label[..1].make_ascii_uppercase();
+    if spec.is_some() && note.is_some() {
+        label.push_str(" (!)");
+    }
+
+    let opacity = if spec.is_some() { 1.0 } else { 0.4 };
+    ui.scope(|ui| {
+        ui.multiply_opacity(opacity);
+        let response = ui.label(RichText::new(format!("{}:", label)).color(text_color));
+        if let Some(note) = &note {
+            response.on_hover_text(note);
+        }
+    });
+    ui.scope(|ui| {
+        ui.multiply_opacity(opacity);
+        add_contents(ui);
+    });
+    ui.end_row();
+}
+
+/// Edits a list of stop sequences, one line each, with `\n` standing for a line break.
+pub fn stop_sequences_editor(ui: &mut egui::Ui, stop_sequences: &mut Vec<String>) {
+    ui.vertical(|ui| {
+        let mut removed = None;
+        for (index, stop) in stop_sequences.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                let mut text = settings_operations::escape_ini_value(stop);
+                if ui.text_edit_singleline(&mut text).on_hover_text("Write a line break as \\n").changed() {
+                    *stop = settings_operations::unescape_ini_value(&text);
+                }
+                if ui.small_button("x").clicked() {
+                    removed = Some(index);
+                }
+            });
+        }
+        if let Some(index) = removed {
+            stop_sequences.remove(index);
+        }
+        if ui.small_button("Add stop sequence").clicked() {
+            stop_sequences.push(String::new());
+        }
+    });
+}
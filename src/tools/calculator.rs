@@ -0,0 +1,258 @@
+use super::{string_input, Tool};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+// Limits on what the model may send, since each nesting level recurses through the parser.
+const MAX_LENGTH: usize = 10_000;
+const MAX_DEPTH: usize = 256;
+
+/// Evaluates arithmetic, which models are unreliable at doing in their head.
+pub struct Calculator;
+
+#[async_trait]
+impl Tool for Calculator {
+    fn name(&self) -> &str {
+        "calculator"
+    }
+
+    fn description(&self) -> &str {
+        "Evaluates an arithmetic expression and returns the number. Supports + - * / % ^, parentheses, \
+         the constants pi and e, and the functions sqrt, abs, exp, ln, log10, sin, cos, tan, floor, ceil and round."
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "expression": { "type": "string", "description": "For example (3 + 4.5) * 2 ^ 10 / sqrt(2)" }
+            },
+            "required": ["expression"]
+        })
+    }
+
+    async fn execute(&self, input: Value) -> Result<String, String> {
+        evaluate(string_input(&input, "expression")?).map(format_number)
+    }
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+pub fn evaluate(expression: &str) -> Result<f64, String> {
+    if expression.len() > MAX_LENGTH {
+        return Err(format!("The expression is longer than {} characters", MAX_LENGTH));
+    }
+    let mut parser = Parser { chars: expression.chars().collect(), position: 0, depth: 0 };
+    let value = parser.sum()?;
+    if let Some(c) = parser.peek() {
+        return Err(format!("Unexpected \"{}\" at position {}", c, parser.position + 1));
+    }
+    if !value.is_finite() {
+        return Err("The result is not a finite number".to_string());
+    }
+    Ok(value)
+}
+
+/// Recursive descent over the usual precedence: sums, products, unary signs, powers.
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// How deeply the current term is nested, in unary signs and parentheses.
+    depth: usize,
+}
+
+impl Parser {
+    /// The next character that isn't whitespace.
+    fn peek(&mut self) -> Option<char> {
+        while self.current().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+        self.current()
+    }
+
+    fn current(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value += self.product()?;
+            } else if self.eat('-') {
+                value -= self.product()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("Division by zero".to_string());
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("Division by zero".to_string());
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    // Below powers, so that -2^2 is -4. Every nested term passes through here,
+    // which makes it the place to stop runaway recursion.
+    fn unary(&mut self) -> Result<f64, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("Expression nests too deeply".to_string());
+        }
+        let value = if self.eat('-') {
+            self.unary().map(|value| -value)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+        value
+    }
+
+    // Right-associative: 2^3^2 is 2^9.
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            Ok(base.powf(self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let value = self.sum()?;
+                if !self.eat(')') {
+                    return Err("Missing closing parenthesis".to_string());
+                }
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.name(),
+            Some(c) => Err(format!("Unexpected \"{}\" at position {}", c, self.position + 1)),
+            None => Err("The expression ends too early".to_string()),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        let start = self.position;
+        while self.current().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.position += 1;
+        }
+        // Exponent notation such as 1.5e-3.
+        if self.current() == Some('e') && self.chars.get(self.position + 1).is_some_and(|c| c.is_ascii_digit() || *c == '-' || *c == '+') {
+            self.position += 2;
+            while self.current().is_some_and(|c| c.is_ascii_digit()) {
+                self.position += 1;
+            }
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse().map_err(|_| format!("\"{}\" is not a number", text))
+    }
+
+    fn name(&mut self) -> Result<f64, String> {
+        let start = self.position;
+        while self.current().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            self.position += 1;
+        }
+        let name: String = self.chars[start..self.position].iter().collect();
+        match name.as_str() {
+            "pi" => return Ok(std::f64::consts::PI),
+            "e" => return Ok(std::f64::consts::E),
+            _ => {}
+        }
+        let function: fn(f64) -> f64 = match name.as_str() {
+            "sqrt" => f64::sqrt,
+            "abs" => f64::abs,
+            "exp" => f64::exp,
+            "ln" => f64::ln,
+            "log10" => f64::log10,
+            "sin" => f64::sin,
+            "cos" => f64::cos,
+            "tan" => f64::tan,
+            "floor" => f64::floor,
+            "ceil" => f64::ceil,
+            "round" => f64::round,
+            _ => return Err(format!("Unknown name \"{}\"", name)),
+        };
+        if self.peek() != Some('(') {
+            return Err(format!("{} needs its argument in parentheses", name));
+        }
+        Ok(function(self.primary()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_operator_precedence() {
+        assert_eq!(evaluate("1 + 2 * 3").unwrap(), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9.0);
+        assert_eq!(evaluate("-2^2").unwrap(), -4.0);
+        assert_eq!(evaluate("2^3^2").unwrap(), 512.0);
+        assert_eq!(evaluate("2^-1").unwrap(), 0.5);
+        assert_eq!(evaluate("10 - 4 - 3").unwrap(), 3.0);
+        assert_eq!(evaluate("17 % 5").unwrap(), 2.0);
+    }
+
+    #[test]
+    fn knows_constants_functions_and_exponents() {
+        assert_eq!(evaluate("sqrt(16) + abs(-2)").unwrap(), 6.0);
+        assert!((evaluate("2 * pi").unwrap() - std::f64::consts::TAU).abs() < 1e-12);
+        assert_eq!(evaluate("1.5e3").unwrap(), 1500.0);
+        assert_eq!(format_number(evaluate("1 / 4").unwrap()), "0.25");
+        assert_eq!(format_number(evaluate("6 * 7").unwrap()), "42");
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for expression in ["", "1 +", "(1 + 2", "1 / 0", "2 $ 3", "foo(1)", "sqrt 4", "sqrt(-1)", "1 2"] {
+            assert!(evaluate(expression).is_err(), "{} should fail", expression);
+        }
+    }
+
+    #[test]
+    fn stops_at_deep_nesting() {
+        let parentheses = format!("{}1{}", "(".repeat(3000), ")".repeat(3000));
+        assert_eq!(evaluate(&parentheses).unwrap_err(), "Expression nests too deeply");
+        assert_eq!(evaluate(&format!("{}1", "-".repeat(3000))).unwrap_err(), "Expression nests too deeply");
+        assert!(evaluate(&"1+".repeat(MAX_LENGTH)).is_err());
+        assert_eq!(evaluate(&format!("{}1{}", "(".repeat(100), ")".repeat(100))).unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,162 @@
+use super::{string_input, Tool};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+const MAX_FILE_BYTES: u64 = 1_000_000;
+const MAX_ENTRIES: usize = 1000;
+
+/// The one directory the file tools may look into.
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Resolves `path`, relative to the root, to a path inside it. Symbolic
+    /// links and `..` are followed first, so neither can lead outside.
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let root = self.root.canonicalize()
+            .map_err(|e| format!("The allowed directory {} is not available: {}", self.root.display(), e))?;
+        let resolved = root.join(path).canonicalize().map_err(|e| format!("Can't open {}: {}", path, e))?;
+        if !resolved.starts_with(&root) {
+            return Err(format!("{} is outside the allowed directory", path));
+        }
+        Ok(resolved)
+    }
+
+    fn display(&self, path: &Path) -> String {
+        let root = self.root.canonicalize().unwrap_or_else(|_| self.root.clone());
+        match path.strip_prefix(&root) {
+            Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+            Ok(relative) => relative.display().to_string(),
+            Err(_) => path.display().to_string(),
+        }
+    }
+}
+
+pub struct ReadFile(pub Sandbox);
+
+#[async_trait]
+impl Tool for ReadFile {
+    fn name(&self) -> &str {
+        "read_file"
+    }
+
+    fn description(&self) -> &str {
+        "Reads a UTF-8 text file from the user's allowed directory. Paths are relative to that directory."
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path of the file, relative to the allowed directory" }
+            },
+            "required": ["path"]
+        })
+    }
+
+    async fn execute(&self, input: Value) -> Result<String, String> {
+        let path = self.0.resolve(string_input(&input, "path")?)?;
+        let metadata = tokio::fs::metadata(&path).await.map_err(|e| e.to_string())?;
+        if !metadata.is_file() {
+            return Err(format!("{} is not a file", self.0.display(&path)));
+        }
+        if metadata.len() > MAX_FILE_BYTES {
+            return Err(format!("{} is too large to read ({} bytes, at most {})", self.0.display(&path), metadata.len(), MAX_FILE_BYTES));
+        }
+        let bytes = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+        if bytes.contains(&0) {
+            return Err(format!("{} is a binary file", self.0.display(&path)));
+        }
+        String::from_utf8(bytes).map_err(|_| format!("{} is not UTF-8 text", self.0.display(&path)))
+    }
+}
+
+pub struct ListDirectory(pub Sandbox);
+
+#[async_trait]
+impl Tool for ListDirectory {
+    fn name(&self) -> &str {
+        "list_directory"
+    }
+
+    fn description(&self) -> &str {
+        "Lists the files and subdirectories of a directory inside the user's allowed directory. \
+         Directories end in a slash; files show their size in bytes."
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path relative to the allowed directory; \".\" or empty for the directory itself" }
+            }
+        })
+    }
+
+    async fn execute(&self, input: Value) -> Result<String, String> {
+        let path = self.0.resolve(input["path"].as_str().unwrap_or("."))?;
+        let mut reader = tokio::fs::read_dir(&path).await.map_err(|e| format!("Can't list {}: {}", self.0.display(&path), e))?;
+        let mut entries = Vec::new();
+        while let Some(entry) = reader.next_entry().await.map_err(|e| e.to_string())? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            match entry.metadata().await {
+                Ok(metadata) if metadata.is_dir() => entries.push(format!("{}/", name)),
+                Ok(metadata) => entries.push(format!("{} ({} bytes)", name, metadata.len())),
+                Err(_) => entries.push(name),
+            }
+        }
+        entries.sort();
+        let total = entries.len();
+        if total == 0 {
+            return Ok(format!("{} is empty", self.0.display(&path)));
+        }
+        entries.truncate(MAX_ENTRIES);
+        if total > MAX_ENTRIES {
+            entries.push(format!("[{} more entries not shown]", total - MAX_ENTRIES));
+        }
+        Ok(entries.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test gets its own directory, since tests run in parallel.
+    fn sandbox(test: &str) -> (Sandbox, PathBuf) {
+        let root = std::env::temp_dir().join(format!("rust_chatbot_{}_{}", test, std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("notes.txt"), "hello").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        (Sandbox::new(root.clone()), root)
+    }
+
+    #[tokio::test]
+    async fn reads_and_lists_inside_the_allowed_directory() {
+        let (sandbox, root) = sandbox("reads");
+        let read = ReadFile(sandbox.clone()).execute(json!({ "path": "src/main.rs" })).await;
+        assert_eq!(read.unwrap(), "fn main() {}");
+        let listed = ListDirectory(sandbox).execute(json!({})).await.unwrap();
+        assert_eq!(listed, "notes.txt (5 bytes)\nsrc/");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_paths_outside_the_allowed_directory() {
+        let (sandbox, root) = sandbox("refuses");
+        let inner = Sandbox::new(root.join("src"));
+        for path in ["../notes.txt", "/etc/passwd", "src/../../notes.txt"] {
+            assert!(ReadFile(inner.clone()).execute(json!({ "path": path })).await.is_err(), "{} should be refused", path);
+        }
+        assert!(ListDirectory(inner).execute(json!({ "path": ".." })).await.is_err());
+        assert!(ReadFile(sandbox).execute(json!({ "path": "src" })).await.is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use super::McpServerConfig;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::oneshot;
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+const LIST_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Writer = Arc<tokio::sync::Mutex<Box<dyn AsyncWrite + Send + Unpin>>>;
+/// Requests waiting for their response, by id; `None` once the server has closed the connection.
+type Pending = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<Result<Value, String>>>>>>;
+
+/// A JSON-RPC connection to an MCP server, one message per line.
+pub struct McpClient {
+    writer: Writer,
+    pending: Pending,
+    next_id: Mutex<u64>,
+    child: Mutex<Option<Child>>,
+}
+
+impl McpClient {
+    /// Launches the server and connects to its stdin and stdout. Its stderr
+    /// goes to ours, where servers write their logs.
+    pub fn spawn(config: &McpServerConfig) -> Result<Self, String> {
+        let command = config.command.trim();
+        let mut child = Command::new(command)
+            .args(config.args())
+            .envs(config.env()?)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Can't start {}: {}", command, e))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(format!("Can't connect to {}", command));
+        };
+        let client = Self::connect(stdout, stdin);
+        *client.child.lock().unwrap() = Some(child);
+        Ok(client)
+    }
+
+    /// Talks to a server over `reader` and `writer`. Must be called inside a
+    /// Tokio runtime, which reads the server's messages in the background.
+    pub fn connect(reader: impl AsyncRead + Send + Unpin + 'static, writer: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        let writer: Writer = Arc::new(tokio::sync::Mutex::new(Box::new(writer)));
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+        tokio::spawn(read_messages(reader, Arc::clone(&pending), Arc::clone(&writer)));
+        Self { writer, pending, next_id: Mutex::new(0), child: Mutex::new(None) }
+    }
+
+    /// Agrees on the protocol version and returns the server's capabilities.
+    pub async fn initialize(&self) -> Result<Value, String> {
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "rust_chatbot", "version": env!("CARGO_PKG_VERSION") }
+        });
+        let result = self.request("initialize", params, LIST_TIMEOUT).await?;
+        self.notify("notifications/initialized").await?;
+        Ok(result["capabilities"].clone())
+    }
+
+    /// Collects every page of a list method, e.g. the `tools` of `tools/list`.
+    pub async fn list(&self, method: &str, field: &str) -> Result<Vec<Value>, String> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self.request(method, params, LIST_TIMEOUT).await?;
+            items.extend(result[field].as_array().cloned().unwrap_or_default());
+            match result["nextCursor"].as_str() {
+                Some(next) if !next.is_empty() => cursor = Some(next.to_string()),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    pub async fn request(&self, method: &str, params: Value, timeout: Duration) -> Result<Value, String> {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+        let (sender, receiver) = oneshot::channel();
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, sender),
+            None => return Err(closed()),
+        };
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(e) = write_message(&self.writer, message).await {
+            self.forget(id);
+            return Err(e);
+        }
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => Err(closed()),
+            Err(_) => {
+                self.forget(id);
+                Err(format!("{} got no answer within {} seconds", method, timeout.as_secs()))
+            }
+        }
+    }
+
+    fn forget(&self, id: u64) {
+        if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+            pending.remove(&id);
+        }
+    }
+
+    async fn notify(&self, method: &str) -> Result<(), String> {
+        write_message(&self.writer, json!({ "jsonrpc": "2.0", "method": method })).await
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.pending.lock().unwrap().is_none()
+    }
+
+    /// Ends the server process, if this client launched one.
+    pub fn stop(&self) {
+        if let Some(child) = self.child.lock().unwrap().as_mut() {
+            if let Err(e) = child.start_kill() {
+                eprintln!("Failed to stop MCP server: {}", e);
+            }
+        }
+    }
+}
+
+fn closed() -> String {
+    "The server closed the connection".to_string()
+}
+
+async fn write_message(writer: &Writer, message: Value) -> Result<(), String> {
+    let mut line = message.to_string();
+    line.push('\n');
+    let mut writer = writer.lock().await;
+    writer.write_all(line.as_bytes()).await.map_err(|e| format!("Can't write to the server: {}", e))?;
+    writer.flush().await.map_err(|e| format!("Can't write to the server: {}", e))
+}
+
+async fn read_messages(reader: impl AsyncRead + Unpin, pending: Pending, writer: Writer) {
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                println!("Debug: Reading from MCP server failed: {}", e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                println!("Debug: MCP server sent a line that is not JSON ({}): {}", e, line);
+                continue;
+            }
+        };
+
+        match (message["method"].as_str(), message.get("id")) {
+            // Requests from the server. Only pings are answered, since the client offers no capabilities.
+            (Some(method), Some(id)) => {
+                let response = if method == "ping" {
+                    json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                } else {
+                    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": format!("Method not found: {}", method) } })
+                };
+                if let Err(e) = write_message(&writer, response).await {
+                    println!("Debug: {}", e);
+                }
+            }
+            (Some(method), None) => println!("Debug: MCP notification {}", method),
+            (None, Some(id)) => {
+                let sender = id.as_u64().and_then(|id| pending.lock().unwrap().as_mut().and_then(|pending| pending.remove(&id)));
+                let Some(sender) = sender else {
+                    println!("Debug: MCP response to unknown request {}", id);
+                    continue;
+                };
+                let response = match message.get("error") {
+                    Some(error) => Err(error["message"].as_str().map(str::to_string).unwrap_or_else(|| error.to_string())),
+                    None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                };
+                let _ = sender.send(response);
+            }
+            (None, None) => println!("Debug: MCP server sent an unexpected message: {}", line),
+        }
+    }
+
+    // Dropping the senders fails the requests still waiting.
+    pending.lock().unwrap().take();
+}
@@ -0,0 +1,291 @@
+pub mod client;
+pub mod server_tools;
+
+use super::Tool;
+use client::McpClient;
+use server_tools::{GetPrompt, ReadResource, ServerTool};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+
+/// A Model Context Protocol server the app launches and talks to over its
+/// stdin and stdout.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct McpServerConfig {
+    pub name: String,
+    pub command: String,
+    /// Arguments, one per line, so that they may contain spaces.
+    pub args: String,
+    /// Environment variables, one `NAME=value` per line. Saved in the keyring,
+    /// since they often hold tokens.
+    pub env: String,
+}
+
+impl McpServerConfig {
+    pub fn args(&self) -> Vec<String> {
+        self.args.lines().map(str::trim).filter(|arg| !arg.is_empty()).map(str::to_string).collect()
+    }
+
+    pub fn env(&self) -> Result<Vec<(String, String)>, String> {
+        self.env.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| match line.split_once('=') {
+                Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.to_string())),
+                _ => Err(format!("\"{}\" is not a NAME=value line", line)),
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum McpStatus {
+    Starting,
+    /// Names of what the server offers.
+    Connected { tools: Vec<String>, resources: Vec<String>, prompts: Vec<String> },
+    Failed(String),
+}
+
+struct Server {
+    config: McpServerConfig,
+    connection: Arc<Mutex<Connection>>,
+}
+
+struct Connection {
+    status: McpStatus,
+    client: Option<Arc<McpClient>>,
+    tools: Vec<Arc<dyn Tool>>,
+    /// Set when the server is removed or changed while it is still starting.
+    stopped: bool,
+}
+
+/// Keeps the configured MCP servers running and collects their tools.
+#[derive(Clone, Default)]
+pub struct McpManager {
+    servers: Arc<Mutex<Vec<Server>>>,
+    /// Set whenever a server finishes starting, so the chat can pick up its tools.
+    changed: Arc<Mutex<bool>>,
+}
+
+impl McpManager {
+    /// Starts the servers in `configs` that aren't running yet and stops the
+    /// ones no longer in it. Servers whose settings changed are restarted.
+    pub fn connect(&self, configs: &[McpServerConfig], runtime: &Runtime) {
+        let mut servers = self.servers.lock().unwrap();
+        servers.retain(|server| {
+            let keep = configs.contains(&server.config);
+            if !keep {
+                println!("Debug: Stopping MCP server {}", server.config.name);
+                let mut connection = server.connection.lock().unwrap();
+                connection.stopped = true;
+                if let Some(client) = connection.client.take() {
+                    client.stop();
+                }
+                connection.tools.clear();
+            }
+            keep
+        });
+
+        for config in configs {
+            if servers.iter().any(|server| &server.config == config) {
+                continue;
+            }
+            println!("Debug: Starting MCP server {}", config.name);
+            let connection = Arc::new(Mutex::new(Connection { status: McpStatus::Starting, client: None, tools: Vec::new(), stopped: false }));
+            servers.push(Server { config: config.clone(), connection: Arc::clone(&connection) });
+
+            let config = config.clone();
+            let changed = Arc::clone(&self.changed);
+            runtime.spawn(async move {
+                let started = start(&config).await;
+                let mut connection = connection.lock().unwrap();
+                match started {
+                    Ok((client, _, _)) if connection.stopped => client.stop(),
+                    Ok((client, tools, status)) => {
+                        connection.status = status;
+                        connection.client = Some(client);
+                        connection.tools = tools;
+                    }
+                    Err(e) => {
+                        eprintln!("MCP server {} failed: {}", config.name, e);
+                        connection.status = McpStatus::Failed(e);
+                    }
+                }
+                *changed.lock().unwrap() = true;
+            });
+        }
+    }
+
+    /// The status of the named server; `None` if it hasn't been started.
+    pub fn status(&self, name: &str) -> Option<McpStatus> {
+        let servers = self.servers.lock().unwrap();
+        let server = servers.iter().find(|server| server.config.name == name)?;
+        let connection = server.connection.lock().unwrap();
+        if connection.client.as_ref().is_some_and(|client| client.is_closed()) {
+            return Some(McpStatus::Failed("The server exited".to_string()));
+        }
+        Some(connection.status.clone())
+    }
+
+    pub fn is_starting(&self) -> bool {
+        self.servers.lock().unwrap().iter().any(|server| server.connection.lock().unwrap().status == McpStatus::Starting)
+    }
+
+    /// The tools of every connected server.
+    pub fn tools(&self) -> Vec<Arc<dyn Tool>> {
+        self.servers.lock().unwrap().iter()
+            .flat_map(|server| server.connection.lock().unwrap().tools.clone())
+            .collect()
+    }
+
+    /// Whether a server has finished starting since the last call.
+    pub fn take_changed(&self) -> bool {
+        std::mem::take(&mut *self.changed.lock().unwrap())
+    }
+}
+
+async fn start(config: &McpServerConfig) -> Result<(Arc<McpClient>, Vec<Arc<dyn Tool>>, McpStatus), String> {
+    let client = Arc::new(McpClient::spawn(config)?);
+    match discover(&config.name, &client).await {
+        Ok((tools, status)) => Ok((client, tools, status)),
+        Err(e) => {
+            client.stop();
+            Err(e)
+        }
+    }
+}
+
+/// Initializes the connection and lists what the server offers. Resources and
+/// prompts are offered to the model through one tool each.
+async fn discover(server: &str, client: &Arc<McpClient>) -> Result<(Vec<Arc<dyn Tool>>, McpStatus), String> {
+    let capabilities = &client.initialize().await?;
+    let list = |method: &'static str, field: &'static str| async move {
+        if capabilities.get(field).is_some() {
+            client.list(method, field).await
+        } else {
+            Ok(Vec::new())
+        }
+    };
+    let server_tools = list("tools/list", "tools").await?;
+    let resources = list("resources/list", "resources").await?;
+    let prompts = list("prompts/list", "prompts").await?;
+
+    let mut tools: Vec<Arc<dyn Tool>> = server_tools.iter()
+        .filter_map(|tool| ServerTool::new(Arc::clone(client), server, tool))
+        .map(|tool| Arc::new(tool) as Arc<dyn Tool>)
+        .collect();
+    if !resources.is_empty() {
+        tools.push(Arc::new(ReadResource::new(Arc::clone(client), server, &resources)));
+    }
+    if !prompts.is_empty() {
+        tools.push(Arc::new(GetPrompt::new(Arc::clone(client), server, &prompts)));
+    }
+
+    let names = |items: &[Value], field: &str| items.iter().filter_map(|item| item[field].as_str().map(str::to_string)).collect();
+    let status = McpStatus::Connected {
+        tools: names(&server_tools, "name"),
+        resources: names(&resources, "uri"),
+        prompts: names(&prompts, "name"),
+    };
+    println!("Debug: MCP server {} offers {} tools", server, tools.len());
+    Ok((tools, status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    /// Plays an MCP server on the other end of an in-memory pipe, answering
+    /// each request with `answer(method, params)`.
+    fn fake_server(answer: fn(&str, &Value) -> Value) -> Arc<McpClient> {
+        let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+        let (client_read, client_write) = tokio::io::split(client_end);
+        let (server_read, mut server_write) = tokio::io::split(server_end);
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(server_read).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let message: Value = serde_json::from_str(&line).unwrap();
+                let (Some(method), Some(id)) = (message["method"].as_str(), message.get("id")) else {
+                    continue;
+                };
+                let response = json!({ "jsonrpc": "2.0", "id": id, "result": answer(method, &message["params"]) });
+                server_write.write_all(format!("{}\n", response).as_bytes()).await.unwrap();
+            }
+        });
+        Arc::new(McpClient::connect(client_read, client_write))
+    }
+
+    fn answer(method: &str, params: &Value) -> Value {
+        match method {
+            "initialize" => json!({ "protocolVersion": "2024-11-05", "capabilities": { "tools": {}, "resources": {}, "prompts": {} } }),
+            // Two pages, to check that the cursor is followed.
+            "tools/list" if params["cursor"].is_null() => json!({
+                "tools": [{ "name": "search", "description": "Searches the wiki", "inputSchema": { "type": "object", "properties": { "query": { "type": "string" } } } }],
+                "nextCursor": "2",
+            }),
+            "tools/list" => json!({ "tools": [{ "name": "fail", "inputSchema": { "type": "object" } }] }),
+            "resources/list" => json!({ "resources": [{ "uri": "wiki://home", "name": "Home page" }] }),
+            "prompts/list" => json!({ "prompts": [{ "name": "summarize", "arguments": [{ "name": "page", "required": true }] }] }),
+            "tools/call" if params["name"] == "fail" => json!({ "content": [{ "type": "text", "text": "No access" }], "isError": true }),
+            "tools/call" => json!({ "content": [{ "type": "text", "text": format!("Found {}", params["arguments"]["query"]) }, { "type": "image", "data": "", "mimeType": "image/png" }] }),
+            "resources/read" => json!({ "contents": [{ "uri": params["uri"], "text": "Welcome" }] }),
+            "prompts/get" => json!({ "messages": [{ "role": "user", "content": { "type": "text", "text": format!("Summarize {}", params["arguments"]["page"]) } }] }),
+            _ => json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn offers_tools_resources_and_prompts_of_a_server() {
+        let client = fake_server(answer);
+        let (tools, status) = discover("wiki", &client).await.unwrap();
+        assert_eq!(status, McpStatus::Connected {
+            tools: vec!["search".into(), "fail".into()],
+            resources: vec!["wiki://home".into()],
+            prompts: vec!["summarize".into()],
+        });
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name()).collect();
+        assert_eq!(names, vec!["wiki__search", "wiki__fail", "wiki__read_resource", "wiki__get_prompt"]);
+        assert!(tools[2].description().contains("wiki://home (Home page)"));
+        assert!(tools[3].description().contains("summarize(page)"));
+
+        assert_eq!(tools[0].execute(json!({ "query": "rust" })).await.unwrap(), "Found \"rust\"\n[image content not shown]");
+        assert_eq!(tools[1].execute(json!({})).await.unwrap_err(), "No access");
+        assert_eq!(tools[2].execute(json!({ "uri": "wiki://home" })).await.unwrap(), "Welcome");
+        assert_eq!(tools[3].execute(json!({ "name": "summarize", "arguments": { "page": "home" } })).await.unwrap(), "user: Summarize \"home\"");
+    }
+
+    #[tokio::test]
+    async fn fails_requests_once_the_server_is_gone() {
+        let (client_end, server_end) = tokio::io::duplex(1024);
+        let (client_read, client_write) = tokio::io::split(client_end);
+        let client = McpClient::connect(client_read, client_write);
+        drop(server_end);
+        assert!(client.initialize().await.is_err());
+        let closed = async {
+            while !client.is_closed() {
+                tokio::task::yield_now().await;
+            }
+        };
+        assert!(tokio::time::timeout(std::time::Duration::from_secs(5), closed).await.is_ok());
+    }
+
+    #[test]
+    fn parses_arguments_and_environment() {
+        let config = McpServerConfig {
+            args: "-y\n@scope/server \n\n/path with spaces".into(),
+            env: "TOKEN=a=b\n\nDEBUG=".into(),
+            ..McpServerConfig::default()
+        };
+        assert_eq!(config.args(), vec!["-y", "@scope/server", "/path with spaces"]);
+        assert_eq!(config.env().unwrap(), vec![("TOKEN".into(), "a=b".into()), ("DEBUG".into(), String::new())]);
+        assert!(McpServerConfig { env: "TOKEN".into(), ..config }.env().is_err());
+    }
+
+    #[test]
+    fn names_tools_the_way_providers_accept() {
+        assert_eq!(server_tools::tool_name("Team wiki", "search.pages"), "Team_wiki__search_pages");
+        assert_eq!(server_tools::tool_name("s", &"x".repeat(100)).len(), 64);
+    }
+}
@@ -0,0 +1,228 @@
+use super::client::McpClient;
+use crate::tools::{string_input, Tool};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Tools may do real work, such as running a build, so they get longer than the list requests.
+const CALL_TIMEOUT: Duration = Duration::from_secs(300);
+// How many resources or prompts are named in a tool description.
+const MAX_LISTED: usize = 50;
+
+/// A tool of an MCP server, offered as `server__tool` so that servers can't
+/// shadow each other or the built-in tools.
+pub struct ServerTool {
+    pub client: Arc<McpClient>,
+    pub name: String,
+    pub remote_name: String,
+    pub description: String,
+    pub input_schema: Value,
+}
+
+impl ServerTool {
+    pub fn new(client: Arc<McpClient>, server: &str, tool: &Value) -> Option<Self> {
+        let remote_name = tool["name"].as_str()?.to_string();
+        let input_schema = match &tool["inputSchema"] {
+            schema @ Value::Object(_) => schema.clone(),
+            _ => json!({ "type": "object" }),
+        };
+        Some(Self {
+            name: tool_name(server, &remote_name),
+            description: tool["description"].as_str().unwrap_or_default().to_string(),
+            remote_name,
+            input_schema,
+            client,
+        })
+    }
+}
+
+#[async_trait]
+impl Tool for ServerTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn input_schema(&self) -> Value {
+        self.input_schema.clone()
+    }
+
+    async fn execute(&self, input: Value) -> Result<String, String> {
+        let result = self.client.request("tools/call", json!({ "name": self.remote_name, "arguments": input }), CALL_TIMEOUT).await?;
+        let text = content_text(&result["content"]);
+        if result["isError"].as_bool().unwrap_or(false) {
+            Err(text)
+        } else {
+            Ok(text)
+        }
+    }
+}
+
+/// Reads the resources a server offers, such as files or database rows.
+pub struct ReadResource {
+    client: Arc<McpClient>,
+    name: String,
+    description: String,
+}
+
+impl ReadResource {
+    pub fn new(client: Arc<McpClient>, server: &str, resources: &[Value]) -> Self {
+        let listed: Vec<String> = resources.iter().take(MAX_LISTED).filter_map(|resource| {
+            let uri = resource["uri"].as_str()?;
+            let mut line = format!("- {}", uri);
+            if let Some(name) = resource["name"].as_str().filter(|name| *name != uri) {
+                line.push_str(&format!(" ({})", name));
+            }
+            if let Some(description) = resource["description"].as_str() {
+                line.push_str(&format!(": {}", description));
+            }
+            Some(line)
+        }).collect();
+        let mut description = format!("Reads a resource of the {} server by its URI. Available resources:\n{}", server, listed.join("\n"));
+        if resources.len() > MAX_LISTED {
+            description.push_str(&format!("\n[{} more]", resources.len() - MAX_LISTED));
+        }
+        Self { client, name: tool_name(server, "read_resource"), description }
+    }
+}
+
+#[async_trait]
+impl Tool for ReadResource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "uri": { "type": "string", "description": "URI of the resource" }
+            },
+            "required": ["uri"]
+        })
+    }
+
+    async fn execute(&self, input: Value) -> Result<String, String> {
+        let uri = string_input(&input, "uri")?;
+        let result = self.client.request("resources/read", json!({ "uri": uri }), CALL_TIMEOUT).await?;
+        let contents = result["contents"].as_array().cloned().unwrap_or_default();
+        Ok(contents.iter().map(resource_text).collect::<Vec<_>>().join("\n"))
+    }
+}
+
+/// Fetches the prompt templates a server offers, filled in with arguments.
+pub struct GetPrompt {
+    client: Arc<McpClient>,
+    name: String,
+    description: String,
+}
+
+impl GetPrompt {
+    pub fn new(client: Arc<McpClient>, server: &str, prompts: &[Value]) -> Self {
+        let listed: Vec<String> = prompts.iter().take(MAX_LISTED).filter_map(|prompt| {
+            let mut line = format!("- {}", prompt["name"].as_str()?);
+            let arguments: Vec<String> = prompt["arguments"].as_array().into_iter().flatten().filter_map(|argument| {
+                let name = argument["name"].as_str()?;
+                Some(if argument["required"].as_bool().unwrap_or(false) { name.to_string() } else { format!("{}?", name) })
+            }).collect();
+            if !arguments.is_empty() {
+                line.push_str(&format!("({})", arguments.join(", ")));
+            }
+            if let Some(description) = prompt["description"].as_str() {
+                line.push_str(&format!(": {}", description));
+            }
+            Some(line)
+        }).collect();
+        let mut description = format!(
+            "Gets a prompt template of the {} server, filled in with the arguments. Optional arguments end in \"?\". Available prompts:\n{}",
+            server, listed.join("\n"),
+        );
+        if prompts.len() > MAX_LISTED {
+            description.push_str(&format!("\n[{} more]", prompts.len() - MAX_LISTED));
+        }
+        Self { client, name: tool_name(server, "get_prompt"), description }
+    }
+}
+
+#[async_trait]
+impl Tool for GetPrompt {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": "Name of the prompt" },
+                "arguments": { "type": "object", "description": "Argument values by name, all strings", "additionalProperties": { "type": "string" } }
+            },
+            "required": ["name"]
+        })
+    }
+
+    async fn execute(&self, input: Value) -> Result<String, String> {
+        let name = string_input(&input, "name")?;
+        let arguments = match &input["arguments"] {
+            Value::Null => json!({}),
+            arguments => arguments.clone(),
+        };
+        let result = self.client.request("prompts/get", json!({ "name": name, "arguments": arguments }), CALL_TIMEOUT).await?;
+        let mut parts: Vec<String> = result["description"].as_str().map(str::to_string).into_iter().collect();
+        for message in result["messages"].as_array().into_iter().flatten() {
+            let role = message["role"].as_str().unwrap_or("user");
+            parts.push(format!("{}: {}", role, content_text(&message["content"])));
+        }
+        Ok(parts.join("\n\n"))
+    }
+}
+
+/// The name a server's tool is offered under. Providers only accept letters,
+/// digits, `_` and `-`, up to 64 characters.
+pub fn tool_name(server: &str, name: &str) -> String {
+    format!("{}__{}", server, name)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .take(64)
+        .collect()
+}
+
+/// Joins the text of MCP content, a single block or a list of them. Other
+/// kinds of content are named but left out.
+pub fn content_text(content: &Value) -> String {
+    let blocks = match content {
+        Value::Array(blocks) => blocks.iter().collect(),
+        Value::Null => Vec::new(),
+        block => vec![block],
+    };
+    blocks.into_iter().map(|block| match block["type"].as_str() {
+        Some("text") => block["text"].as_str().unwrap_or_default().to_string(),
+        Some("resource") => resource_text(&block["resource"]),
+        Some("resource_link") => format!("[Resource {}]", block["uri"].as_str().unwrap_or_default()),
+        Some(kind) => format!("[{} content not shown]", kind),
+        None => block.to_string(),
+    }).collect::<Vec<_>>().join("\n")
+}
+
+fn resource_text(resource: &Value) -> String {
+    match resource["text"].as_str() {
+        Some(text) => text.to_string(),
+        None => format!(
+            "[Binary resource {} ({}) not shown]",
+            resource["uri"].as_str().unwrap_or_default(),
+            resource["mimeType"].as_str().unwrap_or("unknown type"),
+        ),
+    }
+}
@@ -0,0 +1,150 @@
+pub mod calculator;
+pub mod files;
+pub mod mcp;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// Longer results are cut so that one tool call can't fill the context window.
+const MAX_RESULT_CHARS: usize = 50_000;
+
+/// A local function the model may ask to run.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &str;
+    /// Tells the model what the tool does and when to use it.
+    fn description(&self) -> &str;
+    /// JSON Schema of the object the model passes as input.
+    fn input_schema(&self) -> Value;
+    /// Runs the tool. Errors go back to the model as the result, so it can correct the call.
+    async fn execute(&self, input: Value) -> Result<String, String>;
+}
+
+/// A tool as it is offered to the model in a request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub input_schema: Value,
+}
+
+/// A call the model made, with the id the result has to refer to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub input: Value,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolResult {
+    pub content: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_error: bool,
+}
+
+impl ToolResult {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self { content: message.into(), is_error: true }
+    }
+
+    pub fn denied() -> Self {
+        Self::error("The user denied this tool call.")
+    }
+}
+
+/// A call and, once it has been decided on and run, its result.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolUse {
+    pub call: ToolCall,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<ToolResult>,
+}
+
+/// The tools offered to the model in a chat.
+#[derive(Clone, Default)]
+pub struct Toolbox {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl Toolbox {
+    /// The calculator, plus the file tools if `allowed_dir` is set. They can't
+    /// see anything outside that directory.
+    pub fn builtin(allowed_dir: Option<PathBuf>) -> Self {
+        let mut toolbox = Self::default().with(Arc::new(calculator::Calculator));
+        if let Some(dir) = allowed_dir {
+            let sandbox = files::Sandbox::new(dir);
+            toolbox = toolbox
+                .with(Arc::new(files::ReadFile(sandbox.clone())))
+                .with(Arc::new(files::ListDirectory(sandbox)));
+        }
+        toolbox
+    }
+
+    pub fn with(mut self, tool: Arc<dyn Tool>) -> Self {
+        self.tools.push(tool);
+        self
+    }
+
+    pub fn specs(&self) -> Vec<ToolSpec> {
+        self.tools.iter().map(|tool| ToolSpec {
+            name: tool.name().to_string(),
+            description: tool.description().to_string(),
+            input_schema: tool.input_schema(),
+        }).collect()
+    }
+
+    pub async fn run(&self, call: &ToolCall) -> ToolResult {
+        let Some(tool) = self.tools.iter().find(|tool| tool.name() == call.name) else {
+            return ToolResult::error(format!("There is no tool named \"{}\".", call.name));
+        };
+        println!("Debug: Running tool {} with {}", call.name, call.input);
+        match tool.execute(call.input.clone()).await {
+            Ok(output) => ToolResult { content: truncate(output), is_error: false },
+            Err(e) => ToolResult::error(e),
+        }
+    }
+}
+
+fn truncate(output: String) -> String {
+    match output.char_indices().nth(MAX_RESULT_CHARS) {
+        Some((end, _)) => format!("{}\n[Output cut off after {} characters]", &output[..end], MAX_RESULT_CHARS),
+        None => output,
+    }
+}
+
+/// Reads a string field of a tool's input.
+pub fn string_input<'a>(input: &'a Value, field: &str) -> Result<&'a str, String> {
+    input[field].as_str().ok_or_else(|| format!("The input needs a \"{}\" string.", field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(name: &str, input: Value) -> ToolCall {
+        ToolCall { id: "call_1".into(), name: name.into(), input }
+    }
+
+    #[tokio::test]
+    async fn runs_tools_by_name() {
+        let toolbox = Toolbox::builtin(None);
+        assert_eq!(toolbox.specs().iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!["calculator"]);
+
+        let result = toolbox.run(&call("calculator", json!({ "expression": "6 * 7" }))).await;
+        assert_eq!(result, ToolResult { content: "42".into(), is_error: false });
+        assert!(toolbox.run(&call("calculator", json!({}))).await.is_error);
+        assert!(toolbox.run(&call("shell", json!({ "command": "ls" }))).await.is_error);
+    }
+
+    #[test]
+    fn cuts_off_long_output() {
+        let output = truncate("é".repeat(MAX_RESULT_CHARS + 10));
+        assert!(output.starts_with(&"é".repeat(MAX_RESULT_CHARS)));
+        assert!(output.ends_with("[Output cut off after 50000 characters]"));
+    }
+}
@@ -2,14 +2,14 @@ use egui::{Ui, ComboBox, RichText, Layout, Align};
 use crate::chat::Chat;
 use crate::settings::Settings;
 use crate::providers::ProviderTrait;
+use crate::providers::parameters::{self, Param};
 use crate::ui::ChatbotUi;
 use crate::ui::themes::Theme;
-use crate::app::ProfileType;
 use rfd::FileDialog;
 use std::path::Path;
 use std::sync::Arc;
 
-pub fn render(ui: &mut Ui, chat: &mut Chat, settings: &mut Settings, chatbot_ui: &mut ChatbotUi, providers: &[Arc<dyn ProviderTrait + Send + Sync>], theme: &Theme, current_profile: &mut ProfileType) {
+pub fn render(ui: &mut Ui, chat: &mut Chat, settings: &mut Settings, chatbot_ui: &mut ChatbotUi, providers: &[Arc<dyn ProviderTrait + Send + Sync>], theme: &Theme) {
     ui.with_layout(Layout::bottom_up(Align::LEFT), |ui| {
         ui.add_space(18.0);
         ui.visuals_mut().widgets.inactive.bg_fill = theme.model_provider_dropdown_bg_color;
@@ -54,9 +54,9 @@ pub fn render(ui: &mut Ui, chat: &mut Chat, settings: &mut Settings, chatbot_ui:
         .width(dropdown_width)
         .show_ui(ui, |ui| {
             if let Some(current_provider) = providers.iter().find(|p| p.name() == chatbot_ui.selected_provider) {
-                for model in current_provider.models() {
-                    if ui.selectable_value(&mut chatbot_ui.selected_model, model.0.to_string(), RichText::new(model.0).color(theme.model_provider_dropdown_text_color)).clicked() {
-                        chatbot_ui.selected_model = model.0.to_string();
+                for (model_name, _) in current_provider.models() {
+                    if ui.selectable_value(&mut chatbot_ui.selected_model, model_name.clone(), RichText::new(&model_name).color(theme.model_provider_dropdown_text_color)).clicked() {
+                        chatbot_ui.selected_model = model_name;
                         chatbot_ui.model_changed = true;
                     }
                 }
@@ -75,7 +75,7 @@ pub fn render(ui: &mut Ui, chat: &mut Chat, settings: &mut Settings, chatbot_ui:
             for provider in providers {
                 if ui.selectable_label(chatbot_ui.selected_provider == provider.name(), RichText::new(provider.name()).color(theme.model_provider_dropdown_text_color)).clicked() {
                     chatbot_ui.selected_provider = provider.name().to_string();
-                    chatbot_ui.selected_model = provider.models()[0].0.to_string();
+                    chatbot_ui.selected_model = provider.models()[0].0.clone();
                     chatbot_ui.model_changed = true;
                 }
             }
@@ -91,14 +91,29 @@ pub fn render(ui: &mut Ui, chat: &mut Chat, settings: &mut Settings, chatbot_ui:
 
             profile_frame.show(ui, |ui| {
                 ui.set_width(dropdown_width);
-                ui.horizontal(|ui| {
-                    for profile in [ProfileType::Coder, ProfileType::Normal, ProfileType::Creative].iter() {
-                        if ui.selectable_label(*current_profile == *profile, format!("{:?}", profile)).clicked() {
-                            *current_profile = *profile;
-                            if let Some(provider) = providers.iter().find(|p| p.name() == chatbot_ui.selected_provider) {
-                                provider.update_profile(*current_profile);
-                            }
-                            chat.update_profile(*current_profile);
+                ui.horizontal_wrapped(|ui| {
+                    let selected = settings.get_current_profile().name;
+                    let model = chat.get_current_model();
+                    let specs = chat.provider.parameter_specs(&model);
+                    let ignored: Vec<&str> = Param::ALL.iter()
+                        .filter(|param| parameters::find(specs, **param).is_none())
+                        .map(|param| param.label())
+                        .collect();
+                    let mut clicked = None;
+                    for profile in &settings.profiles {
+                        let mut label = ui.selectable_label(selected == profile.name, &profile.name);
+                        if !ignored.is_empty() {
+                            label = label.on_hover_text(format!("{} ignores: {}", model, ignored.join(", ")));
+                        }
+                        if label.clicked() {
+                            clicked = Some(profile.name.clone());
+                        }
+                    }
+                    if let Some(name) = clicked {
+                        settings.select_profile(&name);
+                        let profile = settings.get_current_profile();
+                        for provider in providers {
+                            provider.update_profile(profile.clone());
                         }
                     }
                 });
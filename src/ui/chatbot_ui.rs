@@ -1,14 +1,34 @@
-use egui::{Ui, ScrollArea, FontId, TextEdit, Button, Vec2, Image, Layout, Align};
+use egui::{Ui, ScrollArea, FontId, TextEdit, Button, Vec2, Image, Layout, Align, RichText};
 use crate::chat::Chat;
+use crate::chat::chat_core::ResponseUpdate;
+use crate::chat::compaction::Summary;
 use crate::settings;
 use crate::settings::Settings;
 use crate::app::Icons;
-use super::MessageView;
-use crate::providers::ProviderTrait;
+use super::{MessageView, Streaming};
+use crate::providers::{model_limits, parameters, pricing, ProviderTrait, RequestOptions};
+use crate::providers::parameters::Param;
+use crate::providers::StopReason;
 use crate::ui::themes::Theme;
 use std::sync::Arc;
-use crate::app::ProfileType;
+use std::time::{Duration, Instant};
 use crate::app::ChatbotAppState;
+use crate::attachments;
+use crate::message::{FileAttachment, ImageAttachment};
+use crate::chat::context_budget;
+use crate::json_schema;
+use rfd::FileDialog;
+
+const MAX_AUTO_CONTINUES: u32 = 3;
+
+// What the schema editor starts with when JSON mode is turned on.
+const SCHEMA_TEMPLATE: &str = r#"{
+  "type": "object",
+  "properties": {
+    "name": { "type": "string" }
+  },
+  "required": ["name"]
+}"#;
 
 pub struct ChatbotUi {
     pub input: String,
@@ -16,10 +36,31 @@ pub struct ChatbotUi {
     pub selected_model: String,
     pub is_loading: bool,
     pub current_response: String,
+    /// What the model has thought so far, shown above `current_response`.
+    current_reasoning: String,
     pub model_changed: bool,
     message_view: MessageView,
     pub custom_model_name: String,
     pub show_custom_model_popup: bool,
+    pub last_error: Option<String>,
+    /// Images attached to the message being written.
+    pending_images: Vec<ImageAttachment>,
+    /// Text files attached to the message being written.
+    pending_files: Vec<FileAttachment>,
+    retry_status: Option<(String, Instant)>,
+    system_prompt_draft: String,
+    options_draft: RequestOptions,
+    /// The schema being edited, as typed; `None` while JSON mode is off.
+    json_schema_draft: Option<String>,
+    /// Set while a cut-off reply is being continued.
+    continuing: bool,
+    /// Continuations started in a row without the user asking, capped by `MAX_AUTO_CONTINUES`.
+    auto_continues: u32,
+    // The chat file the drafts were loaded from, so switching chats reloads them.
+    drafts_file: Option<String>,
+    summary_draft: String,
+    // The summary the draft was loaded from, so a new summary replaces it.
+    summary_source: Option<Summary>,
 }
 
 impl ChatbotUi {
@@ -30,22 +71,31 @@ impl ChatbotUi {
             selected_model: initial_model,
             is_loading: false,
             current_response: String::new(),
+            current_reasoning: String::new(),
             model_changed: false,
             message_view: MessageView::new(),
             custom_model_name: String::new(),
             show_custom_model_popup: false,
+            last_error: None,
+            pending_images: Vec::new(),
+            pending_files: Vec::new(),
+            retry_status: None,
+            system_prompt_draft: String::new(),
+            options_draft: RequestOptions::default(),
+            json_schema_draft: None,
+            continuing: false,
+            auto_continues: 0,
+            drafts_file: None,
+            summary_draft: String::new(),
+            summary_source: None,
         }
     }
 
-    pub fn render(&mut self, ui: &mut Ui, chat: &mut Chat, settings: &mut Settings, icons: &Icons, providers: &[Arc<dyn ProviderTrait + Send + Sync>], theme: &Theme, _current_profile: &mut ProfileType, _app_state: &mut ChatbotAppState) {
+    pub fn render(&mut self, ui: &mut Ui, chat: &mut Chat, settings: &mut Settings, icons: &Icons, providers: &[Arc<dyn ProviderTrait + Send + Sync>], theme: &Theme, _app_state: &mut ChatbotAppState) {
         if self.model_changed {
             if let Some(provider) = providers.iter().find(|p| p.name() == self.selected_provider) {
                 chat.update_provider(Arc::clone(provider));
-                let model_to_use = if self.selected_model == "Other" {
-                    self.custom_model_name.clone()
-                } else {
-                    self.selected_model.clone()
-                };
+                let model_to_use = self.selected_model_name();
                 provider.set_current_model(model_to_use.clone());
                 chat.set_current_model(&model_to_use);
                 println!("Debug: Provider updated to {} with model {}", self.selected_provider, model_to_use);
@@ -53,23 +103,119 @@ impl ChatbotUi {
             self.model_changed = false;
         }
 
+        let current_file = chat.get_current_file();
+        if self.drafts_file != current_file {
+            self.system_prompt_draft = chat.get_system_prompt().unwrap_or_default();
+            self.options_draft = chat.get_options();
+            self.json_schema_draft = self.options_draft.json_schema.as_ref()
+                .map(|schema| serde_json::to_string_pretty(schema).unwrap_or_default());
+            self.drafts_file = current_file;
+        }
+        let summary = chat.get_summary();
+        if self.summary_source != summary {
+            self.summary_draft = summary.as_ref().map(|s| s.text.clone()).unwrap_or_default();
+            self.summary_source = summary.clone();
+        }
+
         egui::CentralPanel::default().show_inside(ui, |ui| {
             ui.vertical(|ui| {
+                egui::CollapsingHeader::new("System prompt").id_source("chat_system_prompt").show(ui, |ui| {
+                    ui.add(TextEdit::multiline(&mut self.system_prompt_draft)
+                        .desired_rows(3)
+                        .desired_width(f32::INFINITY)
+                        .hint_text("No system prompt for this chat"));
+                    let changed = self.system_prompt_draft.trim() != chat.get_system_prompt().unwrap_or_default();
+                    if ui.add_enabled(changed, Button::new("Save")).clicked() {
+                        if let Err(e) = chat.set_system_prompt(&self.system_prompt_draft) {
+                            self.last_error = Some(format!("Failed to save system prompt: {}", e));
+                        }
+                    }
+                });
+
+                egui::CollapsingHeader::new("Response options").id_source("chat_options").show(ui, |ui| {
+                    self.render_options_editor(ui, chat, theme);
+                });
+
+                if let Some(summary) = summary {
+                    egui::CollapsingHeader::new(format!("Summary of the first {} messages", summary.covers)).id_source("chat_summary").show(ui, |ui| {
+                        ui.add(TextEdit::multiline(&mut self.summary_draft)
+                            .desired_rows(4)
+                            .desired_width(f32::INFINITY));
+                        ui.horizontal(|ui| {
+                            let mut enabled = summary.enabled;
+                            if ui.checkbox(&mut enabled, "Send instead of the summarized messages").changed() {
+                                if let Err(e) = chat.set_summary(Some(Summary { enabled, ..summary.clone() })) {
+                                    self.last_error = Some(format!("Failed to save summary: {}", e));
+                                }
+                            }
+                            let changed = !self.summary_draft.trim().is_empty() && self.summary_draft.trim() != summary.text;
+                            if ui.add_enabled(changed, Button::new("Save")).clicked() {
+                                let text = self.summary_draft.trim().to_string();
+                                if let Err(e) = chat.set_summary(Some(Summary { text, ..summary.clone() })) {
+                                    self.last_error = Some(format!("Failed to save summary: {}", e));
+                                }
+                            }
+                            if ui.button("Delete").on_hover_text("Send the messages again; a new summary is made when the chat gets long").clicked() {
+                                if let Err(e) = chat.set_summary(None) {
+                                    self.last_error = Some(format!("Failed to delete summary: {}", e));
+                                }
+                            }
+                        });
+                    });
+                }
+
+                let (usage, cost_usd) = chat.usage_totals();
+                if usage.input_tokens.unwrap_or(0) + usage.output_tokens.unwrap_or(0) > 0 {
+                    ui.label(RichText::new(format!(
+                        "This chat: {} tokens in, {} tokens out, {}",
+                        usage.input_tokens.unwrap_or(0),
+                        usage.output_tokens.unwrap_or(0),
+                        pricing::format_cost(cost_usd)
+                    )).small().color(theme.code_block_language_color));
+                }
+
                 let available_height = ui.available_height();
                 let input_height = 80.0;
+                let attachments_height = 28.0;
                 let padding = 10.0;
-                let message_height = available_height - input_height - padding * 2.0;
+                let message_height = available_height - input_height - attachments_height - padding * 2.0;
 
                 ScrollArea::vertical()
                     .auto_shrink([false; 2])
                     .stick_to_bottom(true)
                     .max_height(message_height)
                     .show(ui, |ui| {
-                        self.message_view.render_messages(ui, chat, &self.current_response, self.is_loading, theme);
+                        let streaming = Streaming {
+                            response: &self.current_response,
+                            reasoning: &self.current_reasoning,
+                            is_loading: self.is_loading,
+                            continuing: self.continuing,
+                        };
+                        if self.message_view.render_messages(ui, chat, &streaming, theme) {
+                            self.auto_continues = 0;
+                            self.start_continuation(chat);
+                        }
                     });
 
                 ui.add_space(padding);
 
+                if let Some((reason, until)) = &self.retry_status {
+                    let remaining = until.saturating_duration_since(Instant::now()).as_secs_f32().ceil();
+                    ui.colored_label(egui::Color32::YELLOW, format!("{}, retrying in {}s", reason, remaining));
+                    ui.ctx().request_repaint_after(Duration::from_millis(250));
+                }
+
+                if let Some(error) = self.last_error.clone() {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(egui::Color32::RED, error);
+                        if ui.small_button("Dismiss").clicked() {
+                            self.last_error = None;
+                        }
+                    });
+                }
+
+                self.render_attachments(ui, chat);
+
                 ui.horizontal(|ui| {
                     ui.with_layout(Layout::left_to_right(Align::TOP).with_main_wrap(false), |ui| {
                         let input_width = ui.available_width() - 50.0;
@@ -94,19 +240,24 @@ impl ChatbotUi {
                             || (!self.is_loading && ui.input(|i| i.key_pressed(egui::Key::Enter) && !i.modifiers.shift))
                         {
                             if self.is_loading {
+                                // The partial answer stays on screen until the cancelled task hands it back for saving.
                                 chat.stop_processing();
-                                self.is_loading = false;
-                                self.current_response.clear();
-                            } else if !self.input.trim().is_empty() {
+                                self.retry_status = None;
+                            } else if let Some(error) = self.oversized_prompt(chat, &self.pending_files) {
+                                self.last_error = Some(error);
+                            } else if !self.input.trim().is_empty() || !self.pending_images.is_empty() || !self.pending_files.is_empty() {
                                 println!("Debug: Processing input with model: {}", self.selected_model);
-                                let model_to_use = if self.selected_model == "Other" {
-                                    self.custom_model_name.clone()
-                                } else {
-                                    self.selected_model.clone()
-                                };
-                                chat.process_input(std::mem::take(&mut self.input), model_to_use);
+                                chat.process_input(
+                                    std::mem::take(&mut self.input),
+                                    std::mem::take(&mut self.pending_images),
+                                    std::mem::take(&mut self.pending_files),
+                                    self.selected_model_name(),
+                                );
+                                self.auto_continues = 0;
+                                self.last_error = None;
                                 self.is_loading = true;
                                 self.current_response.clear();
+                                self.current_reasoning.clear();
                             }
                         }
                     });
@@ -114,27 +265,60 @@ impl ChatbotUi {
             });
         });
 
-        settings::render(settings, ui.ctx(), icons);
+        let model = chat.get_current_model();
+        settings::render(settings, ui.ctx(), icons, &settings::CurrentModel { name: &model, specs: chat.provider.parameter_specs(&model) });
 
         if chat.is_processing() {
             self.is_loading = true;
         }
 
-        while let Some((chunk, is_complete)) = chat.check_ui_updates() {
-            if is_complete {
-                chat.add_message(chunk, false);
-                self.current_response.clear();
-                self.is_loading = false;
-            } else {
-                self.current_response.push_str(&chunk);
+        while let Some(update) = chat.check_ui_updates() {
+            match update {
+                ResponseUpdate::Delta(chunk) => {
+                    self.retry_status = None;
+                    self.current_response.push_str(&chunk);
+                }
+                ResponseUpdate::Reasoning(chunk) => {
+                    self.retry_status = None;
+                    self.current_reasoning.push_str(&chunk);
+                }
+                ResponseUpdate::Retrying { reason, until } => self.retry_status = Some((reason, until)),
+                ResponseUpdate::ToolResults(results) => chat.set_tool_results(&results),
+                ResponseUpdate::Summarized(summary) => {
+                    if let Err(e) = chat.set_summary(Some(summary)) {
+                        eprintln!("Failed to save chat summary: {}", e);
+                    }
+                }
+                ResponseUpdate::Complete { content, meta, replaces_last } => {
+                    let cut_off = meta.stop_reason == Some(StopReason::MaxTokens);
+                    // A failed request produces no text; keep whatever partial answer arrived.
+                    if replaces_last {
+                        chat.replace_last_message(content, *meta);
+                    } else if !content.is_empty() || !meta.tool_calls.is_empty() {
+                        chat.add_message_with_meta(content, false, *meta);
+                    }
+                    self.current_response.clear();
+                    self.current_reasoning.clear();
+                    self.retry_status = None;
+                    self.is_loading = false;
+                    self.continuing = false;
+                    if cut_off && settings.auto_continue && self.auto_continues < MAX_AUTO_CONTINUES {
+                        self.auto_continues += 1;
+                        println!("Debug: Continuing cut-off reply automatically ({} of {})", self.auto_continues, MAX_AUTO_CONTINUES);
+                        self.start_continuation(chat);
+                    }
+                }
             }
             ui.ctx().request_repaint();
         }
 
         if let Some(error) = chat.check_error_updates() {
-            chat.add_message(error, false);
+            self.last_error = Some(error);
+            self.retry_status = None;
             self.is_loading = false;
+            self.continuing = false;
             self.current_response.clear();
+            self.current_reasoning.clear();
             ui.ctx().request_repaint();
         }
 
@@ -147,4 +331,185 @@ impl ChatbotUi {
     pub fn clear_message_cache(&mut self) {
         self.message_view.clear_cache();
     }
-}
\ No newline at end of file
+}
+
+impl ChatbotUi {
+    fn selected_model_name(&self) -> String {
+        if self.selected_model == "Other" {
+            self.custom_model_name.clone()
+        } else {
+            self.selected_model.clone()
+        }
+    }
+
+    /// The attach buttons and the images and files attached to the next message, each removable.
+    fn render_attachments(&mut self, ui: &mut Ui, chat: &Chat) {
+        ui.horizontal_wrapped(|ui| {
+            if ui.small_button("Attach file…").on_hover_text("Send text or source files along with the message").clicked() {
+                if let Some(paths) = FileDialog::new().pick_files() {
+                    for path in paths {
+                        match attachments::file_from_path(&path) {
+                            Ok(file) => {
+                                self.pending_files.push(file);
+                                if let Some(error) = self.oversized_prompt(chat, &self.pending_files) {
+                                    let file = self.pending_files.pop().unwrap();
+                                    self.last_error = Some(format!("{} was not attached. {}", file.name, error));
+                                }
+                            }
+                            Err(e) => self.last_error = Some(e),
+                        }
+                    }
+                }
+            }
+            if ui.small_button("Attach image…").clicked() {
+                if let Some(paths) = FileDialog::new().add_filter("Images", attachments::IMAGE_EXTENSIONS).pick_files() {
+                    for path in paths {
+                        match attachments::image_from_file(&path) {
+                            Ok(image) => self.pending_images.push(image),
+                            Err(e) => self.last_error = Some(e),
+                        }
+                    }
+                }
+            }
+            if ui.small_button("Paste image").on_hover_text("Attach the image on the clipboard, e.g. a screenshot").clicked() {
+                match attachments::image_from_clipboard() {
+                    Ok(image) => self.pending_images.push(image),
+                    Err(e) => self.last_error = Some(e),
+                }
+            }
+
+            let mut removed = None;
+            for (index, image) in self.pending_images.iter().enumerate() {
+                if let Some(texture) = self.message_view.thumbnail(ui.ctx(), image) {
+                    ui.add(Image::new(&texture).max_size(Vec2::splat(24.0))).on_hover_text(&image.name);
+                } else {
+                    ui.label(&image.name);
+                }
+                if ui.small_button("x").on_hover_text("Remove").clicked() {
+                    removed = Some(index);
+                }
+            }
+            if let Some(index) = removed {
+                self.pending_images.remove(index);
+            }
+
+            let mut removed = None;
+            for (index, file) in self.pending_files.iter().enumerate() {
+                ui.label(RichText::new(&file.name).small())
+                    .on_hover_text(format!("{} · {} lines", file.language, file.content.lines().count()));
+                if ui.small_button("x").on_hover_text("Remove").clicked() {
+                    removed = Some(index);
+                }
+            }
+            if let Some(index) = removed {
+                self.pending_files.remove(index);
+            }
+        });
+    }
+
+    /// Why the message being written can't be sent with `files`: they and the
+    /// text must fit the selected model's context window on their own.
+    fn oversized_prompt(&self, chat: &Chat, files: &[FileAttachment]) -> Option<String> {
+        if files.is_empty() {
+            return None;
+        }
+        let model = self.selected_model_name();
+        let budget = context_budget::prompt_budget(model_limits::context_window(chat.provider.as_ref(), &model));
+        let tokens: usize = files.iter().map(|file| context_budget::estimate_tokens(&file.as_context())).sum::<usize>()
+            + context_budget::estimate_tokens(&self.input);
+        (tokens > budget).then(|| format!(
+            "The attached files and message take about {} tokens, more than the {} that {} has room for",
+            tokens, budget, model
+        ))
+    }
+
+    /// Streams the rest of the last reply, shown together with what is already there.
+    fn start_continuation(&mut self, chat: &Chat) {
+        let Some(last) = chat.get_messages().pop() else { return };
+        chat.continue_response(self.selected_model_name());
+        self.last_error = None;
+        self.is_loading = true;
+        self.continuing = true;
+        self.current_response = last.content().to_string();
+        self.current_reasoning = last.meta().reasoning.as_ref().map(|r| r.text.clone()).unwrap_or_default();
+    }
+
+    /// Per-chat reply length, stop sequences and JSON mode, checked against the current model.
+    fn render_options_editor(&mut self, ui: &mut Ui, chat: &Chat, theme: &Theme) {
+        let model = chat.get_current_model();
+        let limit = model_limits::max_output_tokens(chat.provider.as_ref(), &model);
+        let specs = chat.provider.parameter_specs(&model);
+        let mut problems = Vec::new();
+
+        ui.horizontal(|ui| {
+            let mut limited = self.options_draft.max_tokens.is_some();
+            if ui.checkbox(&mut limited, "Max reply tokens").on_hover_text("Off uses the generation profile's setting").changed() {
+                self.options_draft.max_tokens = limited.then(|| limit.unwrap_or(4096).min(4096));
+            }
+            if let Some(max_tokens) = self.options_draft.max_tokens.as_mut() {
+                ui.add(egui::DragValue::new(max_tokens).range(1..=1_000_000));
+            }
+            if let Some(limit) = limit {
+                ui.label(RichText::new(format!("{} writes at most {}", model, limit)).small().color(theme.code_block_language_color));
+            }
+        });
+        if let (Some(max_tokens), Some(limit)) = (self.options_draft.max_tokens, limit) {
+            if max_tokens > limit {
+                problems.push(format!("{} can't write more than {} tokens.", model, limit));
+            }
+        }
+
+        let mut own_stops = self.options_draft.stop_sequences.is_some();
+        if ui.checkbox(&mut own_stops, "Own stop sequences").on_hover_text("Off uses the generation profile's stop sequences").changed() {
+            self.options_draft.stop_sequences = own_stops.then(Vec::new);
+        }
+        if let Some(stop_sequences) = self.options_draft.stop_sequences.as_mut() {
+            settings::stop_sequences_editor(ui, stop_sequences);
+            match parameters::find(specs, Param::StopSequences) {
+                None if !stop_sequences.is_empty() => problems.push(format!("{} doesn't support stop sequences.", model)),
+                Some(spec) if stop_sequences.len() > spec.max as usize => {
+                    problems.push(format!("{} takes at most {} stop sequences.", model, spec.max));
+                }
+                _ => {}
+            }
+        }
+
+        let mut json_mode = self.json_schema_draft.is_some();
+        if ui.checkbox(&mut json_mode, "JSON mode").on_hover_text("Replies are JSON objects matching a schema, checked when they complete").changed() {
+            self.json_schema_draft = json_mode.then(|| SCHEMA_TEMPLATE.to_string());
+        }
+        self.options_draft.json_schema = None;
+        if let Some(schema_text) = self.json_schema_draft.as_mut() {
+            ui.add(TextEdit::multiline(schema_text)
+                .code_editor()
+                .desired_rows(6)
+                .desired_width(f32::INFINITY)
+                .hint_text("JSON Schema of the reply"));
+            match json_schema::parse_schema(schema_text) {
+                Ok(schema) => self.options_draft.json_schema = Some(schema),
+                Err(e) => problems.push(e),
+            }
+            ui.label(RichText::new("Tools are off in JSON mode: the reply itself is the answer.")
+                .small()
+                .color(theme.code_block_language_color));
+            if !chat.provider.supports_json_schema() {
+                ui.label(RichText::new(format!("{} can't be held to a schema: the system prompt asks for it, and the reply is checked.", chat.provider.name()))
+                    .small()
+                    .color(theme.code_block_language_color));
+            }
+        }
+
+        for problem in &problems {
+            ui.colored_label(egui::Color32::RED, problem);
+        }
+        let mut draft = self.options_draft.clone();
+        if let Some(stop_sequences) = draft.stop_sequences.as_mut() {
+            stop_sequences.retain(|stop| !stop.is_empty());
+        }
+        if ui.add_enabled(problems.is_empty() && draft != chat.get_options(), Button::new("Save")).clicked() {
+            if let Err(e) = chat.set_options(draft) {
+                self.last_error = Some(format!("Failed to save response options: {}", e));
+            }
+        }
+    }
+}
@@ -1,13 +1,44 @@
-use egui::{Ui, ScrollArea, Align, Frame, Stroke, Rounding, Label, Layout, RichText};
+use egui::{Ui, ScrollArea, Align, Frame, Stroke, Rounding, Label, Layout, RichText, TextureHandle, Vec2};
 use crate::chat::Chat;
 use crate::ui::themes::Theme;
-use crate::message::Message;
+use crate::message::{FileAttachment, ImageAttachment, Message, MessageMeta, Reasoning};
+use crate::providers::{pricing, StopReason};
+use crate::tools::ToolUse;
+use crate::json_schema;
+use serde_json::Value;
 use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use crate::ui::syntax_highlighter::{SyntaxHighlighter, HighlightedBlock};
 
+const THUMBNAIL_SIZE: u32 = 160;
+
+/// The reply being streamed, drawn after the saved messages.
+pub struct Streaming<'a> {
+    pub response: &'a str,
+    /// What the model has thought so far, shown above `response`.
+    pub reasoning: &'a str,
+    pub is_loading: bool,
+    /// Set while a cut-off reply is being continued.
+    pub continuing: bool,
+}
+
+/// What one message frame shows below the name of its author.
+struct FrameContent<'a> {
+    blocks: &'a [HighlightedBlock],
+    thumbnails: &'a [(&'a str, Option<TextureHandle>)],
+    files: &'a [(&'a FileAttachment, &'a [HighlightedBlock])],
+    /// The parsed reply of a chat in JSON mode, shown as a tree instead of the text.
+    json: Option<&'a Value>,
+    meta: Option<&'a MessageMeta>,
+}
+
 pub struct MessageView {
     syntax_highlighter: SyntaxHighlighter,
     message_cache: HashMap<String, Vec<HighlightedBlock>>,
+    // `None` marks images that failed to decode, so they aren't retried every frame.
+    thumbnails: HashMap<u64, Option<TextureHandle>>,
+    // Replies in JSON mode by content, parsed once; `None` if they aren't valid JSON.
+    json_replies: HashMap<String, Option<Value>>,
 }
 
 impl MessageView {
@@ -15,43 +46,125 @@ impl MessageView {
         Self {
             syntax_highlighter: SyntaxHighlighter::new(),
             message_cache: HashMap::new(),
+            thumbnails: HashMap::new(),
+            json_replies: HashMap::new(),
         }
     }
 
-    pub fn render_messages(&mut self, ui: &mut Ui, chat: &Chat, current_response: &str, is_loading: bool, theme: &Theme) {
+    /// A texture of `image` scaled down to thumbnail size, decoded once and cached.
+    pub fn thumbnail(&mut self, ctx: &egui::Context, image: &ImageAttachment) -> Option<TextureHandle> {
+        let key = thumbnail_key(image);
+        self.thumbnails.entry(key).or_insert_with(|| {
+            let decoded = image.bytes().and_then(|bytes| image::load_from_memory(&bytes).ok());
+            let Some(decoded) = decoded else {
+                eprintln!("Failed to decode image {}", image.name);
+                return None;
+            };
+            let thumbnail = decoded.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgba8();
+            let size = [thumbnail.width() as usize, thumbnail.height() as usize];
+            let pixels = egui::ColorImage::from_rgba_unmultiplied(size, &thumbnail);
+            Some(ctx.load_texture(format!("thumbnail-{}", key), pixels, egui::TextureOptions::default()))
+        }).clone()
+    }
+
+    /// Draws the chat and returns whether Continue was clicked on a reply cut
+    /// off at the token limit. While continuing, the last message is shown
+    /// as part of the streamed response instead.
+    pub fn render_messages(&mut self, ui: &mut Ui, chat: &Chat, streaming: &Streaming, theme: &Theme) -> bool {
         ScrollArea::vertical()
             .auto_shrink([false; 2])
             .stick_to_bottom(true)
             .show(ui, |ui| {
-                let messages = chat.get_messages();
-                for message in messages.iter() {
-                    self.render_message(ui, message, theme);
+                let mut messages = chat.get_messages();
+                if streaming.continuing {
+                    messages.pop();
+                }
+                let summarized = chat.get_summary().filter(|s| s.enabled).map_or(0, |s| s.covers.min(messages.len()));
+                let excluded = (*chat.excluded_messages.lock().unwrap()).max(summarized);
+                for (index, message) in messages.iter().enumerate() {
+                    if index < excluded {
+                        // Dimmed: summarized, or left out of the last request to fit the context window.
+                        ui.scope(|ui| {
+                            ui.multiply_opacity(0.4);
+                            self.render_message(ui, message, theme);
+                            MessageView::render_tool_calls(ui, chat, &message.meta().tool_calls, theme);
+                        });
+                    } else {
+                        self.render_message(ui, message, theme);
+                        MessageView::render_tool_calls(ui, chat, &message.meta().tool_calls, theme);
+                    }
+                    if index + 1 == summarized {
+                        let note = if summarized == 1 {
+                            "The message above is sent as part of the chat summary.".to_string()
+                        } else {
+                            format!("The {} messages above are sent as part of the chat summary.", summarized)
+                        };
+                        ui.label(RichText::new(note).small().italics().color(theme.code_block_language_color));
+                    }
+                    if index + 1 == excluded && excluded > summarized {
+                        let count = excluded - summarized;
+                        let note = if count == 1 {
+                            "The message above was not sent with the last request: it doesn't fit the model's context window.".to_string()
+                        } else {
+                            format!("The {} messages above were not sent with the last request: they don't fit the model's context window.", count)
+                        };
+                        ui.label(RichText::new(note).small().italics().color(theme.code_block_language_color));
+                    }
                 }
 
-                if !current_response.is_empty() {
-                    self.render_current_response(ui, current_response, chat.get_current_model(), theme);
+                let cut_off = messages.last().is_some_and(|m| !m.is_user() && m.meta().stop_reason == Some(StopReason::MaxTokens));
+                let continue_clicked = !streaming.is_loading && cut_off && ui.button("Continue")
+                    .on_hover_text("Have the model finish this reply")
+                    .clicked();
+
+                if !streaming.response.is_empty() || !streaming.reasoning.is_empty() {
+                    self.render_current_response(ui, streaming.response, streaming.reasoning, chat.get_current_model(), theme);
                 }
 
-                if is_loading {
+                if streaming.is_loading {
                     ui.add(egui::Spinner::new());
                 }
-            });
+                continue_clicked
+            })
+            .inner
     }
 
     fn render_message(&mut self, ui: &mut Ui, message: &Message, theme: &Theme) {
+        let thumbnails: Vec<_> = message.images().iter().map(|image| (image.name.as_str(), self.thumbnail(ui.ctx(), image))).collect();
         let cache_key = format!("{}-{}", message.content(), message.is_user());
-        let highlighted_blocks = self.message_cache.entry(cache_key.clone())
-            .or_insert_with(|| self.syntax_highlighter.highlight_message(
-                message.content(),
-                message.is_user(),
-                theme,
-                matches!(theme.name.as_str(), "Light" | "Olive and Tan"),
-                false
-            ));
-        MessageView::render_message_frame(ui, message.is_user(), highlighted_blocks, message.model(), theme);
+        let file_keys: Vec<String> = message.files().iter().map(|file| format!("file:{}\n{}", file.language, file.content)).collect();
+        let dark_text = matches!(theme.name.as_str(), "Light" | "Olive and Tan");
+        if !self.message_cache.contains_key(&cache_key) {
+            let blocks = self.syntax_highlighter.highlight_message(message.content(), message.is_user(), theme, dark_text, false);
+            self.message_cache.insert(cache_key.clone(), blocks);
+        }
+        for (file, key) in message.files().iter().zip(&file_keys) {
+            if !self.message_cache.contains_key(key) {
+                // Highlighted as a code block, the way the model sees the file.
+                let markdown = format!("```{}\n{}\n```", file.language, file.content.trim_end());
+                let blocks = self.syntax_highlighter.highlight_message(&markdown, message.is_user(), theme, dark_text, false);
+                self.message_cache.insert(key.clone(), blocks);
+            }
+        }
+        let files: Vec<_> = message.files().iter().zip(&file_keys).map(|(file, key)| (file, self.message_cache[key].as_slice())).collect();
+        let json = if message.meta().schema_errors.is_some() {
+            self.json_replies.entry(message.content().to_string())
+                .or_insert_with(|| json_schema::parse_reply(message.content()).ok())
+                .as_ref()
+        } else {
+            None
+        };
+        let content = FrameContent {
+            blocks: &self.message_cache[&cache_key],
+            thumbnails: &thumbnails,
+            files: &files,
+            json,
+            meta: Some(message.meta()),
+        };
+        MessageView::render_message_frame(ui, message.is_user(), message.model(), content, theme);
     }
 
-    fn render_current_response(&self, ui: &mut Ui, content: &str, model: String, theme: &Theme) {
+    fn render_current_response(&self, ui: &mut Ui, content: &str, reasoning: &str, model: String, theme: &Theme) {
         let message = Message::new(content.to_string(), false, Some(model));
         let highlighted_blocks = self.syntax_highlighter.highlight_message(
             message.content(),
@@ -60,10 +173,14 @@ impl MessageView {
             matches!(theme.name.as_str(), "Light" | "Olive and Tan"),
             true
         );
-        MessageView::render_message_frame(ui, message.is_user(), &highlighted_blocks, message.model(), theme);
+        let reasoning = Some(Reasoning { text: reasoning.to_string(), signature: None }).filter(|r| !r.text.is_empty());
+        let meta = MessageMeta { reasoning, ..MessageMeta::default() };
+        let content = FrameContent { blocks: &highlighted_blocks, thumbnails: &[], files: &[], json: None, meta: Some(&meta) };
+        MessageView::render_message_frame(ui, message.is_user(), message.model(), content, theme);
     }
 
-    fn render_message_frame(ui: &mut Ui, is_user: bool, highlighted_blocks: &[HighlightedBlock], model: Option<&str>, theme: &Theme) {
+    fn render_message_frame(ui: &mut Ui, is_user: bool, model: Option<&str>, content: FrameContent, theme: &Theme) {
+        let FrameContent { blocks: highlighted_blocks, thumbnails, files, json, meta } = content;
         let (border_color, background_color, name_color) = if is_user {
             (theme.user_message_border, theme.user_message_bg, theme.user_name_text_color)
         } else {
@@ -85,29 +202,78 @@ impl MessageView {
                     };
                     ui.label(prefix);
 
-                    for block in highlighted_blocks {
-                        match block {
-                            HighlightedBlock::Text(job) => {
-                                ui.add(Label::new(job.clone()).wrap());
-                            },
-                            HighlightedBlock::Code { language, job } => {
-                                ui.add_space(5.0);
-                                Frame::none()
-                                    .fill(theme.code_block_bg)
-                                    .stroke(Stroke::new(1.0, theme.code_block_border))
-                                    .rounding(Rounding::same(5.0))
-                                    .outer_margin(0.0)
-                                    .inner_margin(18.0)
-                                    .show(ui, |ui| {
-                                        ui.set_max_width(ui.available_width() * 0.99);
-                                        if !language.is_empty() {
-                                            ui.label(RichText::new(language).small().color(theme.code_block_language_color));
-                                        }
-                                        ui.add(Label::new(job.clone()).wrap());
-                                    });
-                                ui.add_space(5.0);
+                    // Dimmed and collapsed: it is how the model got to the answer, not part of it.
+                    if let Some(reasoning) = meta.and_then(|m| m.reasoning.as_ref()) {
+                        let words = reasoning.text.split_whitespace().count();
+                        // Keyed by how the reasoning starts, so it stays open while it streams in.
+                        let start: String = reasoning.text.chars().take(80).collect();
+                        ui.scope(|ui| {
+                            ui.multiply_opacity(0.6);
+                            egui::CollapsingHeader::new(RichText::new(format!("Reasoning · {} word{}", words, if words == 1 { "" } else { "s" })).small().italics().color(theme.code_block_language_color))
+                                .id_source(("reasoning", model, start))
+                                .show(ui, |ui| {
+                                    ui.add(Label::new(RichText::new(&reasoning.text).small().italics()).wrap());
+                                });
+                        });
+                    }
+
+                    if !thumbnails.is_empty() {
+                        ui.horizontal_wrapped(|ui| {
+                            for (name, texture) in thumbnails {
+                                match texture {
+                                    Some(texture) => {
+                                        ui.add(egui::Image::new(texture).max_size(Vec2::splat(THUMBNAIL_SIZE as f32))).on_hover_text(*name);
+                                    }
+                                    None => {
+                                        ui.label(RichText::new(format!("[{} can't be shown]", name)).small().italics().color(theme.code_block_language_color));
+                                    }
+                                }
+                            }
+                        });
+                    }
+
+                    // Collapsed by default: a file can be far longer than the message it came with.
+                    for (index, (file, blocks)) in files.iter().enumerate() {
+                        let lines = file.content.lines().count();
+                        let title = format!("{} · {} · {} line{}", file.name, file.language, lines, if lines == 1 { "" } else { "s" });
+                        egui::CollapsingHeader::new(RichText::new(title).small().color(theme.code_block_language_color))
+                            .id_source(("attached-file", index, &file.name, file.content.len()))
+                            .show(ui, |ui| MessageView::render_blocks(ui, blocks, theme));
+                    }
+
+                    match json {
+                        Some(value) => {
+                            // Keyed by the value, so that each reply keeps its own open and closed nodes.
+                            MessageView::render_json_tree(ui, None, value, ui.id().with(("json-reply", value.to_string())), 0, theme);
+                            if ui.small_button("Copy JSON").clicked() {
+                                let pretty = serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string());
+                                ui.output_mut(|output| output.copied_text = pretty);
+                            }
+                        }
+                        None => MessageView::render_blocks(ui, highlighted_blocks, theme),
+                    }
+                    match meta.and_then(|m| m.schema_errors.as_ref()) {
+                        Some(errors) if errors.is_empty() => {
+                            ui.label(RichText::new("Matches the schema").small().color(egui::Color32::from_rgb(80, 180, 80)));
+                        }
+                        Some(errors) => {
+                            ui.label(RichText::new("Doesn't match the schema:").small().strong().color(egui::Color32::RED));
+                            for error in errors {
+                                ui.add(Label::new(RichText::new(error).small().color(egui::Color32::RED)).wrap());
                             }
                         }
+                        None => {}
+                    }
+
+                    if let Some(reason) = meta.and_then(|m| m.stop_reason.as_ref()).filter(|r| **r != StopReason::EndTurn) {
+                        ui.label(RichText::new(format!("Response ended: {}", reason)).small().italics().color(theme.code_block_language_color));
+                    }
+                    if let Some(usage) = meta.and_then(|m| m.usage.as_ref()) {
+                        let tokens = |count: Option<u64>| count.map_or_else(|| "?".to_string(), |c| c.to_string());
+                        let cost = meta.and_then(|m| m.cost_usd).map_or_else(|| "no price".to_string(), pricing::format_cost);
+                        ui.label(RichText::new(format!("{} in · {} out · {}", tokens(usage.input_tokens), tokens(usage.output_tokens), cost))
+                            .small()
+                            .color(theme.code_block_language_color));
                     }
                 });
             });
@@ -115,12 +281,123 @@ impl MessageView {
         ui.add_space(10.0);
     }
 
+    /// One card per tool call: what the model asked for, then Approve and Deny
+    /// while the call waits for a decision, or the result once it has run.
+    fn render_tool_calls(ui: &mut Ui, chat: &Chat, tool_calls: &[ToolUse], theme: &Theme) {
+        for (index, tool_use) in tool_calls.iter().enumerate() {
+            let call = &tool_use.call;
+            Frame::none()
+                .fill(theme.code_block_bg)
+                .stroke(Stroke::new(1.0, theme.code_block_border))
+                .rounding(Rounding::same(5.0))
+                .outer_margin(egui::Margin { left: 30.0, right: 10.0, top: 0.0, bottom: 8.0 })
+                .inner_margin(8.0)
+                .show(ui, |ui| {
+                    ui.set_max_width(ui.available_width());
+                    ui.label(RichText::new(format!("Tool call: {}", call.name)).strong().color(theme.code_block_language_color));
+                    let input = serde_json::to_string_pretty(&call.input).unwrap_or_else(|_| call.input.to_string());
+                    ui.add(Label::new(RichText::new(input).monospace().small()).wrap());
+                    match &tool_use.result {
+                        Some(result) => {
+                            let title = if result.is_error { "Error" } else { "Result" };
+                            egui::CollapsingHeader::new(RichText::new(title).small().color(theme.code_block_language_color))
+                                .id_source(("tool-result", index, &call.id))
+                                .default_open(result.is_error)
+                                .show(ui, |ui| {
+                                    let text = RichText::new(&result.content).monospace().small();
+                                    ui.add(Label::new(if result.is_error { text.color(egui::Color32::RED) } else { text }).wrap());
+                                });
+                        }
+                        None if chat.awaits_approval(&call.id) => {
+                            ui.horizontal(|ui| {
+                                if ui.button("Approve").on_hover_text("Run this tool and send the result to the model").clicked() {
+                                    chat.decide_tool_call(&call.id, true);
+                                }
+                                if ui.button("Deny").on_hover_text("Tell the model the call was not allowed").clicked() {
+                                    chat.decide_tool_call(&call.id, false);
+                                }
+                            });
+                        }
+                        None => {
+                            ui.label(RichText::new("Not run").small().italics().color(theme.code_block_language_color));
+                        }
+                    }
+                });
+        }
+    }
+
+    /// A JSON value as a tree; objects and arrays collapse, and the first two levels start open.
+    fn render_json_tree(ui: &mut Ui, key: Option<&str>, value: &Value, id: egui::Id, depth: usize, theme: &Theme) {
+        let prefix = key.map(|key| format!("{}: ", key)).unwrap_or_default();
+        let children: Vec<(String, &Value)> = match value {
+            Value::Object(object) => object.iter().map(|(name, child)| (format!("\"{}\"", name), child)).collect(),
+            Value::Array(items) => items.iter().enumerate().map(|(index, child)| (format!("[{}]", index), child)).collect(),
+            _ => {
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(RichText::new(prefix).monospace().color(theme.code_block_language_color));
+                    ui.add(Label::new(RichText::new(value.to_string()).monospace()).wrap());
+                });
+                return;
+            }
+        };
+        let title = match value {
+            Value::Object(_) => format!("{}{{{}}}", prefix, children.len()),
+            _ => format!("{}[{}]", prefix, children.len()),
+        };
+        egui::CollapsingHeader::new(RichText::new(title).monospace().color(theme.code_block_language_color))
+            .id_source(id)
+            .default_open(depth < 2)
+            .show(ui, |ui| {
+                for (name, child) in children {
+                    MessageView::render_json_tree(ui, Some(&name), child, id.with(&name), depth + 1, theme);
+                }
+            });
+    }
+
+    fn render_blocks(ui: &mut Ui, highlighted_blocks: &[HighlightedBlock], theme: &Theme) {
+        for block in highlighted_blocks {
+            match block {
+                HighlightedBlock::Text(job) => {
+                    ui.add(Label::new(job.clone()).wrap());
+                },
+                HighlightedBlock::Code { language, job } => {
+                    ui.add_space(5.0);
+                    Frame::none()
+                        .fill(theme.code_block_bg)
+                        .stroke(Stroke::new(1.0, theme.code_block_border))
+                        .rounding(Rounding::same(5.0))
+                        .outer_margin(0.0)
+                        .inner_margin(18.0)
+                        .show(ui, |ui| {
+                            ui.set_max_width(ui.available_width() * 0.99);
+                            if !language.is_empty() {
+                                ui.label(RichText::new(language).small().color(theme.code_block_language_color));
+                            }
+                            ui.add(Label::new(job.clone()).wrap());
+                        });
+                    ui.add_space(5.0);
+                }
+            }
+        }
+    }
+
     pub fn clear_syntax_cache(&mut self) {
         self.syntax_highlighter.clear_cache();
         self.message_cache.clear();
     }
     pub fn clear_cache(&mut self) {
         self.message_cache.clear();
+        self.json_replies.clear();
         self.syntax_highlighter.clear_cache();  // If SyntaxHighlighter has its own cache
     }
-}
\ No newline at end of file
+}
+
+// Hashing whole images every frame is too slow; the length plus samples
+// from the header and the middle tell attachments apart just as well.
+fn thumbnail_key(image: &ImageAttachment) -> u64 {
+    let data = image.data.as_bytes();
+    let sample = |start: usize| &data[start.min(data.len())..(start + 512).min(data.len())];
+    let mut hasher = DefaultHasher::new();
+    (data.len(), sample(0), sample(data.len() / 2)).hash(&mut hasher);
+    hasher.finish()
+}
@@ -6,4 +6,4 @@ pub mod bottom_panel;
 pub mod themes;
 
 pub use chatbot_ui::ChatbotUi;
-pub use message_view::MessageView;
+pub use message_view::{MessageView, Streaming};